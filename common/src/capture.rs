@@ -0,0 +1,298 @@
+// Packet capture format
+//
+// A capture file records every datagram a ground station binary sends or receives. All integers
+// are big-endian. The file starts with a 10 byte header:
+//
+//   0  magic      the 8 ASCII bytes "PISCECAP"
+//   8  version    1 (u16)
+//
+// followed by one record per datagram until the end of the file:
+//
+//   0  timestamp  microseconds since the unix epoch when the datagram was sent or received (u64)
+//   8  direction  0 = received from the rover, 1 = sent to the rover (u8)
+//   9  length     datagram length in bytes (u32)
+//  13  data       the datagram exactly as it went over the wire
+//
+// A record cut short at the end of the file (e.g. after a crash) is ignored by the reader.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use time;
+
+pub const MAGIC: &'static [u8; 8] = b"PISCECAP";
+pub const VERSION: u16 = 1;
+const FLUSH_INTERVAL_MS: i64 = 1000; // Buffered records are written out at least this often
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Direction::In => 0,
+            Direction::Out => 1,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Direction> {
+        match value {
+            0 => Some(Direction::In),
+            1 => Some(Direction::Out),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub timestamp_us: u64,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Microseconds since the unix epoch, as used in the record timestamps.
+pub fn timestamp_us() -> u64 {
+    let now = time::get_time();
+    (now.sec as u64) * 1_000_000 + (now.nsec as u64) / 1000
+}
+
+pub struct CaptureWriter<W: Write> {
+    out: W,
+    last_flush: time::Tm,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Write the file header and return a writer for the records.
+    pub fn new(mut out: W) -> io::Result<CaptureWriter<W>> {
+        try!(out.write_all(MAGIC));
+        try!(out.write_all(&be_bytes(VERSION as u64, 2)));
+        Ok(CaptureWriter {
+            out: out,
+            last_flush: time::now(),
+        })
+    }
+
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        try!(self.out.write_all(&be_bytes(record.timestamp_us, 8)));
+        try!(self.out.write_all(&[record.direction.to_u8()]));
+        try!(self.out.write_all(&be_bytes(record.data.len() as u64, 4)));
+        try!(self.out.write_all(&record.data));
+
+        let now = time::now();
+        if (now - self.last_flush).num_milliseconds() >= FLUSH_INTERVAL_MS {
+            self.last_flush = now;
+            try!(self.out.flush());
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = time::now();
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// A capture file shared between the receive thread and the UI. Write errors are reported once
+/// and then ignored so a full disk doesn't take the ground station down with it.
+#[derive(Clone)]
+pub struct Capture {
+    inner: Arc<Mutex<(CaptureWriter<BufWriter<File>>, bool)>>, // Writer and whether it has failed
+}
+
+impl Capture {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Capture> {
+        let file = try!(File::create(path));
+        let writer = try!(CaptureWriter::new(BufWriter::new(file)));
+        Ok(Capture { inner: Arc::new(Mutex::new((writer, false))) })
+    }
+
+    /// Record a datagram with the current time.
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        let record = Record {
+            timestamp_us: timestamp_us(),
+            direction: direction,
+            data: data.to_vec(),
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let result = inner.0.write_record(&record);
+        if let Err(e) = result {
+            if !inner.1 {
+                println!("WARNING: Failed to write packet capture: {}", e);
+                inner.1 = true;
+            }
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.inner.lock().unwrap().0.flush()
+    }
+}
+
+pub struct CaptureReader<R: Read> {
+    input: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CaptureReader<BufReader<File>>> {
+        CaptureReader::new(BufReader::new(try!(File::open(path))))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Check the file header and return a reader for the records.
+    pub fn new(mut input: R) -> io::Result<CaptureReader<R>> {
+        let mut header = [0u8; 10];
+        try!(input.read_exact(&mut header));
+        if &header[0..8] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a packet capture file"));
+        }
+        let version = read_be(&header[8..10]) as u16;
+        if version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported packet capture version {}", version)));
+        }
+        Ok(CaptureReader { input: input })
+    }
+
+    /// The next record, or `None` at the end of the file or at a record cut short by a crash.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut header = [0u8; 13];
+        if !try!(read_all_or_eof(&mut self.input, &mut header)) {
+            return Ok(None);
+        }
+        let direction =
+            match Direction::from_u8(header[8]) {
+                Some(direction) => direction,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  format!("bad packet direction {}", header[8]))),
+            };
+        let mut data = vec![0u8; read_be(&header[9..13]) as usize];
+        if !try!(read_all_or_eof(&mut self.input, &mut data)) {
+            return Ok(None);
+        }
+
+        Ok(Some(Record {
+            timestamp_us: read_be(&header[0..8]),
+            direction: direction,
+            data: data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        match self.next_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Cut off a record left unfinished at the end of a capture file by a crash. Returns whether the
+/// file had to be repaired.
+pub fn repair<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let mut valid_len = (MAGIC.len() + 2) as u64;
+    for record in try!(CaptureReader::open(&path)) {
+        valid_len += 13 + try!(record).data.len() as u64;
+    }
+
+    let file = try!(OpenOptions::new().write(true).open(&path));
+    if try!(file.metadata()).len() > valid_len {
+        try!(file.set_len(valid_len));
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+// Fill `buf`, returning false if the input ends first.
+fn read_all_or_eof<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match input.read(&mut buf[read..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn be_bytes(value: u64, bytes: usize) -> Vec<u8> {
+    (0..bytes).rev().map(|i| (value >> (i * 8)) as u8).collect()
+}
+
+fn read_be(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, &b| (value << 8) | b as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Cursor, Write};
+
+    fn records() -> Vec<Record> {
+        vec![Record { timestamp_us: 1_468_000_000_000_001, direction: Direction::Out, data: b"A100|\0".to_vec() },
+             Record { timestamp_us: 1_468_000_000_250_000, direction: Direction::In, data: b"ACK:A|".to_vec() },
+             Record { timestamp_us: 1_468_000_000_250_001, direction: Direction::In, data: vec![] }]
+    }
+
+    fn capture(records: &[Record]) -> Vec<u8> {
+        let mut writer = CaptureWriter::new(vec![]).unwrap();
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let data = capture(&records());
+        assert_eq!(&data[0..10], b"PISCECAP\x00\x01");
+        let read: Vec<Record> = CaptureReader::new(Cursor::new(data)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(read, records());
+    }
+
+    #[test]
+    fn ignores_a_truncated_last_record() {
+        let mut data = capture(&records()[0..2]);
+        data.truncate(data.len() - 3);
+        let read: Vec<Record> = CaptureReader::new(Cursor::new(data)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(read, &records()[0..1]);
+    }
+
+    #[test]
+    fn repairs_a_truncated_file() {
+        let path = env::temp_dir().join(format!("repair_{}.cap", ::link::timestamp_ms()));
+        let data = capture(&records());
+        File::create(&path).unwrap().write_all(&data[0..data.len() - 2]).unwrap();
+
+        assert!(repair(&path).unwrap());
+        assert!(!repair(&path).unwrap());
+        let read: Vec<Record> = CaptureReader::open(&path).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(read, &records()[0..2]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), (data.len() - 13) as u64);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(CaptureReader::new(Cursor::new(b"W_TEMP:20|".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use time;
+
+/// Local time as shown in the top left of every window.
+pub fn local_time_text(now: &time::Tm) -> String {
+    format!("{}", now.to_local().strftime("Local  %x  %X").unwrap())
+}
+
+/// UTC time as shown in the top left of every window.
+pub fn utc_time_text(now: &time::Tm) -> String {
+    format!("{}", now.to_utc().strftime("%Z  %x  %X").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time;
+
+    #[test]
+    fn formats_utc_time() {
+        let t = time::at_utc(time::Timespec::new(1476000000, 0));
+        assert_eq!(utc_time_text(&t), "UTC  10/09/16  08:00:00");
+        assert!(local_time_text(&t).starts_with("Local  "));
+    }
+}
@@ -0,0 +1,345 @@
+// Telemetry data logs
+//
+// Each channel (imu, gps, ...) is logged to its own file, one row per sample. Every row starts with
+// the UTC time and the mission elapsed time, followed by the channel's values. Missing values are
+// left empty in CSV and written as null in JSON lines.
+//
+// CSV files start with a header row naming the columns:
+//
+//   utc,mission_elapsed_s,pitch,roll,heading
+//   2016-06-02T18:04:05.123Z,125.500,1.2,-0.4,
+//
+// JSON lines files start with a schema object, followed by one object per row:
+//
+//   {"schema":"pisces-data-log","version":1,"channel":"imu","columns":["utc","mission_elapsed_s","pitch","roll","heading"]}
+//   {"utc":"2016-06-02T18:04:05.123Z","mission_elapsed_s":125.500,"pitch":1.2,"roll":-0.4,"heading":null}
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use time;
+
+use json::{self, string as json_string};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Csv,
+    JsonLines,
+}
+
+impl LogFormat {
+    /// File extension for logs in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "csv" => Ok(LogFormat::Csv),
+            "jsonl" => Ok(LogFormat::JsonLines),
+            _ => Err(format!("unknown log format '{}', expected csv or jsonl", s)),
+        }
+    }
+}
+
+pub struct DataLog<W: Write> {
+    out: W,
+    format: LogFormat,
+    columns: Vec<&'static str>,
+}
+
+impl DataLog<BufWriter<File>> {
+    /// Create `<dir>/<file>.<csv|jsonl>` for a channel.
+    pub fn create(dir: &str, file: &str, format: LogFormat, channel: &str,
+                  columns: &[&'static str]) -> io::Result<DataLog<BufWriter<File>>> {
+        let file = try!(File::create(format!("{}/{}.{}", dir, file, format.extension())));
+        DataLog::new(BufWriter::new(file), format, channel, columns)
+    }
+}
+
+impl<W: Write> DataLog<W> {
+    /// Write the header and return a log for rows with the given value columns.
+    pub fn new(mut out: W, format: LogFormat, channel: &str,
+               columns: &[&'static str]) -> io::Result<DataLog<W>> {
+        match format {
+            LogFormat::Csv => {
+                try!(write!(out, "utc,mission_elapsed_s"));
+                for column in columns {
+                    try!(write!(out, ",{}", column));
+                }
+                try!(write!(out, "\n"));
+            },
+            LogFormat::JsonLines => {
+                try!(write!(out, "{{\"schema\":\"pisces-data-log\",\"version\":{},\"channel\":{},\"columns\":[\"utc\",\"mission_elapsed_s\"",
+                            SCHEMA_VERSION, json_string(channel)));
+                for column in columns {
+                    try!(write!(out, ",{}", json_string(column)));
+                }
+                try!(write!(out, "]}}\n"));
+            },
+        }
+
+        Ok(DataLog {
+            out: out,
+            format: format,
+            columns: columns.to_vec(),
+        })
+    }
+
+    /// Write a row taken at `utc`, with one value per column.
+    pub fn write_row(&mut self, utc: &time::Tm, mission_elapsed: time::Duration,
+                     values: &[Option<f64>]) -> io::Result<()> {
+        assert_eq!(values.len(), self.columns.len());
+
+        let utc = utc_text(utc);
+        let elapsed = mission_elapsed.num_milliseconds() as f64 / 1000.0;
+        match self.format {
+            LogFormat::Csv => {
+                try!(write!(self.out, "{},{:.3}", utc, elapsed));
+                for value in values {
+                    match *value {
+                        Some(value) if value.is_finite() => try!(write!(self.out, ",{}", value)),
+                        _ => try!(write!(self.out, ",")),
+                    }
+                }
+                try!(write!(self.out, "\n"));
+            },
+            LogFormat::JsonLines => {
+                try!(write!(self.out, "{{\"utc\":\"{}\",\"mission_elapsed_s\":{:.3}", utc, elapsed));
+                for (column, value) in self.columns.iter().zip(values) {
+                    match *value {
+                        Some(value) if value.is_finite() => try!(write!(self.out, ",{}:{}", json_string(column), value)),
+                        _ => try!(write!(self.out, ",{}:null", json_string(column))),
+                    }
+                }
+                try!(write!(self.out, "}}\n"));
+            },
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Cut off a row left unfinished at the end of a log by a crash. Returns whether the file had to
+/// be repaired.
+pub fn repair<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let mut data = vec![];
+    try!(try!(File::open(&path)).read_to_end(&mut data));
+    let valid_len =
+        match data.iter().rposition(|&b| b == b'\n') {
+            Some(newline) => newline + 1,
+            None => 0,
+        };
+
+    if valid_len < data.len() {
+        let file = try!(OpenOptions::new().write(true).open(&path));
+        try!(file.set_len(valid_len as u64));
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// A log read back from a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogData {
+    pub columns: Vec<String>, // The value columns, without the times
+    pub rows: Vec<Row>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub utc_s: f64, // Seconds since the unix epoch
+    pub mission_elapsed_s: f64,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Read a CSV or JSON lines log. Rows that can't be read, like one cut short by a crash, are
+/// skipped.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<LogData> {
+    read_from(BufReader::new(try!(File::open(path))))
+}
+
+pub fn read_from<R: BufRead>(input: R) -> io::Result<LogData> {
+    let mut lines = input.lines();
+    let header = try!(lines.next().unwrap_or(Ok(String::new())));
+    let json = header.starts_with('{');
+
+    let mut columns: Vec<String> =
+        if json {
+            let header = try!(json::parse(&header).map_err(invalid_data));
+            if header.get("schema").and_then(|schema| schema.as_str()) != Some("pisces-data-log") {
+                return Err(invalid_data("not a data log".to_string()));
+            }
+            match header.get("columns").and_then(|columns| columns.as_array()) {
+                Some(columns) => columns.iter().filter_map(|column| column.as_str()).map(|column| column.to_string()).collect(),
+                None => return Err(invalid_data("the header has no columns".to_string())),
+            }
+        } else {
+            header.split(',').map(|column| column.to_string()).collect()
+        };
+    if columns.len() < 2 || columns[0] != "utc" || columns[1] != "mission_elapsed_s" {
+        return Err(invalid_data("not a data log".to_string()));
+    }
+    columns.drain(..2);
+
+    let mut rows = vec![];
+    for line in lines {
+        let line = try!(line);
+        let row = if json { json_row(&line, &columns) } else { csv_row(&line, columns.len()) };
+        if let Some(row) = row {
+            rows.push(row);
+        }
+    }
+
+    Ok(LogData {
+        columns: columns,
+        rows: rows,
+    })
+}
+
+fn csv_row(line: &str, columns: usize) -> Option<Row> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() != columns + 2 {
+        return None;
+    }
+    match (parse_utc(fields[0]), fields[1].parse().ok()) {
+        (Some(utc_s), Some(mission_elapsed_s)) => {
+            Some(Row {
+                utc_s: utc_s,
+                mission_elapsed_s: mission_elapsed_s,
+                values: fields[2..].iter().map(|field| field.parse().ok()).collect(),
+            })
+        },
+        _ => None,
+    }
+}
+
+fn json_row(line: &str, columns: &[String]) -> Option<Row> {
+    let row =
+        match json::parse(line) {
+            Ok(row) => row,
+            Err(_) => return None,
+        };
+    let utc_s = row.get("utc").and_then(|utc| utc.as_str()).and_then(parse_utc);
+    let mission_elapsed_s = row.get("mission_elapsed_s").and_then(|elapsed| elapsed.as_f64());
+    match (utc_s, mission_elapsed_s) {
+        (Some(utc_s), Some(mission_elapsed_s)) => {
+            Some(Row {
+                utc_s: utc_s,
+                mission_elapsed_s: mission_elapsed_s,
+                values: columns.iter().map(|column| row.get(column).and_then(|value| value.as_f64())).collect(),
+            })
+        },
+        _ => None,
+    }
+}
+
+/// Parse a time written by `utc_text` into seconds since the unix epoch.
+pub fn parse_utc(text: &str) -> Option<f64> {
+    if !text.ends_with('Z') {
+        return None;
+    }
+    let text = &text[..text.len() - 1];
+    let (seconds, fraction) =
+        match text.find('.') {
+            Some(dot) => (&text[..dot], format!("0{}", &text[dot..]).parse::<f64>().ok()),
+            None => (text, Some(0.0)),
+        };
+    match (time::strptime(seconds, "%Y-%m-%dT%H:%M:%S"), fraction) {
+        (Ok(tm), Some(fraction)) => Some(tm.to_timespec().sec as f64 + fraction),
+        _ => None,
+    }
+}
+
+fn invalid_data(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// RFC 3339 UTC time with milliseconds, e.g. `2016-06-02T18:04:05.123Z`.
+pub fn utc_text(time: &time::Tm) -> String {
+    let utc = time.to_utc();
+    format!("{}.{:03}Z", utc.strftime("%Y-%m-%dT%H:%M:%S").unwrap(), utc.tm_nsec / 1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use time;
+
+    fn log(format: LogFormat) -> String {
+        let utc = time::at_utc(time::Timespec::new(1_464_890_645, 123_000_000));
+        let mut log = DataLog::new(vec![], format, "imu", &["pitch", "roll", "heading"]).unwrap();
+        log.write_row(&utc, time::Duration::milliseconds(125_500), &[Some(1.25), Some(-0.5), None]).unwrap();
+        String::from_utf8(log.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn writes_csv() {
+        assert_eq!(log(LogFormat::Csv),
+                   "utc,mission_elapsed_s,pitch,roll,heading\n\
+                    2016-06-02T18:04:05.123Z,125.500,1.25,-0.5,\n");
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        assert_eq!(log(LogFormat::JsonLines),
+                   "{\"schema\":\"pisces-data-log\",\"version\":1,\"channel\":\"imu\",\"columns\":[\"utc\",\"mission_elapsed_s\",\"pitch\",\"roll\",\"heading\"]}\n\
+                    {\"utc\":\"2016-06-02T18:04:05.123Z\",\"mission_elapsed_s\":125.500,\"pitch\":1.25,\"roll\":-0.5,\"heading\":null}\n");
+    }
+
+    #[test]
+    fn repairs_an_unfinished_row() {
+        let path = env::temp_dir().join(format!("repair_{}.csv", ::link::timestamp_ms()));
+        File::create(&path).unwrap().write_all(b"utc,mission_elapsed_s,h48_v\n2016-06-02T18:04:05.123Z,1.0").unwrap();
+
+        assert!(repair(&path).unwrap());
+        assert!(!repair(&path).unwrap());
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "utc,mission_elapsed_s,h48_v\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reads_back_both_formats() {
+        for format in &[LogFormat::Csv, LogFormat::JsonLines] {
+            let text = log(*format) + "2016-06-02T18:04:06";
+            let data = read_from(text.as_bytes()).unwrap();
+            assert_eq!(data.columns, vec!["pitch", "roll", "heading"]);
+            assert_eq!(data.rows, vec![Row {
+                utc_s: 1_464_890_645.123,
+                mission_elapsed_s: 125.5,
+                values: vec![Some(1.25), Some(-0.5), None],
+            }]);
+        }
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("csv".parse(), Ok(LogFormat::Csv));
+        assert_eq!("jsonl".parse(), Ok(LogFormat::JsonLines));
+        assert!("tsv".parse::<LogFormat>().is_err());
+    }
+}
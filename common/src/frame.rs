@@ -0,0 +1,312 @@
+// Framed telemetry format
+//
+// Every datagram starts with a fixed 22 byte header, all fields big-endian:
+//
+//   0  magic      0xFE 'P' (0xFE never appears in UTF-8 text, so legacy datagrams can't match)
+//   2  version    1
+//   3  type       see `FrameType`
+//   4  length     payload length in bytes (u16)
+//   6  sequence   per-sender frame counter (u32)
+//  10  timestamp  sender's clock in ms since the unix epoch (u64)
+//  18  crc        CRC-32 of the header up to here followed by the payload (u32)
+//
+// Telemetry frames carry the usual `|` separated text messages as their payload. Datagrams that
+// don't start with the magic bytes are treated as legacy text telemetry, which is checked against
+// its optional text checksum instead (see `protocol::add_checksum`).
+
+use std::fmt;
+use std::str;
+
+use crc::{crc32, update_crc32};
+use link::timestamp_ms;
+use protocol::{strip_checksum, TelemetryMsg};
+
+pub const MAGIC: [u8; 2] = [0xFE, b'P'];
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 22;
+pub const MAX_DATAGRAM: usize = 8192; // Anything bigger is rejected rather than truncated
+pub const MAX_PAYLOAD: usize = MAX_DATAGRAM - HEADER_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameType {
+    Telemetry,
+}
+
+impl FrameType {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            FrameType::Telemetry => 1,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<FrameType> {
+        match value {
+            1 => Some(FrameType::Telemetry),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub frame_type: FrameType,
+    pub seq: u32,
+    pub timestamp_ms: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        assert!(self.payload.len() <= MAX_PAYLOAD, "frame payload too large");
+
+        let mut data = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        data.extend_from_slice(&MAGIC);
+        data.push(VERSION);
+        data.push(self.frame_type.to_u8());
+        push_be(&mut data, self.payload.len() as u64, 2);
+        push_be(&mut data, self.seq as u64, 4);
+        push_be(&mut data, self.timestamp_ms, 8);
+        let crc = update_crc32(crc32(&data), &self.payload);
+        push_be(&mut data, crc as u64, 4);
+        data.extend_from_slice(&self.payload);
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Frame, FrameError> {
+        if data.len() < HEADER_LEN {
+            return Err(FrameError::Truncated { expected: HEADER_LEN, actual: data.len() });
+        }
+        if !is_framed(data) {
+            return Err(FrameError::BadMagic);
+        }
+        if data[2] != VERSION {
+            return Err(FrameError::BadVersion(data[2]));
+        }
+        let frame_type = try!(FrameType::from_u8(data[3]).ok_or(FrameError::UnknownType(data[3])));
+
+        let len = read_be(&data[4..6]) as usize;
+        if data.len() < HEADER_LEN + len {
+            return Err(FrameError::Truncated { expected: HEADER_LEN + len, actual: data.len() });
+        }
+        if data.len() > HEADER_LEN + len {
+            return Err(FrameError::TrailingBytes(data.len() - HEADER_LEN - len));
+        }
+
+        let payload = &data[HEADER_LEN..];
+        let crc = read_be(&data[18..22]) as u32;
+        let actual_crc = update_crc32(crc32(&data[0..18]), payload);
+        if crc != actual_crc {
+            return Err(FrameError::BadCrc { expected: crc, actual: actual_crc });
+        }
+
+        Ok(Frame {
+            frame_type: frame_type,
+            seq: read_be(&data[6..10]) as u32,
+            timestamp_ms: read_be(&data[10..18]),
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// The payload as text, as carried by telemetry frames.
+    pub fn text(&self) -> Result<&str, FrameError> {
+        str::from_utf8(&self.payload).map_err(|_| FrameError::InvalidUtf8)
+    }
+}
+
+/// Numbers and timestamps the frames sent from one socket.
+pub struct FrameWriter {
+    next_seq: u32,
+}
+
+impl FrameWriter {
+    pub fn new() -> FrameWriter {
+        FrameWriter {
+            next_seq: 0,
+        }
+    }
+
+    pub fn frame(&mut self, frame_type: FrameType, payload: Vec<u8>) -> Frame {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        Frame {
+            frame_type: frame_type,
+            seq: seq,
+            timestamp_ms: timestamp_ms(),
+            payload: payload,
+        }
+    }
+
+    /// Pack telemetry messages into as few datagrams as fit within `MAX_DATAGRAM`.
+    pub fn telemetry(&mut self, msgs: &[TelemetryMsg]) -> Vec<Vec<u8>> {
+        let mut datagrams = vec![];
+        let mut payload = String::new();
+        for msg in msgs {
+            let mut msg = msg.encode();
+            msg.push('|');
+            if !payload.is_empty() && payload.len() + msg.len() > MAX_PAYLOAD {
+                datagrams.push(self.frame(FrameType::Telemetry, payload.into_bytes()).encode());
+                payload = String::new();
+            }
+            payload.push_str(msg.as_str());
+        }
+        if !payload.is_empty() {
+            datagrams.push(self.frame(FrameType::Telemetry, payload.into_bytes()).encode());
+        }
+        datagrams
+    }
+}
+
+/// A received datagram in either format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Datagram {
+    Legacy(String),
+    Framed(Frame),
+}
+
+impl Datagram {
+    /// The telemetry text carried by the datagram.
+    pub fn text(&self) -> Result<&str, FrameError> {
+        match *self {
+            Datagram::Legacy(ref text) => Ok(text.as_str()),
+            Datagram::Framed(ref frame) => frame.text(),
+        }
+    }
+}
+
+pub fn is_framed(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0..2] == MAGIC
+}
+
+/// Decode a received datagram, telling framed and legacy text datagrams apart.
+pub fn decode_datagram(data: &[u8]) -> Result<Datagram, FrameError> {
+    if data.len() > MAX_DATAGRAM {
+        return Err(FrameError::Oversized(data.len()));
+    }
+    if is_framed(data) {
+        Frame::decode(data).map(Datagram::Framed)
+    } else {
+        let data = try!(strip_checksum(data).map_err(|e| FrameError::BadCrc { expected: e.expected, actual: e.actual }));
+        str::from_utf8(data).map(|text| Datagram::Legacy(text.to_string()))
+                            .map_err(|_| FrameError::InvalidUtf8)
+    }
+}
+
+fn push_be(data: &mut Vec<u8>, value: u64, bytes: usize) {
+    for i in (0..bytes).rev() {
+        data.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn read_be(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, &b| (value << 8) | b as u64)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameError {
+    Oversized(usize),
+    Truncated { expected: usize, actual: usize },
+    TrailingBytes(usize),
+    BadMagic,
+    BadVersion(u8),
+    UnknownType(u8),
+    BadCrc { expected: u32, actual: u32 },
+    InvalidUtf8,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::Oversized(len) => write!(f, "datagram of {}+ bytes is over the {} byte limit", len, MAX_DATAGRAM),
+            FrameError::Truncated { expected, actual } => write!(f, "frame truncated to {} of {} bytes", actual, expected),
+            FrameError::TrailingBytes(n) => write!(f, "frame has {} unexpected trailing bytes", n),
+            FrameError::BadMagic => write!(f, "not a framed datagram"),
+            FrameError::BadVersion(v) => write!(f, "unsupported frame version {}", v),
+            FrameError::UnknownType(t) => write!(f, "unknown frame type {}", t),
+            FrameError::BadCrc { expected, actual } => write!(f, "frame CRC mismatch ({:08x} != {:08x})", actual, expected),
+            FrameError::InvalidUtf8 => write!(f, "telemetry is not valid UTF-8"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{add_checksum, parse_telemetry, TelemetryMsg};
+
+    fn frame(payload: &[u8]) -> Frame {
+        Frame {
+            frame_type: FrameType::Telemetry,
+            seq: 0x01020304,
+            timestamp_ms: 1_468_000_000_123,
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let frame = frame(b"W_TEMP:20|");
+        let data = frame.encode();
+        assert_eq!(data.len(), HEADER_LEN + 10);
+        assert_eq!(&data[6..10], &[1, 2, 3, 4]);
+        assert_eq!(Frame::decode(&data), Ok(frame.clone()));
+        assert_eq!(decode_datagram(&data), Ok(Datagram::Framed(frame)));
+    }
+
+    #[test]
+    fn detects_legacy_text() {
+        assert_eq!(decode_datagram(b"W_TEMP:20|"), Ok(Datagram::Legacy("W_TEMP:20|".to_string())));
+        assert_eq!(decode_datagram(b"\xff\xfe"), Err(FrameError::InvalidUtf8));
+
+        let checked = add_checksum(b"W_TEMP:20|");
+        assert_eq!(decode_datagram(&checked), Ok(Datagram::Legacy("W_TEMP:20|".to_string())));
+        let mut corrupt = checked.clone();
+        corrupt[7] = b'9';
+        match decode_datagram(&corrupt) {
+            Err(FrameError::BadCrc { .. }) => {},
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_frames() {
+        let data = frame(b"W_TEMP:20|").encode();
+
+        assert_eq!(Frame::decode(&data[..HEADER_LEN + 4]),
+                   Err(FrameError::Truncated { expected: HEADER_LEN + 10, actual: HEADER_LEN + 4 }));
+        assert_eq!(Frame::decode(&data[..10]), Err(FrameError::Truncated { expected: HEADER_LEN, actual: 10 }));
+
+        let mut long = data.clone();
+        long.push(b'|');
+        assert_eq!(Frame::decode(&long), Err(FrameError::TrailingBytes(1)));
+
+        let mut corrupt = data.clone();
+        corrupt[HEADER_LEN] = b'X';
+        match Frame::decode(&corrupt) {
+            Err(FrameError::BadCrc { .. }) => {},
+            other => panic!("{:?}", other),
+        }
+
+        let mut version = data.clone();
+        version[2] = 9;
+        assert_eq!(Frame::decode(&version), Err(FrameError::BadVersion(9)));
+
+        assert_eq!(decode_datagram(&vec![b'W'; MAX_DATAGRAM + 1]), Err(FrameError::Oversized(MAX_DATAGRAM + 1)));
+    }
+
+    #[test]
+    fn packs_telemetry_into_frames() {
+        let mut writer = FrameWriter::new();
+        let msgs: Vec<TelemetryMsg> = (0..2000).map(|i| TelemetryMsg::WeatherTemp(i as f64)).collect();
+        let datagrams = writer.telemetry(&msgs);
+        assert!(datagrams.len() > 1);
+
+        let mut parsed = 0;
+        for (i, data) in datagrams.iter().enumerate() {
+            assert!(data.len() <= MAX_DATAGRAM);
+            let frame = Frame::decode(data).unwrap();
+            assert_eq!(frame.seq, i as u32);
+            parsed += parse_telemetry(frame.text().unwrap()).len();
+        }
+        assert_eq!(parsed, msgs.len());
+    }
+}
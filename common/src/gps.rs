@@ -0,0 +1,49 @@
+use std::f64;
+
+/// Split decimal degrees into whole degrees, whole minutes and seconds. The sign is dropped, use
+/// `format_latitude`/`format_longitude` to show the hemisphere.
+pub fn gps_degrees_to_dms(degrees: f64) -> (i32, i32, f64) {
+    let degrees = f64::abs(degrees);
+
+    let minutes = (degrees - f64::floor(degrees)) * 60.0;
+    let seconds = (minutes - f64::floor(minutes)) * 60.0;
+
+    (f64::floor(degrees) as i32, f64::floor(minutes) as i32, seconds)
+}
+
+pub fn format_latitude(latitude: f64) -> String {
+    format_dms(latitude, if latitude < 0.0 { 'S' } else { 'N' })
+}
+
+pub fn format_longitude(longitude: f64) -> String {
+    format_dms(longitude, if longitude < 0.0 { 'W' } else { 'E' })
+}
+
+fn format_dms(degrees: f64, hemisphere: char) -> String {
+    let (deg, min, sec) = gps_degrees_to_dms(degrees);
+    format!("{}  {}' {:.*}\" {}", deg, min, 2, sec, hemisphere)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_degrees() {
+        let (deg, min, sec) = gps_degrees_to_dms(19.5125);
+        assert_eq!((deg, min), (19, 30));
+        assert!((sec - 45.0).abs() < 1e-6);
+
+        let (deg, min, sec) = gps_degrees_to_dms(-155.5645);
+        assert_eq!((deg, min), (155, 33));
+        assert!((sec - 52.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn formats_hemispheres() {
+        assert_eq!(format_latitude(19.5125), "19  30' 45.00\" N");
+        assert_eq!(format_latitude(-19.5125), "19  30' 45.00\" S");
+        assert_eq!(format_longitude(-155.5645), "155  33' 52.20\" W");
+        assert_eq!(format_longitude(10.0), "10  0' 0.00\" E");
+    }
+}
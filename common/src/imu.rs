@@ -0,0 +1,65 @@
+use std::f64;
+
+/// Pitch, roll and heading in degrees from the raw accelerometer and magnetometer axes sent in
+/// the `IMU` telemetry message. Heading is clockwise from north in [0, 360), roll is in
+/// [-180, 180).
+pub fn pitch_roll_heading(accel: (f64, f64, f64), mag: (f64, f64, f64)) -> (f64, f64, f64) {
+    // Swap the sensor axes into the rover's frame
+    let (ax, ay, az) = (accel.1, -accel.2, accel.0);
+    let (mx, my, mz) = (mag.1, -mag.2, mag.0);
+
+    let roll = f64::atan2(ay, az);
+    let pitch = f64::atan2(-ax, ay*f64::sin(roll) + az*f64::cos(roll));
+    let heading = f64::atan2(mz*f64::sin(roll) - my*f64::cos(roll),
+                             mx*f64::cos(pitch) + my*f64::sin(pitch)*f64::sin(roll) + mz*f64::sin(pitch)*f64::cos(roll));
+
+    let pitch = pitch.to_degrees();
+
+    let mut roll = roll.to_degrees() + 180.0;
+    if roll >= 180.0 {
+        roll -= 360.0;
+    }
+
+    let mut heading = heading.to_degrees();
+    if heading < 0.0 {
+        heading += 360.0;
+    }
+    heading = 360.0 - heading;
+    if heading >= 360.0 {
+        heading -= 360.0;
+    }
+
+    (pitch, roll, heading)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    // A level rover facing `heading` degrees, the same readings the rover simulator sends
+    fn level(heading: f64) -> (f64, f64, f64) {
+        let h = heading.to_radians();
+        pitch_roll_heading((-1.0, 0.0, 0.0), (0.0, h.cos(), h.sin()))
+    }
+
+    #[test]
+    fn level_rover() {
+        for &heading in &[0.0, 45.0, 90.0, 180.0, 270.0, 359.0] {
+            let (pitch, roll, h) = level(heading);
+            assert_close(pitch, 0.0);
+            assert_close(roll, 0.0);
+            assert_close(h, heading);
+        }
+    }
+
+    #[test]
+    fn roll_wraps_around() {
+        let (_, roll, _) = pitch_roll_heading((-0.9, 0.1, 0.0), (0.0, 1.0, 0.0));
+        assert!(roll >= -180.0 && roll < 180.0);
+        assert!(roll.abs() < 10.0);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use time;
+
+use frame::FrameError;
+
+/// Counts packets that failed their integrity checks in either direction and appends each one to
+/// a log in the mission folder.
+pub struct IntegrityStats {
+    corrupted_in: u64, // Telemetry datagrams that failed their CRC or checksum
+    corrupted_out: u64, // Commands the rover rejected, as reported by the rover
+    log: Option<File>,
+}
+
+impl IntegrityStats {
+    /// `log_path` is opened for appending. Without one the counts are only kept in memory.
+    pub fn new(log_path: Option<&str>) -> IntegrityStats {
+        let log =
+            log_path.and_then(|path| {
+                match OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        println!("WARNING: Failed to open packet error log {}: {}", path, e);
+                        None
+                    },
+                }
+            });
+
+        IntegrityStats {
+            corrupted_in: 0,
+            corrupted_out: 0,
+            log: log,
+        }
+    }
+
+    /// Call for every received datagram that was dropped.
+    pub fn on_dropped(&mut self, error: &FrameError) {
+        if let FrameError::BadCrc { .. } = *error {
+            self.corrupted_in += 1;
+        }
+        self.write_log("in", format!("{}", error));
+    }
+
+    /// Call with the rover's running count of rejected commands.
+    pub fn on_rover_report(&mut self, bad_commands: u64) {
+        if bad_commands > self.corrupted_out {
+            let new = bad_commands - self.corrupted_out;
+            self.write_log("out", format!("rover rejected {} corrupted command(s)", new));
+        }
+        self.corrupted_out = bad_commands;
+    }
+
+    pub fn corrupted_in(&self) -> u64 {
+        self.corrupted_in
+    }
+
+    pub fn corrupted_out(&self) -> u64 {
+        self.corrupted_out
+    }
+
+    pub fn text(&self) -> String {
+        format!("Corrupt: {} in / {} out", self.corrupted_in, self.corrupted_out)
+    }
+
+    fn write_log(&mut self, direction: &str, msg: String) {
+        if let Some(ref mut log) = self.log {
+            let line = format!("{}\t{}\t{}\n", time::now_utc().rfc3339(), direction, msg);
+            if let Err(e) = log.write_all(line.as_bytes()) {
+                println!("WARNING: Failed to write packet error log: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+
+    use frame::FrameError;
+
+    #[test]
+    fn counts_and_logs_corruption() {
+        let path = env::temp_dir().join(format!("packet_errors_{}.log", ::link::timestamp_ms()));
+        let path = path.to_str().unwrap();
+        {
+            let mut stats = IntegrityStats::new(Some(path));
+            stats.on_dropped(&FrameError::BadCrc { expected: 1, actual: 2 });
+            stats.on_dropped(&FrameError::InvalidUtf8);
+            stats.on_rover_report(3);
+            stats.on_rover_report(3);
+            assert_eq!(stats.corrupted_in(), 1);
+            assert_eq!(stats.corrupted_out(), 3);
+            assert_eq!(stats.text(), "Corrupt: 1 in / 3 out");
+        }
+
+        let mut log = String::new();
+        File::open(path).unwrap().read_to_string(&mut log).unwrap();
+        fs::remove_file(path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("\tin\tframe CRC mismatch (00000002 != 00000001)"));
+        assert!(lines[2].ends_with("\tout\trover rejected 3 corrupted command(s)"));
+    }
+}
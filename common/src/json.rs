@@ -0,0 +1,212 @@
+// Just enough JSON for the files the ground station writes and reads back.
+
+use std::str::Chars;
+use std::iter::Peekable;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The value of `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref fields) => fields.iter().find(|field| field.0 == key).map(|field| &field.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a JSON document.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut chars = text.chars().peekable();
+    let value = try!(parse_value(&mut chars));
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{}' after the value", c)),
+    }
+}
+
+/// `s` as a quoted JSON string.
+pub fn string(s: &str) -> String {
+    let mut out = "\"".to_string();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `s` as a quoted JSON string, or null.
+pub fn opt_string(s: Option<&str>) -> String {
+    s.map(string).unwrap_or("null".to_string())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_whitespace(chars);
+    match chars.peek().cloned() {
+        Some('{') => {
+            chars.next();
+            let mut fields = vec![];
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Value::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = try!(parse_string(chars));
+                skip_whitespace(chars);
+                try!(expect(chars, ':'));
+                fields.push((key, try!(parse_value(chars))));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => { },
+                    Some('}') => return Ok(Value::Object(fields)),
+                    _ => return Err("expected ',' or '}'".to_string()),
+                }
+            }
+        },
+        Some('[') => {
+            chars.next();
+            let mut values = vec![];
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Value::Array(values));
+            }
+            loop {
+                values.push(try!(parse_value(chars)));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => { },
+                    Some(']') => return Ok(Value::Array(values)),
+                    _ => return Err("expected ',' or ']'".to_string()),
+                }
+            }
+        },
+        Some('"') => parse_string(chars).map(Value::String),
+        Some('n') => parse_word(chars, "null", Value::Null),
+        Some('t') => parse_word(chars, "true", Value::Bool(true)),
+        Some('f') => parse_word(chars, "false", Value::Bool(false)),
+        Some(c) if c == '-' || c.is_digit(10) => {
+            let mut number = String::new();
+            while let Some(c) = chars.peek().cloned() {
+                if c.is_digit(10) || "+-.eE".contains(c) {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            number.parse().map(Value::Number).map_err(|_| format!("bad number '{}'", number))
+        },
+        Some(c) => Err(format!("unexpected '{}'", c)),
+        None => Err("unexpected end of input".to_string()),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    try!(expect(chars, '"'));
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => {
+                match chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32);
+                        s.push(try!(c.ok_or(format!("bad escape '\\u{}'", hex))));
+                    },
+                    Some(c) => s.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_word(chars: &mut Peekable<Chars>, word: &str, value: Value) -> Result<Value, String> {
+    for expected in word.chars() {
+        try!(expect(chars, expected));
+    }
+    Ok(value)
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+        None => Err(format!("expected '{}', found the end of input", expected)),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\u000a\"");
+        assert_eq!(opt_string(None), "null");
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let text = format!("{{\"utc\":{},\"elapsed\":-1.5e2,\"ok\":true,\"list\":[null, 3]}}", string("a\"\n"));
+        let value = parse(&text).unwrap();
+        assert_eq!(value.get("utc").and_then(|v| v.as_str()), Some("a\"\n"));
+        assert_eq!(value.get("elapsed").and_then(|v| v.as_f64()), Some(-150.0));
+        assert_eq!(value.get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("list").and_then(|v| v.as_array()), Some(&[Value::Null, Value::Number(3.0)][..]));
+        assert!(parse("{\"a\":1").is_err());
+        assert!(parse("[1] 2").is_err());
+    }
+}
@@ -0,0 +1,312 @@
+// Dashboard layouts
+//
+// A layout splits the window into rows from the top, each row into columns from the left, and
+// stacks named panels in each column from the top. In a config file:
+//
+//   [telemetry.layout]
+//   rows = [
+//       [["status", "power"], ["gps", "temp", "weather", "imu"], ["graphs"]],
+//   ]
+//   row_heights = [1.0]
+//   column_widths = [
+//       [0.25, 0.2, 0.55],
+//   ]
+//
+// Heights and widths are relative so the layout follows the window size. Panels that have a
+// height of their own get it, the others share what's left of their column.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use json;
+
+/// Space left around each panel.
+pub const PANEL_MARGIN: f64 = 5.0;
+
+/// A part of the window in conrod's coordinates, with the origin in the middle of the window and
+/// y going up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Area {
+    pub x: f64, // Center
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Area {
+    pub fn new(x: f64, y: f64, w: f64, h: f64) -> Area {
+        Area { x: x, y: y, w: w, h: h }
+    }
+
+    /// The whole window.
+    pub fn window(win_w: f64, win_h: f64) -> Area {
+        Area::new(0.0, 0.0, win_w, win_h)
+    }
+
+    pub fn from_top_left(left: f64, top: f64, w: f64, h: f64) -> Area {
+        Area::new(left + w / 2.0, top - h / 2.0, w, h)
+    }
+
+    pub fn left(&self) -> f64 { self.x - self.w / 2.0 }
+    pub fn right(&self) -> f64 { self.x + self.w / 2.0 }
+    pub fn top(&self) -> f64 { self.y + self.h / 2.0 }
+    pub fn bottom(&self) -> f64 { self.y - self.h / 2.0 }
+
+    pub fn xy(&self) -> [f64; 2] {
+        [self.x, self.y]
+    }
+
+    pub fn wh(&self) -> [f64; 2] {
+        [self.w, self.h]
+    }
+
+    /// The point `dx` right of the left edge and `dy` below the top.
+    pub fn at(&self, dx: f64, dy: f64) -> [f64; 2] {
+        [self.left() + dx, self.top() - dy]
+    }
+
+    /// Split off a strip `h` high along the bottom. Returns what's left above it and the strip.
+    pub fn cut_bottom(&self, h: f64) -> (Area, Area) {
+        let h = h.min(self.h);
+        (Area::from_top_left(self.left(), self.top(), self.w, self.h - h),
+         Area::from_top_left(self.left(), self.bottom() + h, self.w, h))
+    }
+
+    /// `[left, top, width, height]` in window pixels from the top left corner, as piston draws.
+    pub fn to_window(&self, win_w: f64, win_h: f64) -> [f64; 4] {
+        [self.left() + win_w / 2.0, win_h / 2.0 - self.top(), self.w, self.h]
+    }
+
+    /// Whether a point in window pixels from the top left corner is in the area.
+    pub fn contains_window_point(&self, win_w: f64, win_h: f64, x: f64, y: f64) -> bool {
+        let rect = self.to_window(win_w, win_h);
+        x >= rect[0] && x <= rect[0] + rect[2] && y >= rect[1] && y <= rect[1] + rect[3]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub width: f64, // Relative to the other columns of the row
+    pub panels: Vec<String>, // From the top
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub height: f64, // Relative to the other rows
+    pub columns: Vec<Column>, // From the left
+}
+
+/// Which way to move a panel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Move {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub rows: Vec<Row>,
+}
+
+impl Layout {
+    /// A layout of a single row with a column of panels for each entry of `columns`, with their
+    /// relative widths.
+    pub fn columns(columns: &[(f64, &[&str])]) -> Layout {
+        Layout {
+            rows: vec![Row {
+                height: 1.0,
+                columns: columns.iter().map(|&(width, panels)| {
+                    Column { width: width, panels: panels.iter().map(|p| p.to_string()).collect() }
+                }).collect(),
+            }],
+        }
+    }
+
+    /// Every panel, from the top left.
+    pub fn panels(&self) -> Vec<&str> {
+        self.rows.iter()
+            .flat_map(|row| row.columns.iter())
+            .flat_map(|column| column.panels.iter())
+            .map(|panel| panel.as_str())
+            .collect()
+    }
+
+    pub fn remove(&mut self, panel: &str) {
+        for column in self.rows.iter_mut().flat_map(|row| row.columns.iter_mut()) {
+            column.panels.retain(|p| p != panel);
+        }
+    }
+
+    /// Where each panel goes in `area`. `height_of` gives the height a panel needs, or None for
+    /// panels that fill their column.
+    pub fn arrange<F: Fn(&str) -> Option<f64>>(&self, area: Area, height_of: F) -> Vec<(String, Area)> {
+        let mut arranged = vec![];
+
+        let total_h: f64 = self.rows.iter().map(|row| row.height).sum();
+        let mut top = area.top();
+        for row in &self.rows {
+            let row_h = area.h * row.height / total_h;
+
+            let total_w: f64 = row.columns.iter().map(|column| column.width).sum();
+            let mut left = area.left();
+            for column in &row.columns {
+                let column_w = area.w * column.width / total_w;
+
+                let fixed_h: f64 = column.panels.iter().filter_map(|panel| height_of(panel)).sum();
+                let fills = column.panels.iter().filter(|panel| height_of(panel).is_none()).count();
+                let fill_h =
+                    if fills > 0 {
+                        ((row_h - fixed_h - 2.0 * PANEL_MARGIN * column.panels.len() as f64) / fills as f64).max(0.0)
+                    } else {
+                        0.0
+                    };
+
+                let mut panel_top = top;
+                for panel in &column.panels {
+                    let h = height_of(panel).unwrap_or(fill_h);
+                    arranged.push((panel.clone(),
+                                   Area::from_top_left(left + PANEL_MARGIN, panel_top - PANEL_MARGIN,
+                                                       column_w - 2.0 * PANEL_MARGIN, h)));
+                    panel_top -= h + 2.0 * PANEL_MARGIN;
+                }
+                left += column_w;
+            }
+            top -= row_h;
+        }
+
+        arranged
+    }
+
+    /// Move a panel within its column, or into the next column or row. Returns whether it moved.
+    pub fn move_panel(&mut self, panel: &str, to: Move) -> bool {
+        let (r, c, i) =
+            match self.find(panel) {
+                Some(position) => position,
+                None => return false,
+            };
+        let column_len = self.rows[r].columns[c].panels.len();
+
+        let (r2, c2, i2) =
+            match to {
+                Move::Up if i > 0 => (r, c, i - 1),
+                Move::Down if i + 1 < column_len => (r, c, i + 1),
+                Move::Up if r > 0 => {
+                    match self.nearest_column(r - 1, c) {
+                        Some(c2) => (r - 1, c2, self.rows[r - 1].columns[c2].panels.len()),
+                        None => return false,
+                    }
+                },
+                Move::Down if r + 1 < self.rows.len() => {
+                    match self.nearest_column(r + 1, c) {
+                        Some(c2) => (r + 1, c2, 0),
+                        None => return false,
+                    }
+                },
+                Move::Left if c > 0 => (r, c - 1, i.min(self.rows[r].columns[c - 1].panels.len())),
+                Move::Right if c + 1 < self.rows[r].columns.len() => {
+                    (r, c + 1, i.min(self.rows[r].columns[c + 1].panels.len()))
+                },
+                _ => return false,
+            };
+
+        let panel = self.rows[r].columns[c].panels.remove(i);
+        self.rows[r2].columns[c2].panels.insert(i2, panel);
+        true
+    }
+
+    fn find(&self, panel: &str) -> Option<(usize, usize, usize)> {
+        for (r, row) in self.rows.iter().enumerate() {
+            for (c, column) in row.columns.iter().enumerate() {
+                if let Some(i) = column.panels.iter().position(|p| p == panel) {
+                    return Some((r, c, i));
+                }
+            }
+        }
+        None
+    }
+
+    fn nearest_column(&self, row: usize, column: usize) -> Option<usize> {
+        match self.rows[row].columns.len() {
+            0 => None,
+            len => Some(column.min(len - 1)),
+        }
+    }
+
+    /// The layout as a `[<binary>.layout]` config table.
+    pub fn to_toml(&self, binary: &str) -> String {
+        let mut text = format!("[{}.layout]\nrows = [\n", binary);
+        for row in &self.rows {
+            let columns: Vec<String> = row.columns.iter().map(|column| {
+                let panels: Vec<String> = column.panels.iter().map(|panel| json::string(panel)).collect();
+                format!("[{}]", panels.join(", "))
+            }).collect();
+            text.push_str(&format!("    [{}],\n", columns.join(", ")));
+        }
+        let heights: Vec<String> = self.rows.iter().map(|row| format!("{:?}", row.height)).collect();
+        text.push_str(&format!("]\nrow_heights = [{}]\ncolumn_widths = [\n", heights.join(", ")));
+        for row in &self.rows {
+            let widths: Vec<String> = row.columns.iter().map(|column| format!("{:?}", column.width)).collect();
+            text.push_str(&format!("    [{}],\n", widths.join(", ")));
+        }
+        text.push_str("]\n");
+        text
+    }
+
+    /// Write the layout to its own config file at `path`, e.g. to keep an operator's arrangement.
+    pub fn save(&self, path: &str, binary: &str) -> io::Result<()> {
+        if let Some(dir) = Path::new(path).parent() {
+            if dir != Path::new("") {
+                try!(fs::create_dir_all(dir));
+            }
+        }
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(file.write_all(self.to_toml(binary).as_bytes()));
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> Layout {
+        Layout::columns(&[(1.0, &["status", "power"]), (3.0, &["graphs"])])
+    }
+
+    #[test]
+    fn fills_columns_with_what_the_fixed_panels_leave() {
+        let height_of = |panel: &str| if panel == "graphs" { None } else { Some(100.0) };
+        let arranged = layout().arrange(Area::window(800.0, 600.0), height_of);
+        assert_eq!(arranged,
+                   vec![("status".to_string(), Area::from_top_left(-395.0, 295.0, 190.0, 100.0)),
+                        ("power".to_string(), Area::from_top_left(-395.0, 185.0, 190.0, 100.0)),
+                        ("graphs".to_string(), Area::from_top_left(-195.0, 295.0, 590.0, 590.0))]);
+        assert_eq!(arranged[2].1.to_window(800.0, 600.0), [205.0, 5.0, 590.0, 590.0]);
+    }
+
+    #[test]
+    fn moves_panels_between_columns() {
+        let mut layout = layout();
+        assert!(layout.move_panel("power", Move::Right));
+        assert_eq!(layout.panels(), vec!["status", "graphs", "power"]);
+        assert!(layout.move_panel("power", Move::Up));
+        assert_eq!(layout.panels(), vec!["status", "power", "graphs"]);
+        assert!(!layout.move_panel("power", Move::Up));
+        assert!(layout.move_panel("power", Move::Left));
+        assert_eq!(layout.rows[0].columns[0].panels, vec!["power", "status"]);
+        assert!(!layout.move_panel("missing", Move::Down));
+    }
+
+    #[test]
+    fn writes_config() {
+        assert_eq!(layout().to_toml("telemetry"),
+                   "[telemetry.layout]\nrows = [\n    [[\"status\", \"power\"], [\"graphs\"]],\n]\n\
+                    row_heights = [1.0]\ncolumn_widths = [\n    [1.0, 3.0],\n]\n");
+    }
+}
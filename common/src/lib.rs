@@ -0,0 +1,28 @@
+//! Code shared by the ground station binaries.
+
+extern crate time;
+#[cfg(feature = "widgets")] extern crate conrod;
+
+pub use mission_time::MissionTime;
+pub use out_queue::OutQueue;
+
+pub mod capture;
+pub mod clock;
+pub mod crc;
+pub mod data_log;
+pub mod frame;
+pub mod gps;
+pub mod imu;
+pub mod integrity;
+pub mod json;
+pub mod layout;
+pub mod limits;
+pub mod link;
+pub mod logger;
+pub mod mission_time;
+pub mod out_queue;
+pub mod protocol;
+pub mod receiver;
+pub mod replay;
+pub mod session;
+#[cfg(feature = "widgets")] pub mod widgets;
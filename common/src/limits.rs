@@ -0,0 +1,81 @@
+/// Red/yellow/green limits for a telemetry value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RygLimit {
+    LessThan(f64, f64), // Red below the first value, yellow below the second
+    GreaterThan(f64, f64), // Red above the first value, yellow above the second
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Green,
+    Yellow,
+    Red,
+}
+
+impl RygLimit {
+    pub fn level(&self, value: f64) -> Level {
+        match *self {
+            RygLimit::LessThan(r, y) => {
+                if value < r {
+                    Level::Red
+                } else if value < y {
+                    Level::Yellow
+                } else {
+                    Level::Green
+                }
+            },
+            RygLimit::GreaterThan(r, y) => {
+                if value > r {
+                    Level::Red
+                } else if value > y {
+                    Level::Yellow
+                } else {
+                    Level::Green
+                }
+            },
+        }
+    }
+
+    /// The ranges of values at each level, lowest first. The outer ones are open ended.
+    pub fn bands(&self) -> [(f64, f64, Level); 3] {
+        use std::f64::{INFINITY, NEG_INFINITY};
+
+        match *self {
+            RygLimit::LessThan(r, y) => [(NEG_INFINITY, r, Level::Red), (r, y, Level::Yellow), (y, INFINITY, Level::Green)],
+            RygLimit::GreaterThan(r, y) => [(NEG_INFINITY, y, Level::Green), (y, r, Level::Yellow), (r, INFINITY, Level::Red)],
+        }
+    }
+}
+
+pub const H48_V: RygLimit = RygLimit::LessThan(45.0, 48.0);
+pub const P12E_V: RygLimit = RygLimit::LessThan(10.0, 12.0);
+pub const MOTOR_TEMP: RygLimit = RygLimit::GreaterThan(80.0, 60.0);
+pub const AVIONICS_TEMP: RygLimit = RygLimit::GreaterThan(50.0, 40.0);
+
+/// The limits of the logged telemetry columns that have them, by channel and column.
+pub const ALARMS: &'static [(&'static str, &'static str, RygLimit)] = &[
+    ("volt", "h48_v", H48_V),
+    ("volt", "p12e_v", P12E_V),
+    ("motor_temp", "l_motor_c", MOTOR_TEMP),
+    ("motor_temp", "r_motor_c", MOTOR_TEMP),
+    ("motor_temp", "avionics_c", AVIONICS_TEMP),
+];
+
+pub fn alarm_limit(channel: &str, column: &str) -> Option<RygLimit> {
+    ALARMS.iter().find(|alarm| alarm.0 == channel && alarm.1 == column).map(|alarm| alarm.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands_match_levels() {
+        for limit in &[H48_V, MOTOR_TEMP] {
+            for &(low, high, level) in limit.bands().iter() {
+                let value = if low.is_infinite() { high - 1.0 } else if high.is_infinite() { low + 1.0 } else { (low + high) / 2.0 };
+                assert_eq!(limit.level(value), level);
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::UdpSocket;
+
+use time;
+
+use capture::{Capture, Direction};
+use protocol::{add_checksum, Command, HANDSHAKE};
+
+const PING_INTERVAL_MS: i64 = 1000;
+const PING_TIMEOUT_MS: i64 = 3000; // A ping without a pong by now counts as lost
+const DEGRADED_MS: i64 = 2000; // Nothing heard for this long and the link is degraded
+const LOST_MS: i64 = 5000; // Nothing heard for this long and the link is lost
+const HANDSHAKE_INTERVAL_MS: i64 = 5000; // Resend the handshake this often while the link is lost
+const LOSS_WINDOW: usize = 20; // Number of recent pings used for the packet loss percentage
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkState {
+    Waiting, // Haven't heard from the rover yet
+    Up,
+    Degraded,
+    Lost,
+    Error, // The socket is failing, see `LinkMonitor::error`
+}
+
+/// Keeps track of the health of the link to the rover with a periodic ping over the existing UDP
+/// socket. Pings are sent straight to the socket so they measure the real link, not any simulated
+/// delay.
+pub struct LinkMonitor {
+    rover_addr: (String, u16),
+    checksums: bool, // Whether pings carry a checksum like the other commands
+    capture: Option<Capture>, // Pings and handshakes are recorded here
+
+    next_seq: u32,
+    last_ping_time: time::Tm,
+    last_handshake_time: time::Tm,
+    outstanding: VecDeque<(u32, time::Tm)>, // Pings waiting for a pong
+
+    ping_results: VecDeque<bool>, // Whether each recent ping got a pong
+    rtt: Option<time::Duration>,
+    one_way: Option<(i64, i64)>, // Uplink and downlink delay in ms from the ping timestamps
+    last_heard: Option<time::Tm>,
+    last_error: Option<(time::Tm, String)>,
+}
+
+impl LinkMonitor {
+    pub fn new(rover_addr: (String, u16)) -> LinkMonitor {
+        LinkMonitor {
+            rover_addr: rover_addr,
+            checksums: false,
+            capture: None,
+
+            next_seq: 0,
+            last_ping_time: time::now(),
+            last_handshake_time: time::now(),
+            outstanding: VecDeque::new(),
+
+            ping_results: VecDeque::new(),
+            rtt: None,
+            one_way: None,
+            last_heard: None,
+            last_error: None,
+        }
+    }
+
+    pub fn set_checksums(&mut self, checksums: bool) {
+        self.checksums = checksums;
+    }
+
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    /// Send a ping when one is due and resend the handshake while the link is lost.
+    pub fn update(&mut self, socket: &UdpSocket) -> io::Result<()> {
+        let now = time::now();
+
+        // Expire pings that never got a pong
+        while !self.outstanding.is_empty() &&
+              (now - self.outstanding[0].1).num_milliseconds() >= PING_TIMEOUT_MS {
+            self.outstanding.pop_front();
+            self.add_ping_result(false);
+        }
+
+        if (now - self.last_ping_time).num_milliseconds() >= PING_INTERVAL_MS {
+            self.last_ping_time = now;
+            let seq = self.next_seq;
+            self.next_seq = self.next_seq.wrapping_add(1);
+            self.outstanding.push_back((seq, now));
+            let mut ping = Command::Ping(seq, timestamp_ms()).encode();
+            if self.checksums {
+                ping = add_checksum(&ping);
+            }
+            try!(self.send(socket, ping.as_slice()));
+        }
+
+        let silent = match self.state() {
+            LinkState::Waiting | LinkState::Lost | LinkState::Error => true,
+            LinkState::Up | LinkState::Degraded => false,
+        };
+        if silent && (now - self.last_handshake_time).num_milliseconds() >= HANDSHAKE_INTERVAL_MS {
+            self.last_handshake_time = now;
+            try!(self.send(socket, HANDSHAKE));
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, socket: &UdpSocket, data: &[u8]) -> io::Result<()> {
+        let result = socket.send_to(data, (self.rover_addr.0.as_str(), self.rover_addr.1));
+        match result {
+            Ok(_) => {
+                if let Some(ref capture) = self.capture {
+                    capture.record(Direction::Out, data);
+                }
+            },
+            Err(ref e) => self.on_error(format!("Send failed: {}", e)),
+        }
+        result.map(|_| ())
+    }
+
+    /// Call for every datagram received from the rover.
+    pub fn on_packet(&mut self) {
+        self.last_heard = Some(time::now());
+    }
+
+    /// Call when the socket reports an error. The link shows as failing until the next datagram
+    /// arrives or the error is cleared.
+    pub fn on_error(&mut self, error: String) {
+        self.last_error = Some((time::now(), error));
+    }
+
+    /// Call after the socket has been replaced.
+    pub fn clear_error(&mut self) {
+        self.last_error = None;
+    }
+
+    /// The socket error that is currently affecting the link, if any.
+    pub fn error(&self) -> Option<&str> {
+        match self.last_error {
+            Some((error_time, ref error)) => {
+                match self.last_heard {
+                    Some(heard) if heard > error_time => None,
+                    _ => Some(error.as_str()),
+                }
+            },
+            None => None,
+        }
+    }
+
+    pub fn on_pong(&mut self, seq: u32, sent_ms: u64, rover_ms: u64) {
+        if let Some(i) = self.outstanding.iter().position(|&(s, _)| s == seq) {
+            let (_, sent_time) = self.outstanding.remove(i).unwrap();
+            self.rtt = Some(time::now() - sent_time);
+            self.add_ping_result(true);
+
+            // The one-way delays are only meaningful when both clocks are synchronized (GPS/NTP).
+            // If they clearly aren't we fall back to half the round trip.
+            let received_ms = timestamp_ms();
+            let uplink = rover_ms as i64 - sent_ms as i64;
+            let downlink = received_ms as i64 - rover_ms as i64;
+            self.one_way = if uplink >= 0 && downlink >= 0 { Some((uplink, downlink)) } else { None };
+        }
+    }
+
+    fn add_ping_result(&mut self, ponged: bool) {
+        self.ping_results.push_back(ponged);
+        if self.ping_results.len() > LOSS_WINDOW {
+            self.ping_results.pop_front();
+        }
+    }
+
+    pub fn state(&self) -> LinkState {
+        if self.error().is_some() {
+            return LinkState::Error;
+        }
+        match self.last_heard_age() {
+            None => LinkState::Waiting,
+            Some(age) if age.num_milliseconds() >= LOST_MS => LinkState::Lost,
+            Some(age) if age.num_milliseconds() >= DEGRADED_MS => LinkState::Degraded,
+            Some(_) => {
+                match self.packet_loss() {
+                    Some(loss) if loss >= 20.0 => LinkState::Degraded,
+                    _ => LinkState::Up,
+                }
+            },
+        }
+    }
+
+    pub fn rtt(&self) -> Option<time::Duration> {
+        self.rtt
+    }
+
+    /// Measured uplink and downlink delay in milliseconds, if the rover's clock agrees with ours.
+    pub fn one_way_delay(&self) -> Option<(i64, i64)> {
+        self.one_way
+    }
+
+    pub fn delay_text(&self) -> String {
+        match (self.rtt, self.one_way) {
+            (Some(rtt), Some((uplink, downlink))) => {
+                format!("Comm Delay: {}ms up / {}ms down (RTT {}ms)", uplink, downlink, rtt.num_milliseconds())
+            },
+            (Some(rtt), None) => {
+                format!("Comm Delay: ~{}ms (RTT {}ms)", rtt.num_milliseconds() / 2, rtt.num_milliseconds())
+            },
+            (None, _) => "Comm Delay: --".to_string(),
+        }
+    }
+
+    /// Percentage of recent pings that never got a pong.
+    pub fn packet_loss(&self) -> Option<f64> {
+        if self.ping_results.is_empty() {
+            return None;
+        }
+        let lost = self.ping_results.iter().filter(|ponged| !**ponged).count();
+        Some(100.0 * lost as f64 / self.ping_results.len() as f64)
+    }
+
+    pub fn last_heard_age(&self) -> Option<time::Duration> {
+        self.last_heard.map(|t| time::now() - t)
+    }
+
+    pub fn status_text(&self) -> String {
+        let state =
+            match self.state() {
+                LinkState::Waiting => "WAITING",
+                LinkState::Up => "UP",
+                LinkState::Degraded => "DEGRADED",
+                LinkState::Lost => "LOST",
+                LinkState::Error => "ERROR",
+            };
+        let rtt =
+            match self.rtt {
+                Some(rtt) => format!("{}ms", rtt.num_milliseconds()),
+                None => "--".to_string(),
+            };
+        let loss =
+            match self.packet_loss() {
+                Some(loss) => format!("{:.0}%", loss),
+                None => "--".to_string(),
+            };
+        let heard =
+            match self.last_heard_age() {
+                Some(age) => format!("{:.1}s", age.num_milliseconds() as f64 / 1000.0),
+                None => "never".to_string(),
+            };
+        match self.error() {
+            Some(error) => format!("Link {}  {}  Heard {}", state, error, heard),
+            None => format!("Link {}  RTT {}  Loss {}  Heard {}", state, rtt, loss, heard),
+        }
+    }
+
+    /// Green, yellow or red depending on the link state.
+    pub fn status_rgb(&self) -> (f32, f32, f32) {
+        match self.state() {
+            LinkState::Up => (0.0, 1.0, 0.0),
+            LinkState::Degraded => (1.0, 1.0, 0.0),
+            LinkState::Waiting | LinkState::Lost | LinkState::Error => (1.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Milliseconds since the unix epoch, as used in the ping timestamps.
+pub fn timestamp_ms() -> u64 {
+    let now = time::get_time();
+    (now.sec as u64) * 1000 + (now.nsec as u64) / 1_000_000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn waits_for_the_rover() {
+        let link = LinkMonitor::new(("127.0.0.1".to_string(), 30001));
+        assert_eq!(link.state(), LinkState::Waiting);
+        assert_eq!(link.packet_loss(), None);
+        assert_eq!(link.delay_text(), "Comm Delay: --");
+    }
+
+    #[test]
+    fn measures_round_trip() {
+        let rover = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut link = LinkMonitor::new(("127.0.0.1".to_string(), rover.local_addr().unwrap().port()));
+
+        link.last_ping_time = time::now() - time::Duration::seconds(2);
+        link.update(&socket).unwrap();
+        assert_eq!(link.outstanding.len(), 1);
+
+        let sent_ms = timestamp_ms();
+        link.on_packet();
+        link.on_pong(0, sent_ms, sent_ms);
+        assert_eq!(link.state(), LinkState::Up);
+        assert_eq!(link.packet_loss(), Some(0.0));
+        assert!(link.rtt().is_some());
+        assert!(link.one_way_delay().is_some());
+
+        // Unknown pongs are ignored
+        link.on_pong(42, sent_ms, sent_ms);
+        assert_eq!(link.packet_loss(), Some(0.0));
+    }
+
+    #[test]
+    fn reports_socket_errors_until_heard_from() {
+        let mut link = LinkMonitor::new(("127.0.0.1".to_string(), 30001));
+        link.on_error("Receive failed: network is down".to_string());
+        assert_eq!(link.state(), LinkState::Error);
+        assert!(link.status_text().contains("network is down"));
+
+        link.last_heard = Some(time::now() + time::Duration::milliseconds(1));
+        assert_eq!(link.error(), None);
+        assert_eq!(link.state(), LinkState::Up);
+
+        link.on_error("Send failed: network is down".to_string());
+        link.clear_error();
+        assert_eq!(link.error(), None);
+    }
+}
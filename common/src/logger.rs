@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use time;
+
+use data_log::{DataLog, LogFormat};
+use imu::pitch_roll_heading;
+use protocol::TelemetryMsg;
+use session::Session;
+
+/// The telemetry channels that can be logged and their value columns.
+pub const CHANNELS: &'static [(&'static str, &'static [&'static str])] = &[
+    ("imu", &["pitch", "roll", "heading"]),
+    ("gps", &["latitude", "longitude", "speed", "altitude", "angle"]),
+    ("volt", &["h48_v", "p12e_v"]),
+    ("amp", &["p12e_a", "l_motor_a", "r_motor_a"]),
+    ("motor_temp", &["l_motor_c", "r_motor_c", "avionics_c"]),
+    ("weather", &["wind_speed", "pressure", "altitude", "temp"]),
+];
+
+pub fn channel_columns(channel: &str) -> Option<&'static [&'static str]> {
+    CHANNELS.iter().find(|c| c.0 == channel).map(|c| c.1)
+}
+
+/// The channel a telemetry message belongs to, with the columns it updates.
+pub fn channel_values(msg: &TelemetryMsg) -> Option<(&'static str, Vec<(usize, Option<f64>)>)> {
+    match *msg {
+        TelemetryMsg::Imu { accel, mag, .. } => {
+            let (pitch, roll, heading) = pitch_roll_heading(accel, mag);
+            Some(("imu", vec![(0, Some(pitch)), (1, Some(roll)), (2, Some(heading))]))
+        },
+        TelemetryMsg::Gps { latitude, longitude, speed, altitude, angle } => {
+            Some(("gps", vec![(0, latitude), (1, longitude), (2, speed), (3, altitude), (4, angle)]))
+        },
+        TelemetryMsg::Volt { h_48_v, p_12_e_v } => Some(("volt", vec![(0, Some(h_48_v)), (1, Some(p_12_e_v))])),
+        TelemetryMsg::Amp { l_motor, r_motor, p_12_e } => {
+            Some(("amp", vec![(0, Some(p_12_e)), (1, Some(l_motor)), (2, Some(r_motor))]))
+        },
+        TelemetryMsg::LMotorTemp(temp) => Some(("motor_temp", vec![(0, Some(temp))])),
+        TelemetryMsg::RMotorTemp(temp) => Some(("motor_temp", vec![(1, Some(temp))])),
+        TelemetryMsg::AvionicsTemp(temp) => Some(("motor_temp", vec![(2, Some(temp))])),
+        TelemetryMsg::WindSpeed(speed) => Some(("weather", vec![(0, Some(speed))])),
+        TelemetryMsg::WeatherPressureAltitude { pressure, altitude } => {
+            Some(("weather", vec![(1, Some(pressure)), (2, Some(altitude))]))
+        },
+        TelemetryMsg::WeatherTemp(temp) => Some(("weather", vec![(3, Some(temp))])),
+        TelemetryMsg::Ack { .. } | TelemetryMsg::Pong { .. } | TelemetryMsg::BadCommands(_) => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogMode {
+    EveryPacket, // A row for every message, with the latest value of each column
+    Sample(u32), // The latest values every this many milliseconds
+    Average(u32), // The mean of the values received in each window of this many milliseconds
+}
+
+/// A log file for one channel, as declared in the config.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogSink {
+    pub channel: String,
+    pub file: String, // File name in the mission folder, without the extension
+    pub format: LogFormat,
+    pub mode: LogMode,
+}
+
+impl LogSink {
+    /// The sinks used when none are configured, every channel sampled once a second.
+    pub fn defaults(format: LogFormat) -> Vec<LogSink> {
+        CHANNELS.iter().map(|&(channel, _)| {
+            LogSink {
+                channel: channel.to_string(),
+                file: channel.to_string(),
+                format: format,
+                mode: LogMode::Sample(1000),
+            }
+        }).collect()
+    }
+}
+
+struct Sink<W: Write> {
+    channel: String,
+    mode: LogMode,
+    log: DataLog<W>,
+    latest: Vec<Option<f64>>,
+    sums: Vec<(f64, u32)>, // Sum and count of each column in the current window
+    window_start: Option<time::Tm>,
+}
+
+/// Writes telemetry to any number of logs, each at its own rate. Logs are flushed on a timer so
+/// little is lost if the ground station crashes.
+pub struct Logger<W: Write> {
+    sinks: Vec<Sink<W>>,
+    flush_interval_ms: u32,
+    last_flush: time::Tm,
+}
+
+impl Logger<BufWriter<File>> {
+    /// Create the log files of `sinks` in the session folder and add them to its manifest. Sinks
+    /// that fail are reported and skipped.
+    pub fn create(session: &Session, sinks: &[LogSink], flush_interval_ms: u32) -> Logger<BufWriter<File>> {
+        let mut logger = Logger::new(flush_interval_ms);
+        for sink in sinks {
+            let columns =
+                match channel_columns(sink.channel.as_str()) {
+                    Some(columns) => columns,
+                    None => {
+                        println!("WARNING: Unknown log channel '{}'", sink.channel);
+                        continue;
+                    },
+                };
+            match DataLog::create(session.folder(), sink.file.as_str(), sink.format, sink.channel.as_str(), columns) {
+                Ok(log) => {
+                    logger.add_sink(sink.channel.as_str(), sink.mode, log);
+                    session.add_artifact(&format!("{}.{}", sink.file, sink.format.extension()), "log",
+                                         &format!("{} telemetry", sink.channel));
+                },
+                Err(e) => println!("WARNING: Failed to create {} log: {}", sink.file, e),
+            }
+        }
+        logger
+    }
+}
+
+impl<W: Write> Logger<W> {
+    pub fn new(flush_interval_ms: u32) -> Logger<W> {
+        Logger {
+            sinks: vec![],
+            flush_interval_ms: flush_interval_ms,
+            last_flush: time::now(),
+        }
+    }
+
+    /// Log `channel` to `log`, which must have the channel's columns.
+    pub fn add_sink(&mut self, channel: &str, mode: LogMode, log: DataLog<W>) {
+        let columns = channel_columns(channel).map(|c| c.len()).unwrap_or(0);
+        self.sinks.push(Sink {
+            channel: channel.to_string(),
+            mode: mode,
+            log: log,
+            latest: vec![None; columns],
+            sums: vec![(0.0, 0); columns],
+            window_start: None,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Log a received message.
+    pub fn on_msg(&mut self, msg: &TelemetryMsg, mission_elapsed: time::Duration) {
+        self.on_msg_at(time::now(), msg, mission_elapsed);
+    }
+
+    fn on_msg_at(&mut self, now: time::Tm, msg: &TelemetryMsg, mission_elapsed: time::Duration) {
+        let (channel, values) =
+            match channel_values(msg) {
+                Some(channel_values) => channel_values,
+                None => return,
+            };
+
+        for sink in self.sinks.iter_mut().filter(|sink| sink.channel == channel) {
+            for &(column, value) in &values {
+                sink.latest[column] = value;
+                if let Some(value) = value {
+                    sink.sums[column].0 += value;
+                    sink.sums[column].1 += 1;
+                }
+            }
+            if sink.mode == LogMode::EveryPacket {
+                let result = sink.log.write_row(&now, mission_elapsed, &sink.latest);
+                report(&sink.channel, result);
+            }
+        }
+    }
+
+    /// Write the rows of every window that has ended and flush the logs when it's time. Call
+    /// regularly.
+    pub fn update(&mut self, mission_elapsed: time::Duration) {
+        self.update_at(time::now(), mission_elapsed);
+    }
+
+    fn update_at(&mut self, now: time::Tm, mission_elapsed: time::Duration) {
+        for sink in &mut self.sinks {
+            let interval_ms =
+                match sink.mode {
+                    LogMode::EveryPacket => continue,
+                    LogMode::Sample(ms) | LogMode::Average(ms) => ms as i64,
+                };
+            let window_start =
+                match sink.window_start {
+                    Some(window_start) => window_start,
+                    None => {
+                        sink.window_start = Some(now);
+                        continue;
+                    },
+                };
+            if (now - window_start).num_milliseconds() < interval_ms {
+                continue;
+            }
+            sink.window_start = Some(now);
+
+            let row =
+                match sink.mode {
+                    LogMode::Average(_) => {
+                        sink.sums.iter().map(|&(sum, count)| {
+                            if count > 0 { Some(sum / count as f64) } else { None }
+                        }).collect()
+                    },
+                    _ => sink.latest.clone(),
+                };
+            for sum in sink.sums.iter_mut() {
+                *sum = (0.0, 0);
+            }
+            let result = sink.log.write_row(&now, mission_elapsed, &row);
+            report(&sink.channel, result);
+        }
+
+        if (now - self.last_flush).num_milliseconds() >= self.flush_interval_ms as i64 {
+            self.last_flush = now;
+            if let Err(e) = self.flush() {
+                println!("WARNING: Failed to flush logs: {}", e);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        for sink in &mut self.sinks {
+            try!(sink.log.flush());
+        }
+        Ok(())
+    }
+}
+
+fn report(channel: &str, result: io::Result<()>) {
+    if let Err(e) = result {
+        println!("WARNING: Failed to write {} log: {}", channel, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_log::{DataLog, LogFormat};
+    use protocol::TelemetryMsg;
+    use time;
+
+    fn at_ms(ms: i64) -> time::Tm {
+        time::at_utc(time::Timespec::new(1_464_890_645 + ms / 1000, (ms % 1000) as i32 * 1_000_000))
+    }
+
+    fn logger(mode: LogMode) -> Logger<Vec<u8>> {
+        let mut logger = Logger::new(1000);
+        let log = DataLog::new(vec![], LogFormat::Csv, "motor_temp", channel_columns("motor_temp").unwrap()).unwrap();
+        logger.add_sink("motor_temp", mode, log);
+        logger
+    }
+
+    fn rows(logger: Logger<Vec<u8>>) -> Vec<String> {
+        let sink = logger.sinks.into_iter().next().unwrap();
+        let text = String::from_utf8(sink.log.into_inner()).unwrap();
+        // Drop the header and the times
+        text.lines().skip(1).map(|line| line.splitn(3, ',').nth(2).unwrap().to_string()).collect()
+    }
+
+    fn run(logger: &mut Logger<Vec<u8>>) {
+        let elapsed = time::Duration::zero();
+        logger.update_at(at_ms(0), elapsed);
+        logger.on_msg_at(at_ms(100), &TelemetryMsg::LMotorTemp(40.0), elapsed);
+        logger.on_msg_at(at_ms(200), &TelemetryMsg::AvionicsTemp(30.0), elapsed);
+        logger.on_msg_at(at_ms(300), &TelemetryMsg::LMotorTemp(50.0), elapsed);
+        logger.on_msg_at(at_ms(300), &TelemetryMsg::Pong { seq: 1, sent_ms: 0, rover_ms: 0 }, elapsed);
+        logger.update_at(at_ms(500), elapsed);
+        logger.update_at(at_ms(1000), elapsed);
+        logger.update_at(at_ms(2000), elapsed);
+    }
+
+    #[test]
+    fn logs_every_packet() {
+        let mut logger = logger(LogMode::EveryPacket);
+        run(&mut logger);
+        assert_eq!(rows(logger), vec!["40,,", "40,,30", "50,,30"]);
+    }
+
+    #[test]
+    fn samples_the_latest_values() {
+        let mut logger = logger(LogMode::Sample(1000));
+        run(&mut logger);
+        assert_eq!(rows(logger), vec!["50,,30", "50,,30"]);
+    }
+
+    #[test]
+    fn averages_each_window() {
+        let mut logger = logger(LogMode::Average(1000));
+        run(&mut logger);
+        assert_eq!(rows(logger), vec!["45,,30", ",,"]);
+    }
+}
@@ -0,0 +1,123 @@
+use time;
+
+/// Mission elapsed time that can be paused and resumed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissionTime {
+    Paused(time::Duration),
+    Running(time::Tm, time::Duration), // Start of the current run and the time accumulated before it
+}
+
+impl MissionTime {
+    pub fn new() -> MissionTime {
+        MissionTime::Paused(time::Duration::zero())
+    }
+
+    pub fn is_running(&self) -> bool {
+        match *self {
+            MissionTime::Paused(_) => false,
+            MissionTime::Running(_, _) => true,
+        }
+    }
+
+    pub fn elapsed(&self) -> time::Duration {
+        self.elapsed_at(time::now())
+    }
+
+    pub fn elapsed_at(&self, now: time::Tm) -> time::Duration {
+        match *self {
+            MissionTime::Paused(t) => t,
+            MissionTime::Running(start_time, extra_time) => (now - start_time) + extra_time,
+        }
+    }
+
+    pub fn start(&mut self) {
+        if let MissionTime::Paused(current_time) = *self {
+            *self = MissionTime::Running(time::now(), current_time);
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if self.is_running() {
+            *self = MissionTime::Paused(self.elapsed());
+        }
+    }
+
+    /// Start if paused, pause if running.
+    pub fn toggle(&mut self) {
+        if self.is_running() {
+            self.pause();
+        } else {
+            self.start();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = MissionTime::new();
+    }
+
+    /// Label for the start/pause button.
+    pub fn button_text(&self) -> &'static str {
+        if self.is_running() { "Pause" } else { "Start" }
+    }
+
+    pub fn text(&self) -> String {
+        format!("Mission Time: {}", format_duration(self.elapsed()))
+    }
+}
+
+/// Format a duration as `days:hours:minutes:seconds`.
+pub fn format_duration(duration: time::Duration) -> String {
+    let total_days = duration.num_days();
+    let total_hours = duration.num_hours();
+    let total_minutes = duration.num_minutes();
+    let total_seconds = duration.num_seconds();
+
+    let days = total_days;
+    let hours = total_hours - total_days*24;
+    let minutes = total_minutes - total_hours*60;
+    let seconds = total_seconds - total_minutes*60;
+    format!("{}:{}:{}:{}", days, hours, minutes, seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(time::Duration::zero()), "0:0:0:0");
+        assert_eq!(format_duration(time::Duration::seconds(59)), "0:0:0:59");
+        assert_eq!(format_duration(time::Duration::seconds(2*86400 + 3*3600 + 4*60 + 5)), "2:3:4:5");
+    }
+
+    #[test]
+    fn accumulates_time_across_pauses() {
+        let start = time::now();
+        let running = MissionTime::Running(start, time::Duration::seconds(10));
+        let later = start + time::Duration::seconds(5);
+        assert_eq!(running.elapsed_at(later), time::Duration::seconds(15));
+
+        let paused = MissionTime::Paused(time::Duration::seconds(15));
+        assert_eq!(paused.elapsed_at(later + time::Duration::hours(1)), time::Duration::seconds(15));
+    }
+
+    #[test]
+    fn start_pause_and_reset() {
+        let mut mission_time = MissionTime::new();
+        assert!(!mission_time.is_running());
+        assert_eq!(mission_time.button_text(), "Start");
+
+        mission_time.toggle();
+        assert!(mission_time.is_running());
+        assert_eq!(mission_time.button_text(), "Pause");
+        mission_time.start(); // Already running, keeps the original start time
+        assert!(mission_time.is_running());
+
+        mission_time.toggle();
+        assert!(!mission_time.is_running());
+
+        mission_time.reset();
+        assert_eq!(mission_time, MissionTime::Paused(time::Duration::zero()));
+    }
+}
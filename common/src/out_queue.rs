@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::UdpSocket;
+
+use time;
+
+use capture::{Capture, Direction};
+
+/// Outbound packets held back by an artificial delay, used to simulate the communication delay of
+/// a far away rover.
+pub struct OutQueue {
+    packets: VecDeque<(time::Tm, time::Duration, Vec<u8>, (String, u16))>,
+    capture: Option<Capture>, // Every packet sent is recorded here
+}
+
+impl OutQueue {
+    pub fn new() -> OutQueue {
+        OutQueue { packets: VecDeque::new(), capture: None }
+    }
+
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    pub fn push(&mut self, delay: time::Duration, data: Vec<u8>, addr: (String, u16)) {
+        self.push_at(time::now(), delay, data, addr);
+    }
+
+    fn push_at(&mut self, now: time::Tm, delay: time::Duration, data: Vec<u8>, addr: (String, u16)) {
+        self.packets.push_back((now, delay, data, addr));
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Remove the packets whose delay has passed, in the order they were queued.
+    fn pop_due(&mut self, now: time::Tm) -> Vec<(Vec<u8>, (String, u16))> {
+        let mut due = vec![];
+        while !self.packets.is_empty() {
+            if now - self.packets[0].0 >= self.packets[0].1 {
+                let (_, _, data, addr) = self.packets.pop_front().unwrap();
+                due.push((data, addr));
+            } else {
+                break;
+            }
+        }
+        due
+    }
+
+    /// Send every packet that is due.
+    pub fn flush(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        let mut bytes_written = 0;
+        for (data, addr) in self.pop_due(time::now()) {
+            bytes_written += try!(socket.send_to(data.as_slice(), (addr.0.as_str(), addr.1)));
+            if let Some(ref capture) = self.capture {
+                capture.record(Direction::Out, &data);
+            }
+        }
+        Ok(bytes_written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time;
+
+    fn addr() -> (String, u16) {
+        ("127.0.0.1".to_string(), 30001)
+    }
+
+    #[test]
+    fn holds_packets_until_due() {
+        let now = time::now();
+        let mut queue = OutQueue::new();
+        queue.push_at(now, time::Duration::seconds(0), b"A".to_vec(), addr());
+        queue.push_at(now, time::Duration::seconds(2), b"B".to_vec(), addr());
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop_due(now), vec![(b"A".to_vec(), addr())]);
+        assert_eq!(queue.pop_due(now + time::Duration::seconds(1)), vec![]);
+        assert_eq!(queue.pop_due(now + time::Duration::seconds(2)), vec![(b"B".to_vec(), addr())]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn keeps_packets_in_order() {
+        // A packet with a shorter delay doesn't overtake one queued before it
+        let now = time::now();
+        let mut queue = OutQueue::new();
+        queue.push_at(now, time::Duration::seconds(5), b"A".to_vec(), addr());
+        queue.push_at(now, time::Duration::seconds(0), b"B".to_vec(), addr());
+        assert_eq!(queue.pop_due(now), vec![]);
+        assert_eq!(queue.pop_due(now + time::Duration::seconds(5)).len(), 2);
+    }
+
+    #[test]
+    fn sends_due_packets() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = receiver.local_addr().unwrap().port();
+
+        let mut queue = OutQueue::new();
+        queue.push(time::Duration::seconds(0), b"G|\0".to_vec(), ("127.0.0.1".to_string(), port));
+        assert_eq!(queue.flush(&sender).unwrap(), 3);
+
+        let mut buf = [0u8; 16];
+        let (bytes_read, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..bytes_read], b"G|\0");
+    }
+}
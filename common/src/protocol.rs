@@ -0,0 +1,586 @@
+use std::fmt;
+use std::str;
+
+use crc::crc32;
+
+/// Sent by every ground station binary so the rover knows where to send telemetry.
+pub const HANDSHAKE: &'static [u8] = b"connect me plz";
+
+// Uplink command protocol
+//
+// Every command is a one-letter opcode followed by its arguments, each argument terminated by a
+// `|`. Commands without arguments are sent as the opcode followed by a single `|`. The whole
+// packet is null terminated.
+//
+//     A{l_rpm}|        C{pan}|        H{l_rpm}|{r_rpm}|        G|
+//     Z{COMMAND}|{motor_speed}|
+//
+// `M{l_rpm}|{r_rpm}|{ttl_ms}|` drives like `H` but the rover stops on its own if it doesn't get
+// another drive command within `ttl_ms`. The ground station re-sends it while a key is held.
+//
+// Commands that need to be acknowledged are prefixed with a sequence number, `R{seq}|G|`. The
+// rover answers with `ACK:G:{seq}` whether or not it applies the command. Sequence numbers are
+// only compared between commands that control the same thing (see `Command::class`): the rover
+// ignores a sequenced command unless it is newer than the last one it applied in that class, so a
+// retransmitted brake can't undo a later drive but is still applied after a later SADL command.
+// Sequence numbers wrap, so "newer" means less than 2^31 ahead.
+//
+// Any packet can optionally end with a checksum, `*` and the CRC-32 of everything before it as 8
+// hex digits, e.g. `A100|*49B8F754`. This applies to commands and legacy text telemetry alike.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    LRpm(i32),                  // A
+    RRpm(i32),                  // B
+    FPan(i32),                  // C
+    FTilt(i32),                 // D
+    Sadl(i32),                  // E
+    Blade(i32),                 // F
+    Brake,                      // G
+    LrRpm(i32, i32),            // H
+    StereoPan(i32),             // I
+    StereoTilt(i32),            // J
+    StereoSnapshot,             // K
+    StereoPanorama,             // L
+    TimedDrive(i32, i32, u32),  // M {l_rpm}|{r_rpm}|{ttl_ms}|
+    Ping(u32, u64),             // P {seq}|{ground station time in ms}|
+    Command(String, f32),       // Z
+}
+
+impl Command {
+    pub fn opcode(&self) -> char {
+        match *self {
+            Command::LRpm(_) => 'A',
+            Command::RRpm(_) => 'B',
+            Command::FPan(_) => 'C',
+            Command::FTilt(_) => 'D',
+            Command::Sadl(_) => 'E',
+            Command::Blade(_) => 'F',
+            Command::Brake => 'G',
+            Command::LrRpm(_, _) => 'H',
+            Command::StereoPan(_) => 'I',
+            Command::StereoTilt(_) => 'J',
+            Command::StereoSnapshot => 'K',
+            Command::StereoPanorama => 'L',
+            Command::TimedDrive(_, _, _) => 'M',
+            Command::Ping(_, _) => 'P',
+            Command::Command(_, _) => 'Z',
+        }
+    }
+
+    /// Commands of the same class control the same thing, so a newer one makes older ones
+    /// obsolete. All the drive commands share class `H`, everything else is its own class.
+    pub fn class(&self) -> char {
+        match self.opcode() {
+            'A' | 'B' | 'G' | 'H' | 'M' => 'H',
+            opcode => opcode,
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        match *self {
+            Command::LRpm(v) | Command::RRpm(v) |
+            Command::FPan(v) | Command::FTilt(v) |
+            Command::Sadl(v) | Command::Blade(v) |
+            Command::StereoPan(v) | Command::StereoTilt(v) => vec![v.to_string()],
+            Command::LrRpm(l, r) => vec![l.to_string(), r.to_string()],
+            Command::TimedDrive(l, r, ttl_ms) => vec![l.to_string(), r.to_string(), ttl_ms.to_string()],
+            Command::Ping(seq, sent_ms) => vec![seq.to_string(), sent_ms.to_string()],
+            Command::Brake | Command::StereoSnapshot | Command::StereoPanorama => vec![],
+            Command::Command(ref command, motor_speed) => vec![command.clone(), motor_speed.to_string()],
+        }
+    }
+
+    /// Encode the command into the bytes that go on the wire, including the null terminator.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = String::new();
+        packet.push(self.opcode());
+
+        let args = self.args();
+        if args.is_empty() {
+            packet.push('|');
+        }
+        for arg in args {
+            packet.push_str(arg.as_str());
+            packet.push('|');
+        }
+
+        let mut data = packet.into_bytes();
+        data.push(0); // Null terminate all of our packets
+        data
+    }
+
+    /// Decode a single command packet. The trailing `|` and null terminator are optional so bare
+    /// packets like `G` are still understood.
+    pub fn decode(data: &[u8]) -> Result<Command, DecodeError> {
+        let data =
+            match data.iter().position(|b| *b == 0) {
+                Some(end) => &data[..end],
+                None => data,
+            };
+        let packet = try!(str::from_utf8(data).map_err(|_| DecodeError::InvalidUtf8));
+
+        let mut chars = packet.chars();
+        let opcode = try!(chars.next().ok_or(DecodeError::Empty));
+        let body = chars.as_str();
+        let body = if body.ends_with('|') { &body[..body.len() - 1] } else { body };
+        let args: Vec<&str> = if body.is_empty() { vec![] } else { body.split('|').collect() };
+
+        match opcode {
+            'A' => Ok(Command::LRpm(try!(int_arg(opcode, &args, 0)))),
+            'B' => Ok(Command::RRpm(try!(int_arg(opcode, &args, 0)))),
+            'C' => Ok(Command::FPan(try!(int_arg(opcode, &args, 0)))),
+            'D' => Ok(Command::FTilt(try!(int_arg(opcode, &args, 0)))),
+            'E' => Ok(Command::Sadl(try!(int_arg(opcode, &args, 0)))),
+            'F' => Ok(Command::Blade(try!(int_arg(opcode, &args, 0)))),
+            'G' => Ok(Command::Brake),
+            'H' => Ok(Command::LrRpm(try!(int_arg(opcode, &args, 0)), try!(int_arg(opcode, &args, 1)))),
+            'I' => Ok(Command::StereoPan(try!(int_arg(opcode, &args, 0)))),
+            'J' => Ok(Command::StereoTilt(try!(int_arg(opcode, &args, 0)))),
+            'K' => Ok(Command::StereoSnapshot),
+            'L' => Ok(Command::StereoPanorama),
+            'M' => {
+                let ttl_ms = try!(arg(opcode, &args, 2));
+                Ok(Command::TimedDrive(try!(int_arg(opcode, &args, 0)), try!(int_arg(opcode, &args, 1)),
+                                       try!(ttl_ms.parse().map_err(|_| DecodeError::BadArg(opcode, 2)))))
+            },
+            'P' => {
+                let seq = try!(arg(opcode, &args, 0));
+                let sent_ms = try!(arg(opcode, &args, 1));
+                Ok(Command::Ping(try!(seq.parse().map_err(|_| DecodeError::BadArg(opcode, 0))),
+                                 try!(sent_ms.parse().map_err(|_| DecodeError::BadArg(opcode, 1)))))
+            },
+            'Z' => {
+                let command = try!(arg(opcode, &args, 0)).to_string();
+                let motor_speed = try!(arg(opcode, &args, 1));
+                let motor_speed = try!(motor_speed.parse().map_err(|_| DecodeError::BadArg(opcode, 1)));
+                Ok(Command::Command(command, motor_speed))
+            },
+            _ => Err(DecodeError::UnknownOpcode(opcode)),
+        }
+    }
+}
+
+/// Encode a command with a sequence number so the rover acknowledges it.
+pub fn encode_sequenced(seq: u32, command: &Command) -> Vec<u8> {
+    let mut data = format!("R{}|", seq).into_bytes();
+    data.extend(command.encode());
+    data
+}
+
+/// Decode a command that may be prefixed with a sequence number.
+pub fn decode_sequenced(data: &[u8]) -> Result<(Option<u32>, Command), DecodeError> {
+    if data.first() != Some(&b'R') {
+        return Command::decode(data).map(|command| (None, command));
+    }
+
+    let end = try!(data.iter().position(|b| *b == b'|').ok_or(DecodeError::MissingArg('R', 0)));
+    let seq = try!(str::from_utf8(&data[1..end]).map_err(|_| DecodeError::InvalidUtf8));
+    let seq = try!(seq.parse().map_err(|_| DecodeError::BadArg('R', 0)));
+    let command = try!(Command::decode(&data[end + 1..]));
+    Ok((Some(seq), command))
+}
+
+/// Append a checksum to a command or text telemetry packet, ahead of any null terminator.
+pub fn add_checksum(packet: &[u8]) -> Vec<u8> {
+    let (body, terminated) =
+        match packet.iter().position(|b| *b == 0) {
+            Some(end) => (&packet[..end], true),
+            None => (packet, false),
+        };
+    let mut data = body.to_vec();
+    data.extend_from_slice(format!("*{:08X}", crc32(body)).as_bytes());
+    if terminated {
+        data.push(0);
+    }
+    data
+}
+
+/// Verify and remove the checksum added by `add_checksum`. Checksums are optional so a packet
+/// without one is returned unchanged.
+pub fn strip_checksum(packet: &[u8]) -> Result<&[u8], ChecksumError> {
+    let body =
+        match packet.iter().position(|b| *b == 0) {
+            Some(end) => &packet[..end],
+            None => packet,
+        };
+    if body.len() < 9 || body[body.len() - 9] != b'*' {
+        return Ok(packet);
+    }
+    let expected =
+        match str::from_utf8(&body[body.len() - 8..]).ok().and_then(|hex| u32::from_str_radix(hex, 16).ok()) {
+            Some(crc) => crc,
+            None => return Ok(packet),
+        };
+
+    let body = &body[..body.len() - 9];
+    let actual = crc32(body);
+    if actual == expected {
+        Ok(body)
+    } else {
+        Err(ChecksumError { expected: expected, actual: actual })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChecksumError {
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "checksum mismatch ({:08X} != {:08X})", self.actual, self.expected)
+    }
+}
+
+fn arg<'a>(opcode: char, args: &[&'a str], index: usize) -> Result<&'a str, DecodeError> {
+    args.get(index).map(|a| *a).ok_or(DecodeError::MissingArg(opcode, index))
+}
+
+fn int_arg(opcode: char, args: &[&str], index: usize) -> Result<i32, DecodeError> {
+    let a = try!(arg(opcode, args, index));
+    a.parse().map_err(|_| DecodeError::BadArg(opcode, index))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    Empty,
+    InvalidUtf8,
+    UnknownOpcode(char),
+    MissingArg(char, usize),
+    BadArg(char, usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Empty => write!(f, "empty command packet"),
+            DecodeError::InvalidUtf8 => write!(f, "command packet is not valid UTF-8"),
+            DecodeError::UnknownOpcode(op) => write!(f, "unknown command opcode '{}'", op),
+            DecodeError::MissingArg(op, i) => write!(f, "command '{}' is missing argument {}", op, i),
+            DecodeError::BadArg(op, i) => write!(f, "command '{}' has malformed argument {}", op, i),
+        }
+    }
+}
+
+// Downlink telemetry protocol
+//
+// The rover sends `|` separated messages, each made of `:` separated fields starting with the
+// message ID, e.g. `VOLT:47.9:0:12.1|L_MOTOR_TEMP:35.2|`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TelemetryMsg {
+    Volt { h_48_v: f64, p_12_e_v: f64 },
+    Amp { l_motor: f64, r_motor: f64, p_12_e: f64 },
+    Gps {
+        latitude: Option<f64>,
+        longitude: Option<f64>,
+        speed: Option<f64>,
+        altitude: Option<f64>,
+        angle: Option<f64>,
+    },
+    Imu { accel: (f64, f64, f64), gyro: (f64, f64, f64), mag: (f64, f64, f64) },
+    LMotorTemp(f64),
+    RMotorTemp(f64),
+    AvionicsTemp(f64),
+    WeatherTemp(f64),
+    WeatherPressureAltitude { pressure: f64, altitude: f64 },
+    WindSpeed(f64),
+    Ack { opcode: char, seq: Option<u32> }, // The sequence number is only sent for sequenced commands
+    // Sequence number and ground station time from the ping, and the rover's time when it
+    // answered. Times are milliseconds since the unix epoch.
+    Pong { seq: u32, sent_ms: u64, rover_ms: u64 },
+    BadCommands(u64), // Total commands the rover has rejected because of a bad checksum
+}
+
+impl TelemetryMsg {
+    /// Parse a single `:` separated message.
+    pub fn parse(msg: &str) -> Result<TelemetryMsg, ParseError> {
+        let fields: Vec<&str> = msg.split(':').collect();
+        let id = fields[0];
+
+        match id {
+            "VOLT" => {
+                // Field 2 is unused by the ground station
+                Ok(TelemetryMsg::Volt {
+                    h_48_v: try!(float_field("VOLT", &fields, 1)),
+                    p_12_e_v: try!(float_field("VOLT", &fields, 3)),
+                })
+            },
+            "AMP" => {
+                Ok(TelemetryMsg::Amp {
+                    l_motor: try!(float_field("AMP", &fields, 1)),
+                    r_motor: try!(float_field("AMP", &fields, 2)),
+                    p_12_e: try!(float_field("AMP", &fields, 3)),
+                })
+            },
+            "GPS" => {
+                // The GPS sends empty fields until it has a fix
+                try!(field("GPS", &fields, 5));
+                Ok(TelemetryMsg::Gps {
+                    latitude: fields[1].parse().ok(),
+                    longitude: fields[2].parse().ok(),
+                    speed: fields[3].parse().ok(),
+                    altitude: fields[4].parse().ok(),
+                    angle: fields[5].parse().ok(),
+                })
+            },
+            "IMU" => {
+                Ok(TelemetryMsg::Imu {
+                    accel: (try!(float_field("IMU", &fields, 1)),
+                            try!(float_field("IMU", &fields, 2)),
+                            try!(float_field("IMU", &fields, 3))),
+                    gyro: (try!(float_field("IMU", &fields, 4)),
+                           try!(float_field("IMU", &fields, 5)),
+                           try!(float_field("IMU", &fields, 6))),
+                    mag: (try!(float_field("IMU", &fields, 7)),
+                          try!(float_field("IMU", &fields, 8)),
+                          try!(float_field("IMU", &fields, 9))),
+                })
+            },
+            "L_MOTOR_TEMP" => Ok(TelemetryMsg::LMotorTemp(try!(float_field("L_MOTOR_TEMP", &fields, 1)))),
+            "R_MOTOR_TEMP" => Ok(TelemetryMsg::RMotorTemp(try!(float_field("R_MOTOR_TEMP", &fields, 1)))),
+            "AVIONICS_TEMP" => Ok(TelemetryMsg::AvionicsTemp(try!(float_field("AVIONICS_TEMP", &fields, 1)))),
+            "W_TEMP" => Ok(TelemetryMsg::WeatherTemp(try!(float_field("W_TEMP", &fields, 1)))),
+            "W_PR_ALT" => {
+                Ok(TelemetryMsg::WeatherPressureAltitude {
+                    pressure: try!(float_field("W_PR_ALT", &fields, 1)),
+                    altitude: try!(float_field("W_PR_ALT", &fields, 2)),
+                })
+            },
+            "W_WND_SPD" => Ok(TelemetryMsg::WindSpeed(try!(float_field("W_WND_SPD", &fields, 1)))),
+            "ACK" => {
+                let opcode_field = try!(field("ACK", &fields, 1));
+                let mut chars = opcode_field.chars();
+                let opcode =
+                    match (chars.next(), chars.next()) {
+                        (Some(opcode), None) => opcode,
+                        _ => return Err(ParseError::BadField("ACK", 1, opcode_field.to_string())),
+                    };
+                let seq =
+                    match fields.get(2) {
+                        Some(seq) => Some(try!(seq.parse().map_err(|_| ParseError::BadField("ACK", 2, seq.to_string())))),
+                        None => None,
+                    };
+                Ok(TelemetryMsg::Ack { opcode: opcode, seq: seq })
+            },
+            "PONG" => {
+                let seq = try!(field("PONG", &fields, 1));
+                let sent_ms = try!(field("PONG", &fields, 2));
+                let rover_ms = try!(field("PONG", &fields, 3));
+                Ok(TelemetryMsg::Pong {
+                    seq: try!(seq.parse().map_err(|_| ParseError::BadField("PONG", 1, seq.to_string()))),
+                    sent_ms: try!(sent_ms.parse().map_err(|_| ParseError::BadField("PONG", 2, sent_ms.to_string()))),
+                    rover_ms: try!(rover_ms.parse().map_err(|_| ParseError::BadField("PONG", 3, rover_ms.to_string()))),
+                })
+            },
+            "BAD_CMD" => {
+                let count = try!(field("BAD_CMD", &fields, 1));
+                Ok(TelemetryMsg::BadCommands(try!(count.parse().map_err(|_| ParseError::BadField("BAD_CMD", 1, count.to_string())))))
+            },
+            _ => Err(ParseError::UnknownId(id.to_string())),
+        }
+    }
+
+    /// Encode the message in the format the rover sends, without the trailing `|`.
+    pub fn encode(&self) -> String {
+        fn opt(value: Option<f64>) -> String {
+            value.map(|v| v.to_string()).unwrap_or(String::new())
+        }
+
+        match *self {
+            TelemetryMsg::Volt { h_48_v, p_12_e_v } => format!("VOLT:{}:0:{}", h_48_v, p_12_e_v),
+            TelemetryMsg::Amp { l_motor, r_motor, p_12_e } => format!("AMP:{}:{}:{}", l_motor, r_motor, p_12_e),
+            TelemetryMsg::Gps { latitude, longitude, speed, altitude, angle } => {
+                format!("GPS:{}:{}:{}:{}:{}", opt(latitude), opt(longitude), opt(speed),
+                        opt(altitude), opt(angle))
+            },
+            TelemetryMsg::Imu { accel, gyro, mag } => {
+                format!("IMU:{}:{}:{}:{}:{}:{}:{}:{}:{}", accel.0, accel.1, accel.2,
+                        gyro.0, gyro.1, gyro.2, mag.0, mag.1, mag.2)
+            },
+            TelemetryMsg::LMotorTemp(temp) => format!("L_MOTOR_TEMP:{}", temp),
+            TelemetryMsg::RMotorTemp(temp) => format!("R_MOTOR_TEMP:{}", temp),
+            TelemetryMsg::AvionicsTemp(temp) => format!("AVIONICS_TEMP:{}", temp),
+            TelemetryMsg::WeatherTemp(temp) => format!("W_TEMP:{}", temp),
+            TelemetryMsg::WeatherPressureAltitude { pressure, altitude } => format!("W_PR_ALT:{}:{}", pressure, altitude),
+            TelemetryMsg::WindSpeed(speed) => format!("W_WND_SPD:{}", speed),
+            TelemetryMsg::Ack { opcode, seq: None } => format!("ACK:{}", opcode),
+            TelemetryMsg::Ack { opcode, seq: Some(seq) } => format!("ACK:{}:{}", opcode, seq),
+            TelemetryMsg::Pong { seq, sent_ms, rover_ms } => format!("PONG:{}:{}:{}", seq, sent_ms, rover_ms),
+            TelemetryMsg::BadCommands(count) => format!("BAD_CMD:{}", count),
+        }
+    }
+}
+
+/// Build a telemetry datagram out of several messages.
+pub fn encode_telemetry(msgs: &[TelemetryMsg]) -> String {
+    let mut packet = String::new();
+    for msg in msgs {
+        packet.push_str(msg.encode().as_str());
+        packet.push('|');
+    }
+    packet
+}
+
+/// Parse every message in a telemetry datagram. Empty messages (such as the one after the final
+/// `|`) are skipped.
+pub fn parse_telemetry(packet: &str) -> Vec<Result<TelemetryMsg, ParseError>> {
+    packet.split('|')
+          .map(|msg| msg.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+          .filter(|msg| !msg.is_empty())
+          .map(TelemetryMsg::parse)
+          .collect()
+}
+
+fn field<'a>(id: &'static str, fields: &[&'a str], index: usize) -> Result<&'a str, ParseError> {
+    fields.get(index).map(|f| *f).ok_or(ParseError::MissingField(id, index))
+}
+
+fn float_field(id: &'static str, fields: &[&str], index: usize) -> Result<f64, ParseError> {
+    let f = try!(field(id, fields, index));
+    f.parse().map_err(|_| ParseError::BadField(id, index, f.to_string()))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    UnknownId(String),
+    MissingField(&'static str, usize),
+    BadField(&'static str, usize, String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnknownId(ref id) => write!(f, "unknown packet ID '{}'", id),
+            ParseError::MissingField(id, i) => write!(f, "{} is missing field {}", id, i),
+            ParseError::BadField(id, i, ref value) => write!(f, "{} field {} is malformed: '{}'", id, i, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(command: Command) {
+        let data = command.encode();
+        assert_eq!(data.last(), Some(&0));
+        assert_eq!(Command::decode(&data), Ok(command));
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        round_trip(Command::LRpm(-100));
+        round_trip(Command::RRpm(42));
+        round_trip(Command::FPan(90));
+        round_trip(Command::FTilt(130));
+        round_trip(Command::Sadl(-100));
+        round_trip(Command::Blade(100));
+        round_trip(Command::Brake);
+        round_trip(Command::LrRpm(-50, 50));
+        round_trip(Command::StereoPan(0));
+        round_trip(Command::StereoTilt(180));
+        round_trip(Command::StereoSnapshot);
+        round_trip(Command::StereoPanorama);
+        round_trip(Command::TimedDrive(100, -100, 600));
+        round_trip(Command::Command("FORWARD 10".to_string(), 0.5));
+        round_trip(Command::Ping(7, 1476000000123));
+    }
+
+    #[test]
+    fn encodes_legacy_formats() {
+        assert_eq!(Command::LRpm(10).encode(), b"A10|\0".to_vec());
+        assert_eq!(Command::LrRpm(10, -10).encode(), b"H10|-10|\0".to_vec());
+        assert_eq!(Command::Blade(-100).encode(), b"F-100|\0".to_vec());
+        assert_eq!(Command::StereoSnapshot.encode(), b"K|\0".to_vec());
+    }
+
+    #[test]
+    fn decodes_bare_packets() {
+        assert_eq!(Command::decode(b"G"), Ok(Command::Brake));
+        assert_eq!(Command::decode(b"F100"), Ok(Command::Blade(100)));
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        assert_eq!(Command::decode(b""), Err(DecodeError::Empty));
+        assert_eq!(Command::decode(b"Q1|"), Err(DecodeError::UnknownOpcode('Q')));
+        assert_eq!(Command::decode(b"H10|"), Err(DecodeError::MissingArg('H', 1)));
+        assert_eq!(Command::decode(b"M10|10|-5|"), Err(DecodeError::BadArg('M', 2)));
+        assert_eq!(Command::decode(b"Ax|"), Err(DecodeError::BadArg('A', 0)));
+    }
+
+    #[test]
+    fn sequenced_commands_round_trip() {
+        let data = encode_sequenced(12, &Command::Brake);
+        assert_eq!(data, b"R12|G|\0".to_vec());
+        assert_eq!(decode_sequenced(&data), Ok((Some(12), Command::Brake)));
+        assert_eq!(decode_sequenced(b"E-100|\0"), Ok((None, Command::Sadl(-100))));
+        assert_eq!(decode_sequenced(b"Rx|G|"), Err(DecodeError::BadArg('R', 0)));
+        assert_eq!(decode_sequenced(b"R12"), Err(DecodeError::MissingArg('R', 0)));
+    }
+
+    #[test]
+    fn parses_telemetry_packet() {
+        let msgs = parse_telemetry("VOLT:47.5:0:12.25|L_MOTOR_TEMP:35|W_PR_ALT:1013.2:120|\0");
+        assert_eq!(msgs, vec![Ok(TelemetryMsg::Volt { h_48_v: 47.5, p_12_e_v: 12.25 }),
+                              Ok(TelemetryMsg::LMotorTemp(35.0)),
+                              Ok(TelemetryMsg::WeatherPressureAltitude { pressure: 1013.2, altitude: 120.0 })]);
+    }
+
+    #[test]
+    fn checksums_are_verified() {
+        let packet = add_checksum(&Command::LRpm(100).encode());
+        assert_eq!(packet, b"A100|*49B8F754\0".to_vec());
+        assert_eq!(strip_checksum(&packet), Ok(&b"A100|"[..]));
+        assert_eq!(Command::decode(strip_checksum(&packet).unwrap()), Ok(Command::LRpm(100)));
+
+        // A corrupted digit is caught
+        let mut corrupt = packet.clone();
+        corrupt[1] = b'9';
+        assert!(strip_checksum(&corrupt).is_err());
+
+        // Packets without a checksum pass through
+        assert_eq!(strip_checksum(b"A100|\0"), Ok(&b"A100|\0"[..]));
+        let sequenced = add_checksum(&encode_sequenced(7, &Command::Brake));
+        assert_eq!(decode_sequenced(strip_checksum(&sequenced).unwrap()), Ok((Some(7), Command::Brake)));
+    }
+
+    #[test]
+    fn telemetry_round_trip() {
+        let msgs = vec![TelemetryMsg::Volt { h_48_v: 47.5, p_12_e_v: 12.25 },
+                        TelemetryMsg::Amp { l_motor: 1.0, r_motor: 2.0, p_12_e: 0.5 },
+                        TelemetryMsg::Gps { latitude: Some(19.7), longitude: Some(-155.1), speed: None,
+                                            altitude: Some(10.0), angle: Some(90.0) },
+                        TelemetryMsg::Imu { accel: (-1.0, 0.0, 0.0), gyro: (0.0, 0.0, 0.1),
+                                            mag: (0.0, 1.0, 0.0) },
+                        TelemetryMsg::WindSpeed(3.5),
+                        TelemetryMsg::Ack { opcode: 'G', seq: None },
+                        TelemetryMsg::Ack { opcode: 'E', seq: Some(12) },
+                        TelemetryMsg::Pong { seq: 3, sent_ms: 1476000000123, rover_ms: 1476000000180 },
+                        TelemetryMsg::BadCommands(2)];
+        let parsed: Vec<_> = parse_telemetry(encode_telemetry(&msgs).as_str()).into_iter()
+                                                                              .map(|m| m.unwrap())
+                                                                              .collect();
+        assert_eq!(parsed, msgs);
+    }
+
+    #[test]
+    fn parses_gps_without_fix() {
+        assert_eq!(TelemetryMsg::parse("GPS:::::"),
+                   Ok(TelemetryMsg::Gps { latitude: None, longitude: None, speed: None,
+                                          altitude: None, angle: None }));
+    }
+
+    #[test]
+    fn reports_truncated_telemetry() {
+        assert_eq!(TelemetryMsg::parse("IMU:0.1:0.2:0.3:0:0:0:1"), Err(ParseError::MissingField("IMU", 8)));
+        assert_eq!(TelemetryMsg::parse("W_TEMP:2"), Ok(TelemetryMsg::WeatherTemp(2.0)));
+        assert_eq!(TelemetryMsg::parse("W_TEMP:2."), Ok(TelemetryMsg::WeatherTemp(2.0)));
+        assert_eq!(TelemetryMsg::parse("L_MOTOR_TEMP:3a"),
+                   Err(ParseError::BadField("L_MOTOR_TEMP", 1, "3a".to_string())));
+        assert_eq!(TelemetryMsg::parse("FOO:1"), Err(ParseError::UnknownId("FOO".to_string())));
+    }
+}
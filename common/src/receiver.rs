@@ -0,0 +1,296 @@
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use time;
+
+use capture::{Capture, Direction};
+use frame::{decode_datagram, FrameError, MAX_DATAGRAM};
+
+const READ_TIMEOUT_MS: u64 = 200; // How often the receive thread checks for shutdown
+const ERROR_BACKOFF_MS: u64 = 500; // Pause after a socket error so we don't spin on it
+const REBIND_AFTER_MS: i64 = 5000; // Rebind the socket when errors persist for this long
+
+pub enum NetEvent {
+    Packet(String), // Telemetry text, from a frame or a legacy datagram
+    Dropped(FrameError), // A datagram that arrived but couldn't be used
+    Error(String),
+}
+
+/// Receives datagrams from the rover on a background thread. Socket errors are reported as
+/// events instead of killing the thread, and the thread stops when the receiver is dropped.
+pub struct PacketReceiver {
+    port: u16,
+    capture: Option<Capture>, // Every datagram received is recorded here
+    events: Receiver<NetEvent>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+
+    error_since: Option<time::Tm>, // Start of the current run of errors
+    last_rebind: time::Tm,
+}
+
+impl PacketReceiver {
+    /// Bind the local UDP port and start receiving on it. Returns the socket to send with.
+    pub fn bind(port: u16, capture: Option<Capture>) -> io::Result<(UdpSocket, PacketReceiver)> {
+        let socket = try!(UdpSocket::bind(("0.0.0.0", port)));
+        let (events, shutdown, thread) = try!(spawn_thread(&socket, capture.clone()));
+        let receiver = PacketReceiver {
+            port: port,
+            capture: capture,
+            events: events,
+            shutdown: shutdown,
+            thread: Some(thread),
+
+            error_since: None,
+            last_rebind: time::now(),
+        };
+        Ok((socket, receiver))
+    }
+
+    /// The next event from the receive thread, if there is one.
+    pub fn try_next(&mut self) -> Option<NetEvent> {
+        match self.events.try_recv() {
+            Ok(NetEvent::Packet(packet)) => {
+                self.error_since = None;
+                Some(NetEvent::Packet(packet))
+            },
+            Ok(NetEvent::Dropped(e)) => Some(NetEvent::Dropped(e)),
+            Ok(NetEvent::Error(e)) => {
+                if self.error_since.is_none() {
+                    self.error_since = Some(time::now());
+                }
+                Some(NetEvent::Error(e))
+            },
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                // The thread only exits on its own if it can't continue
+                if self.error_since.is_none() {
+                    self.error_since = Some(time::now());
+                }
+                None
+            },
+        }
+    }
+
+    /// Whether socket errors have persisted long enough that the socket should be rebound.
+    pub fn needs_rebind(&self) -> bool {
+        let now = time::now();
+        match self.error_since {
+            Some(since) => (now - since).num_milliseconds() >= REBIND_AFTER_MS &&
+                           (now - self.last_rebind).num_milliseconds() >= REBIND_AFTER_MS,
+            None => false,
+        }
+    }
+
+    /// Close `socket` and bind a new one on the same port, restarting the receive thread.
+    pub fn rebind(&mut self, socket: &mut UdpSocket) -> io::Result<()> {
+        self.last_rebind = time::now();
+        self.stop_thread();
+
+        // Both copies of the old socket have to be closed before the port can be bound again, so
+        // park `socket` on a throwaway port in the meantime.
+        *socket = try!(UdpSocket::bind(("0.0.0.0", 0)));
+        let new_socket = try!(UdpSocket::bind(("0.0.0.0", self.port)));
+        let (events, shutdown, thread) = try!(spawn_thread(&new_socket, self.capture.clone()));
+        *socket = new_socket;
+
+        self.events = events;
+        self.shutdown = shutdown;
+        self.thread = Some(thread);
+        self.error_since = None;
+        Ok(())
+    }
+
+    /// Stop the receive thread. Also done when the receiver is dropped.
+    pub fn shutdown(&mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PacketReceiver {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// The event for a datagram received from the rover.
+pub fn datagram_event(data: &[u8]) -> NetEvent {
+    match decode_datagram(data) {
+        Ok(datagram) => {
+            match datagram.text() {
+                Ok(text) => NetEvent::Packet(text.to_string()),
+                Err(e) => NetEvent::Dropped(e),
+            }
+        },
+        Err(e) => NetEvent::Dropped(e),
+    }
+}
+
+fn spawn_thread(socket: &UdpSocket, capture: Option<Capture>) -> io::Result<(Receiver<NetEvent>, Arc<AtomicBool>, JoinHandle<()>)> {
+    let socket = try!(socket.try_clone());
+    try!(socket.set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS))));
+
+    let (packet_t, packet_r) = channel();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let thread_shutdown = shutdown.clone();
+
+    let thread = try!(thread::Builder::new()
+        .name("packet_in".to_string())
+        .spawn(move || {
+            // One byte more than we accept, so oversized datagrams show up instead of being cut short
+            let mut buf = vec![0u8; MAX_DATAGRAM + 1];
+            while !thread_shutdown.load(Ordering::SeqCst) {
+                let event =
+                    match socket.recv_from(&mut buf) {
+                        Ok((bytes_read, _)) => {
+                            if let Some(ref capture) = capture {
+                                capture.record(Direction::In, &buf[0..bytes_read]);
+                            }
+                            datagram_event(&buf[0..bytes_read])
+                        },
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                                      e.kind() == io::ErrorKind::TimedOut => continue,
+                        Err(e) => {
+                            thread::sleep(Duration::from_millis(ERROR_BACKOFF_MS));
+                            NetEvent::Error(format!("Receive failed: {}", e))
+                        },
+                    };
+                if packet_t.send(event).is_err() {
+                    break; // The UI has gone away
+                }
+            }
+        }));
+
+    Ok((packet_r, shutdown, thread))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capture::{Capture, CaptureReader, Direction, Record};
+    use frame::{FrameError, FrameWriter, MAX_DATAGRAM};
+    use protocol::TelemetryMsg;
+    use std::env;
+    use std::fs;
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    fn next_packet(receiver: &mut PacketReceiver) -> String {
+        for _ in 0..100 {
+            match receiver.try_next() {
+                Some(NetEvent::Packet(packet)) => return packet,
+                Some(NetEvent::Dropped(e)) => panic!("{}", e),
+                Some(NetEvent::Error(e)) => panic!("{}", e),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        panic!("No packet received");
+    }
+
+    fn next_packet_after_drops(receiver: &mut PacketReceiver) -> String {
+        for _ in 0..100 {
+            match receiver.try_next() {
+                Some(NetEvent::Packet(packet)) => return packet,
+                Some(NetEvent::Dropped(_)) => {},
+                Some(NetEvent::Error(e)) => panic!("{}", e),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        panic!("No packet received");
+    }
+
+    #[test]
+    fn receives_packets() {
+        let (socket, mut receiver) = PacketReceiver::bind(0, None).unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(b"W_TEMP:20|", ("127.0.0.1", port)).unwrap();
+        let frame = FrameWriter::new().telemetry(&[TelemetryMsg::WeatherTemp(21.0)]).remove(0);
+        sender.send_to(&frame, ("127.0.0.1", port)).unwrap();
+
+        assert_eq!(next_packet(&mut receiver), "W_TEMP:20|");
+        assert_eq!(next_packet(&mut receiver), "W_TEMP:21|");
+
+        // Corrupt and oversized datagrams are reported, not passed on
+        let mut corrupt = frame.clone();
+        *corrupt.last_mut().unwrap() = b'#';
+        sender.send_to(&corrupt, ("127.0.0.1", port)).unwrap();
+        sender.send_to(&vec![b'W'; MAX_DATAGRAM + 100], ("127.0.0.1", port)).unwrap();
+        sender.send_to(b"W_TEMP:22|", ("127.0.0.1", port)).unwrap();
+
+        let mut dropped = vec![];
+        while dropped.len() < 2 {
+            match receiver.try_next() {
+                Some(NetEvent::Dropped(e)) => dropped.push(e),
+                Some(NetEvent::Packet(packet)) => panic!("Unexpected packet {}", packet),
+                Some(NetEvent::Error(e)) => panic!("{}", e),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        assert_eq!(next_packet(&mut receiver), "W_TEMP:22|");
+        assert_eq!(dropped.len(), 2);
+        match dropped[0] {
+            FrameError::BadCrc { .. } => {},
+            ref e => panic!("{:?}", e),
+        }
+        assert_eq!(dropped[1], FrameError::Oversized(MAX_DATAGRAM + 1));
+        assert!(!receiver.needs_rebind());
+    }
+
+    #[test]
+    fn rebinds_on_the_same_port() {
+        let (mut socket, mut receiver) = PacketReceiver::bind(0, None).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        receiver.port = port;
+
+        receiver.rebind(&mut socket).unwrap();
+        assert_eq!(socket.local_addr().unwrap().port(), port);
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(b"W_TEMP:22|", ("127.0.0.1", port)).unwrap();
+        assert_eq!(next_packet(&mut receiver), "W_TEMP:22|");
+    }
+
+    #[test]
+    fn captures_received_datagrams() {
+        let path = env::temp_dir().join(format!("receiver_{}.cap", ::link::timestamp_ms()));
+        let capture = Capture::create(&path).unwrap();
+        let (socket, mut receiver) = PacketReceiver::bind(0, Some(capture.clone())).unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(b"\xff\xfe", ("127.0.0.1", port)).unwrap(); // Captured even though it's dropped
+        sender.send_to(b"W_TEMP:20|", ("127.0.0.1", port)).unwrap();
+        assert_eq!(next_packet_after_drops(&mut receiver), "W_TEMP:20|");
+        receiver.shutdown();
+        capture.flush().unwrap();
+
+        let records: Vec<Record> = CaptureReader::open(&path).unwrap().map(|r| r.unwrap()).collect();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, Direction::In);
+        assert_eq!(records[0].data, b"\xff\xfe".to_vec());
+        assert_eq!(records[1].data, b"W_TEMP:20|".to_vec());
+    }
+
+    #[test]
+    fn shuts_down() {
+        let (_socket, mut receiver) = PacketReceiver::bind(0, None).unwrap();
+        receiver.shutdown();
+        assert!(receiver.thread.is_none());
+    }
+}
@@ -0,0 +1,230 @@
+use std::io;
+use std::path::Path;
+
+use capture::{CaptureReader, Direction};
+
+pub const SPEEDS: [f64; 5] = [0.5, 1.0, 2.0, 5.0, 10.0];
+
+/// Packets to hand to the UI after moving through a replay, with the time they were captured in
+/// microseconds since the unix epoch. When `reset` is set the replay went backwards, so the UI has
+/// to start over from a clean state before handling `packets`.
+#[derive(Debug, PartialEq)]
+pub struct ReplayUpdate {
+    pub reset: bool,
+    pub packets: Vec<(u64, Vec<u8>)>,
+}
+
+impl ReplayUpdate {
+    fn packets(packets: Vec<(u64, Vec<u8>)>) -> ReplayUpdate {
+        ReplayUpdate { reset: false, packets: packets }
+    }
+}
+
+/// Plays back the datagrams received during a run, from a capture file.
+pub struct Replay {
+    packets: Vec<(u64, Vec<u8>)>, // Received datagrams with their time in us since the first one
+    start_us: u64, // Capture time of the first packet
+    next: usize, // Index of the next packet to play
+    time_us: u64, // Current position
+    playing: bool,
+    speed: usize, // Index into SPEEDS
+    restarted: bool, // Started over from the end, so the next update has to reset the UI
+}
+
+impl Replay {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        let mut packets = vec![];
+        for record in try!(CaptureReader::open(path)) {
+            let record = try!(record);
+            if record.direction == Direction::In {
+                packets.push((record.timestamp_us, record.data));
+            }
+        }
+        Ok(Replay::new(packets))
+    }
+
+    /// `packets` are the received datagrams with their capture timestamps.
+    pub fn new(mut packets: Vec<(u64, Vec<u8>)>) -> Replay {
+        let start = packets.first().map(|p| p.0).unwrap_or(0);
+        for packet in packets.iter_mut() {
+            packet.0 = packet.0.saturating_sub(start);
+        }
+
+        Replay {
+            packets: packets,
+            start_us: start,
+            next: 0,
+            time_us: 0,
+            playing: true,
+            speed: 1,
+            restarted: false,
+        }
+    }
+
+    /// Advance by `dt` seconds of wall clock time while playing.
+    pub fn update(&mut self, dt: f64) -> ReplayUpdate {
+        if !self.playing {
+            return ReplayUpdate::packets(vec![]);
+        }
+        let time_us = self.time_us + (dt * self.speed() * 1_000_000.0) as u64;
+        let update = self.seek_us(time_us);
+        if self.time_us >= self.duration_us() {
+            self.playing = false;
+        }
+        update
+    }
+
+    /// Pause and play just the next packet.
+    pub fn step(&mut self) -> ReplayUpdate {
+        self.playing = false;
+        match self.packets.get(self.next).map(|p| p.0) {
+            Some(time_us) => self.seek_us(time_us),
+            None => ReplayUpdate::packets(vec![]),
+        }
+    }
+
+    /// Jump to `fraction` of the way through the replay.
+    pub fn seek(&mut self, fraction: f64) -> ReplayUpdate {
+        let fraction = fraction.max(0.0).min(1.0);
+        let time_us = (self.duration_us() as f64 * fraction) as u64;
+        self.seek_us(time_us)
+    }
+
+    fn seek_us(&mut self, time_us: u64) -> ReplayUpdate {
+        let time_us = time_us.min(self.duration_us());
+        let reset = time_us < self.time_us || self.restarted;
+        self.restarted = false;
+        if reset {
+            self.next = 0;
+        }
+        self.time_us = time_us;
+
+        let mut packets = vec![];
+        while self.next < self.packets.len() && self.packets[self.next].0 <= time_us {
+            let (time_us, ref data) = self.packets[self.next];
+            packets.push((self.start_us + time_us, data.clone()));
+            self.next += 1;
+        }
+        ReplayUpdate { reset: reset, packets: packets }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn toggle_pause(&mut self) {
+        // Playing again from the end starts over
+        if !self.playing && self.time_us >= self.duration_us() {
+            self.time_us = 0;
+            self.next = 0;
+            self.restarted = true;
+        }
+        self.playing = !self.playing;
+    }
+
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    /// Switch to the next playback speed, wrapping around to the slowest.
+    pub fn cycle_speed(&mut self) {
+        self.speed = (self.speed + 1) % SPEEDS.len();
+    }
+
+    pub fn position(&self) -> f64 {
+        if self.duration_us() == 0 {
+            1.0
+        } else {
+            self.time_us as f64 / self.duration_us() as f64
+        }
+    }
+
+    pub fn duration_us(&self) -> u64 {
+        self.packets.last().map(|p| p.0).unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    pub fn text(&self) -> String {
+        fn hms(us: u64) -> String {
+            let s = us / 1_000_000;
+            format!("{}:{:02}:{:02}", s / 3600, (s / 60) % 60, s % 60)
+        }
+        format!("Replay {} / {}  x{}{}", hms(self.time_us), hms(self.duration_us()), self.speed(),
+                if self.playing { "" } else { "  (paused)" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(update: ReplayUpdate) -> Vec<Vec<u8>> {
+        update.packets.into_iter().map(|packet| packet.1).collect()
+    }
+
+    fn replay() -> Replay {
+        Replay::new(vec![(5_000_000, b"a".to_vec()),
+                         (5_500_000, b"b".to_vec()),
+                         (7_000_000, b"c".to_vec())])
+    }
+
+    #[test]
+    fn plays_in_real_time() {
+        let mut replay = replay();
+        assert_eq!(replay.duration_us(), 2_000_000);
+        assert_eq!(data(replay.update(0.0)), vec![b"a".to_vec()]);
+        assert_eq!(data(replay.update(0.4)), Vec::<Vec<u8>>::new());
+        assert_eq!(data(replay.update(0.1)), vec![b"b".to_vec()]);
+        assert_eq!(data(replay.update(5.0)), vec![b"c".to_vec()]);
+        assert!(!replay.is_playing());
+        assert_eq!(replay.position(), 1.0);
+    }
+
+    #[test]
+    fn plays_faster() {
+        let mut replay = replay();
+        replay.cycle_speed();
+        replay.cycle_speed();
+        assert_eq!(replay.speed(), 5.0);
+        assert_eq!(replay.update(0.1).packets.len(), 2);
+    }
+
+    #[test]
+    fn steps_and_scrubs() {
+        let mut replay = replay();
+        assert_eq!(data(replay.step()), vec![b"a".to_vec()]);
+        assert!(!replay.is_playing());
+        assert_eq!(data(replay.step()), vec![b"b".to_vec()]);
+
+        let update = replay.seek(1.0);
+        assert!(!update.reset);
+        assert_eq!(update.packets, vec![(7_000_000, b"c".to_vec())]);
+
+        // Going back replays everything up to the new position
+        let update = replay.seek(0.5);
+        assert!(update.reset);
+        assert_eq!(data(update), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(data(replay.update(1.0)), Vec::<Vec<u8>>::new()); // Still paused
+    }
+
+    #[test]
+    fn playing_again_from_the_end_resets() {
+        let mut replay = replay();
+        replay.update(5.0);
+        assert!(!replay.is_playing());
+
+        replay.toggle_pause();
+        assert!(replay.is_playing());
+        let update = replay.update(0.0);
+        assert!(update.reset);
+        assert_eq!(data(update), vec![b"a".to_vec()]);
+        assert!(!replay.update(0.5).reset);
+    }
+}
@@ -0,0 +1,244 @@
+// Mission sessions
+//
+// Every run of a ground station binary is a session with its own folder in the mission data
+// directory, named after its ID:
+//
+//   mission_data/2016Jun02_11_04_05_navigation_3fa2/
+//
+// The ID starts with the local start time and the binary, and ends with a few random hex digits so
+// IDs stay unique across ground stations. The folder holds a `session.json` manifest describing
+// the session and every file written to it:
+//
+//   {
+//     "schema": "pisces-session",
+//     "version": 1,
+//     "session_id": "2016Jun02_11_04_05_navigation_3fa2",
+//     "binary": "navigation",
+//     "software_version": "0.1.0",
+//     "started_utc": "2016-06-02T18:04:05.123Z",
+//     "stopped_utc": null,
+//     "config": {...},
+//     "artifacts": [
+//       {"file": "navigation.cap", "kind": "capture", "source": "rover link", "started_utc": "2016-06-02T18:04:05.130Z"},
+//       ...
+//     ]
+//   }
+//
+// The manifest is rewritten whenever a file is added. `stopped_utc` stays null if the binary
+// didn't exit cleanly.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use time;
+
+use data_log::utc_text;
+use json;
+
+pub const MANIFEST_FILE: &'static str = "session.json";
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A file written during a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Artifact {
+    pub file: String, // Relative to the session folder
+    pub kind: String, // capture, log, video, snapshot, ...
+    pub source: String, // What it was recorded from, e.g. the camera or telemetry channel
+    pub started: time::Tm,
+}
+
+struct Manifest {
+    binary: String,
+    software_version: String,
+    config: String, // JSON object
+    started: time::Tm,
+    stopped: Option<time::Tm>,
+    artifacts: Vec<Artifact>,
+}
+
+/// A session folder and its manifest, shared by everything that writes to the folder.
+#[derive(Clone)]
+pub struct Session {
+    id: String,
+    folder: String,
+    manifest: Arc<Mutex<Manifest>>,
+}
+
+impl Session {
+    /// Create a session folder in `mission_data_dir` and write its manifest. `config` is the
+    /// binary's settings as a JSON object.
+    pub fn create(mission_data_dir: &str, binary: &str, software_version: &str,
+                  config: String) -> io::Result<Session> {
+        Session::create_at(time::now(), mission_data_dir, binary, software_version, config)
+    }
+
+    fn create_at(now: time::Tm, mission_data_dir: &str, binary: &str, software_version: &str,
+                 config: String) -> io::Result<Session> {
+        try!(fs::create_dir_all(mission_data_dir));
+
+        // Another session may have taken the ID, e.g. two ground stations sharing a folder
+        let mut suffix = ((now.tm_nsec as u32) ^ ((now.tm_nsec as u32) >> 16)) & 0xffff;
+        let id = loop {
+            let id = format!("{}_{}_{:04x}", now.strftime("%Y%b%d_%H_%M_%S").unwrap(), binary, suffix);
+            match fs::create_dir(Path::new(mission_data_dir).join(&id)) {
+                Ok(()) => break id,
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => suffix = (suffix + 1) & 0xffff,
+                Err(e) => return Err(e),
+            }
+        };
+
+        let session = Session {
+            folder: format!("{}/{}", mission_data_dir, id),
+            id: id,
+            manifest: Arc::new(Mutex::new(Manifest {
+                binary: binary.to_string(),
+                software_version: software_version.to_string(),
+                config: config,
+                started: now,
+                stopped: None,
+                artifacts: vec![],
+            })),
+        };
+        try!(session.write_manifest(&session.manifest.lock().unwrap()));
+        Ok(session)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn folder(&self) -> &str {
+        &self.folder
+    }
+
+    /// Path of a file in the session folder.
+    pub fn path(&self, file: &str) -> String {
+        format!("{}/{}", self.folder, file)
+    }
+
+    /// Add a file to the manifest, started now.
+    pub fn add_artifact(&self, file: &str, kind: &str, source: &str) {
+        self.add_artifact_at(time::now(), file, kind, source);
+    }
+
+    fn add_artifact_at(&self, now: time::Tm, file: &str, kind: &str, source: &str) {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.artifacts.push(Artifact {
+            file: file.to_string(),
+            kind: kind.to_string(),
+            source: source.to_string(),
+            started: now,
+        });
+        self.report(self.write_manifest(&manifest));
+    }
+
+    pub fn artifacts(&self) -> Vec<Artifact> {
+        self.manifest.lock().unwrap().artifacts.clone()
+    }
+
+    /// Record the stop time. Call when the binary exits.
+    pub fn finish(&self) {
+        self.finish_at(time::now());
+    }
+
+    fn finish_at(&self, now: time::Tm) {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.stopped = Some(now);
+        self.report(self.write_manifest(&manifest));
+    }
+
+    // Written to a temporary file first so a crash never leaves a half written manifest
+    fn write_manifest(&self, manifest: &Manifest) -> io::Result<()> {
+        let text = self.manifest_text(manifest);
+        let path = self.path(MANIFEST_FILE);
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(file.write_all(text.as_bytes()));
+            try!(file.sync_all());
+        }
+        fs::rename(&tmp_path, &path)
+    }
+
+    fn manifest_text(&self, manifest: &Manifest) -> String {
+        let mut text = format!("{{\n  \"schema\": \"pisces-session\",\n  \"version\": {},\n", SCHEMA_VERSION);
+        text.push_str(&format!("  \"session_id\": {},\n", json::string(&self.id)));
+        text.push_str(&format!("  \"binary\": {},\n", json::string(&manifest.binary)));
+        text.push_str(&format!("  \"software_version\": {},\n", json::string(&manifest.software_version)));
+        text.push_str(&format!("  \"started_utc\": {},\n", json::string(&utc_text(&manifest.started))));
+        let stopped = manifest.stopped.map(|stopped| utc_text(&stopped));
+        text.push_str(&format!("  \"stopped_utc\": {},\n", json::opt_string(stopped.as_ref().map(|s| s.as_str()))));
+        text.push_str(&format!("  \"config\": {},\n", manifest.config));
+        text.push_str("  \"artifacts\": [");
+        for (i, artifact) in manifest.artifacts.iter().enumerate() {
+            text.push_str(if i == 0 { "\n" } else { ",\n" });
+            text.push_str(&format!("    {{\"file\": {}, \"kind\": {}, \"source\": {}, \"started_utc\": {}}}",
+                                   json::string(&artifact.file), json::string(&artifact.kind),
+                                   json::string(&artifact.source), json::string(&utc_text(&artifact.started))));
+        }
+        if !manifest.artifacts.is_empty() {
+            text.push_str("\n  ");
+        }
+        text.push_str("]\n}\n");
+        text
+    }
+
+    fn report(&self, result: io::Result<()>) {
+        if let Err(e) = result {
+            println!("WARNING: Failed to write session manifest {}: {}", self.path(MANIFEST_FILE), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use time;
+
+    fn at_ms(ms: i64) -> time::Tm {
+        time::at_utc(time::Timespec::new(1_464_890_645 + ms / 1000, (ms % 1000) as i32 * 1_000_000))
+    }
+
+    fn mission_data_dir(test: &str) -> String {
+        env::temp_dir().join(format!("{}_{}", test, ::link::timestamp_ms())).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn writes_the_manifest() {
+        let dir = mission_data_dir("session_manifest");
+        let session = Session::create_at(at_ms(0), &dir, "telemetry", "0.1.0", "{\"bind_port\":30001}".to_string()).unwrap();
+        assert_eq!(session.id(), "2016Jun02_18_04_05_telemetry_0000");
+        session.add_artifact_at(at_ms(250), "imu.csv", "log", "imu");
+        session.finish_at(at_ms(60_000));
+
+        let mut text = String::new();
+        File::open(session.path(MANIFEST_FILE)).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text,
+                   "{\n  \"schema\": \"pisces-session\",\n  \"version\": 1,\n  \
+                    \"session_id\": \"2016Jun02_18_04_05_telemetry_0000\",\n  \
+                    \"binary\": \"telemetry\",\n  \
+                    \"software_version\": \"0.1.0\",\n  \
+                    \"started_utc\": \"2016-06-02T18:04:05.000Z\",\n  \
+                    \"stopped_utc\": \"2016-06-02T18:05:05.000Z\",\n  \
+                    \"config\": {\"bind_port\":30001},\n  \
+                    \"artifacts\": [\n    \
+                    {\"file\": \"imu.csv\", \"kind\": \"log\", \"source\": \"imu\", \"started_utc\": \"2016-06-02T18:04:05.250Z\"}\n  \
+                    ]\n}\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sessions_started_together_get_their_own_folders() {
+        let dir = mission_data_dir("session_folders");
+        let first = Session::create_at(at_ms(0), &dir, "navigation", "0.1.0", "{}".to_string()).unwrap();
+        let second = Session::create_at(at_ms(0), &dir, "navigation", "0.1.0", "{}".to_string()).unwrap();
+        assert!(first.id() != second.id());
+        assert!(first.folder() != second.folder());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
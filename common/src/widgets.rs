@@ -0,0 +1,258 @@
+use conrod::{Borderable, Color, Colorable, Labelable, Positionable, Sizeable, UiCell};
+use conrod::widget::{self, Button, Rectangle, Slider, Text, Widget};
+use time;
+
+use clock;
+use layout::{Area, Layout, Move};
+use mission_time::MissionTime;
+use replay::{Replay, ReplayUpdate};
+
+/// Local and UTC time in the top left corner of `area`.
+pub fn clock(ui: &mut UiCell, area: &Area, local_id: widget::Id, utc_id: widget::Id, color: Color) {
+    let time_now = time::now();
+
+    Text::new(clock::local_time_text(&time_now).as_str())
+        .xy(area.at(100.0, 10.0))
+        .font_size(16)
+        .color(color)
+        .set(local_id, ui);
+
+    Text::new(clock::utc_time_text(&time_now).as_str())
+        .xy(area.at(104.0, 30.0))
+        .font_size(16)
+        .color(color)
+        .set(utc_id, ui);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissionTimerEvent {
+    Started,
+    Paused,
+    Reset,
+}
+
+pub struct MissionTimerIds {
+    pub label: widget::Id,
+    pub start_button: widget::Id,
+    pub reset_button: widget::Id,
+}
+
+/// Mission time label with its start/pause and reset buttons, below the clock in `area`.
+pub fn mission_timer(ui: &mut UiCell, area: &Area, ids: &MissionTimerIds, mission_time: &mut MissionTime,
+                     color: Color) -> Option<MissionTimerEvent> {
+    let mut event = None;
+
+    Text::new(mission_time.text().as_str())
+        .xy(area.at(150.0, 70.0))
+        .font_size(20)
+        .color(color)
+        .set(ids.label, ui);
+
+    if Button::new()
+        .w_h(100.0, 30.0)
+        .xy(area.at(55.0, 100.0))
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label(mission_time.button_text())
+        .set(ids.start_button, ui)
+        .was_clicked()
+    {
+        mission_time.toggle();
+        event = Some(if mission_time.is_running() { MissionTimerEvent::Started } else { MissionTimerEvent::Paused });
+    }
+
+    if Button::new()
+        .w_h(100.0, 30.0)
+        .xy(area.at(160.0, 100.0))
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label("Reset")
+        .set(ids.reset_button, ui)
+        .was_clicked()
+    {
+        mission_time.reset();
+        event = Some(MissionTimerEvent::Reset);
+    }
+
+    event
+}
+
+pub struct ReplayControlIds {
+    pub play_button: widget::Id,
+    pub step_button: widget::Id,
+    pub speed_button: widget::Id,
+    pub scrub_slider: widget::Id,
+    pub label: widget::Id,
+}
+
+/// Play/pause, step and speed buttons with a scrub bar along the bottom of `area`. Returns the
+/// packets to play when the operator stepped or scrubbed.
+pub fn replay_controls(ui: &mut UiCell, area: &Area, ids: &ReplayControlIds, replay: &mut Replay,
+                       color: Color) -> Option<ReplayUpdate> {
+    let mut update = None;
+    let y = area.bottom() + 20.0;
+
+    if Button::new()
+        .w_h(70.0, 30.0)
+        .x_y(area.left() + 40.0, y)
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label(if replay.is_playing() { "Pause" } else { "Play" })
+        .set(ids.play_button, ui)
+        .was_clicked()
+    {
+        replay.toggle_pause();
+    }
+
+    if Button::new()
+        .w_h(70.0, 30.0)
+        .x_y(area.left() + 115.0, y)
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label("Step")
+        .set(ids.step_button, ui)
+        .was_clicked()
+    {
+        update = Some(replay.step());
+    }
+
+    if Button::new()
+        .w_h(70.0, 30.0)
+        .x_y(area.left() + 190.0, y)
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label(format!("x{}", replay.speed()).as_str())
+        .set(ids.speed_button, ui)
+        .was_clicked()
+    {
+        replay.cycle_speed();
+    }
+
+    // Scrub bar over the rest of the width with the replay time above it
+    let slider_w = area.w - 250.0;
+    let slider_x = area.left() + 240.0 + slider_w / 2.0;
+    if let Some(position) = Slider::new(replay.position(), 0.0, 1.0)
+        .w_h(slider_w, 20.0)
+        .x_y(slider_x, y)
+        .rgb(0.5, 0.3, 0.6)
+        .border(1.0)
+        .set(ids.scrub_slider, ui)
+    {
+        update = Some(replay.seek(position));
+    }
+
+    Text::new(replay.text().as_str())
+        .x_y(slider_x, y + 28.0)
+        .font_size(14)
+        .color(color)
+        .set(ids.label, ui);
+
+    update
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayoutEditorEvent {
+    Save,
+    Done,
+}
+
+pub struct LayoutEditorIds {
+    pub outline: widget::Id,
+    pub label: widget::Id,
+    pub next_button: widget::Id,
+    pub left_button: widget::Id,
+    pub right_button: widget::Id,
+    pub up_button: widget::Id,
+    pub down_button: widget::Id,
+    pub save_button: widget::Id,
+    pub done_button: widget::Id,
+}
+
+/// Toolbar along the bottom of `area` to pick one of the arranged `panels` and move it around
+/// `layout`, with the `selected` one outlined.
+pub fn layout_editor(ui: &mut UiCell, area: &Area, ids: &LayoutEditorIds, layout: &mut Layout,
+                     panels: &[(String, Area)], selected: &mut usize, color: Color) -> Option<LayoutEditorEvent> {
+    let mut event = None;
+    let y = area.bottom() + 20.0;
+
+    let names: Vec<String> = layout.panels().iter().map(|name| name.to_string()).collect();
+    if names.is_empty() {
+        return None;
+    }
+    if *selected >= names.len() {
+        *selected = 0;
+    }
+    let name = names[*selected].clone();
+
+    if let Some(&(_, ref panel)) = panels.iter().find(|&&(ref panel, _)| *panel == name) {
+        // Just outside the panel, where piston won't draw over it
+        Rectangle::outline([panel.w + 4.0, panel.h + 4.0])
+            .xy(panel.xy())
+            .rgb(1.0, 1.0, 0.0)
+            .set(ids.outline, ui);
+    }
+
+    Text::new(format!("Panel: {}", name).as_str())
+        .x_y(area.left() + 80.0, y)
+        .font_size(16)
+        .color(color)
+        .set(ids.label, ui);
+
+    if Button::new()
+        .w_h(70.0, 30.0)
+        .x_y(area.left() + 200.0, y)
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label("Next")
+        .set(ids.next_button, ui)
+        .was_clicked()
+    {
+        *selected = (*selected + 1) % names.len();
+    }
+
+    let moves = [(ids.left_button, "Left", Move::Left),
+                 (ids.right_button, "Right", Move::Right),
+                 (ids.up_button, "Up", Move::Up),
+                 (ids.down_button, "Down", Move::Down)];
+    for (i, &(id, label, to)) in moves.iter().enumerate() {
+        if Button::new()
+            .w_h(70.0, 30.0)
+            .x_y(area.left() + 290.0 + 75.0 * i as f64, y)
+            .rgb(0.3, 0.8, 0.3)
+            .border(1.0)
+            .label(label)
+            .set(id, ui)
+            .was_clicked()
+        {
+            layout.move_panel(&name, to);
+            // Keep the same panel selected wherever it ended up
+            *selected = layout.panels().iter().position(|panel| *panel == name).unwrap_or(0);
+        }
+    }
+
+    if Button::new()
+        .w_h(70.0, 30.0)
+        .x_y(area.right() - 115.0, y)
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label("Save")
+        .set(ids.save_button, ui)
+        .was_clicked()
+    {
+        event = Some(LayoutEditorEvent::Save);
+    }
+
+    if Button::new()
+        .w_h(70.0, 30.0)
+        .x_y(area.right() - 40.0, y)
+        .rgb(0.3, 0.8, 0.3)
+        .border(1.0)
+        .label("Done")
+        .set(ids.done_button, ui)
+        .was_clicked()
+    {
+        event = Some(LayoutEditorEvent::Done);
+    }
+
+    event
+}
@@ -1,33 +1,40 @@
 use std::io;
 use std::net::UdpSocket;
-use std::ops::DerefMut;
 
 use conrod::{
-    Background,
-    Button,
+    self,
     Color,
+
+    Borderable,
     Colorable,
-    Frameable,
-    Label,
     Labelable,
     Positionable,
-    Slider,
     Sizeable,
-    Ui,
-    WidgetId,
+};
+use conrod::widget::{
+    self,
+    Button,
+    Text,
     Widget,
 };
-use conrod::color::{rgb, white};
-use graphics::Context;
-use opengl_graphics::GlGraphics;
-use opengl_graphics::glyph_cache::GlyphCache;
-use piston::input;
-use time;
+use conrod::color::rgb;
+use graphics::{Context, Graphics};
+use gfx_graphics;
+use gfx_device_gl;
+use piston_window::{self, Key};
 
-enum MissionTime {
-    Paused(time::Duration),
-    Running(time::Tm, time::Duration),
-}
+use rover_common::MissionTime;
+use rover_common::capture::{Capture, Direction};
+use rover_common::frame::FrameError;
+use rover_common::integrity::IntegrityStats;
+use rover_common::layout::Area;
+use rover_common::link::LinkMonitor;
+use rover_common::protocol::{add_checksum, parse_telemetry, Command, TelemetryMsg};
+use rover_common::receiver::PacketReceiver;
+use rover_common::session::Session;
+use rover_common::widgets::{self, MissionTimerIds};
+
+use conrod_config;
 
 pub struct BladeUi {
     bg_color: Color,
@@ -36,191 +43,185 @@ pub struct BladeUi {
     
     pub blade: f32,
     
+    link: LinkMonitor,
+    checksums: bool, // Append a checksum to every command
+    integrity: IntegrityStats,
     socket: UdpSocket,
+    rover_addr: (String, u16),
+    capture: Option<Capture>, // Every command sent is recorded here
+
+    image_map: conrod::image::Map<<piston_window::G2d<'static> as Graphics>::Texture>,
 }
 
 impl BladeUi {
-    pub fn new(socket: UdpSocket) -> BladeUi {
+    pub fn new(socket: UdpSocket, rover_addr: (String, u16), session: &Session, command_checksums: bool) -> BladeUi {
+        let mut link = LinkMonitor::new(rover_addr.clone());
+        link.set_checksums(command_checksums);
+        session.add_artifact("packet_errors.log", "packet_errors", "rover link");
+        let integrity = IntegrityStats::new(Some(session.path("packet_errors.log").as_str()));
+
         BladeUi {
             bg_color: rgb(0.2, 0.35, 0.45),
             
-            mission_time: MissionTime::Paused(time::Duration::zero()),
+            mission_time: MissionTime::new(),
             
             blade: 0.0,
             
+            link: link,
+            checksums: command_checksums,
+            integrity: integrity,
             socket: socket,
+            rover_addr: rover_addr,
+            capture: None,
+
+            image_map: conrod::image::Map::new(),
         }
     }
     
-    pub fn draw_ui<'a>(&mut self, c: Context, gl: &mut GlGraphics, ui: &mut Ui<GlyphCache<'a>>) {
-        use graphics::*;
-    
+    pub fn update(&mut self) {
+        if let Err(e) = self.link.update(&self.socket) {
+            println!("WARNING: Failed to send link heartbeat: {}", e);
+        }
+    }
+
+    pub fn draw_ui<'a>(&mut self, c: Context,
+                          g: &mut gfx_graphics::GfxGraphics<'a, gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
+                          glyph_cache: &mut conrod::backend::piston_window::GlyphCache, ui: &mut conrod_config::Ui) {
+        self.set_widgets(&mut ui.set_widgets());
+
+        // Draw our UI!
+        conrod::backend::piston_window::draw(c, g, ui.draw(),
+                                             glyph_cache,
+                                             &self.image_map,
+                                             |img| img);
+    }
+
+    pub fn set_widgets(&mut self, ui: &mut conrod_config::UiCell) {
         // Draw the background.
-        Background::new().color(self.bg_color).draw(ui, gl);
-        
-        let time_now = time::now();
-        
-        // Local time
-        Label::new(format!("{}", time_now.strftime("Local  %x  %X").unwrap()).as_str())
-            .xy((-ui.win_w / 2.0) + 100.0, (ui.win_h / 2.0) - 10.0)
-            .font_size(16)
-            .color(self.bg_color.plain_contrast())
-            .set(LOCAL_TIME, ui);
-        
-        // UTC time
-        Label::new(format!("{}", time_now.to_utc().strftime("%Z  %x  %X").unwrap()).as_str())
-            .xy((-ui.win_w / 2.0) + 104.0, (ui.win_h / 2.0) - 30.0)
+        widget::Canvas::new()
+            .color(self.bg_color)
+            .set(CANVAS, ui);
+
+        let window = Area::window(ui.win_w, ui.win_h);
+
+        // Local and UTC time
+        widgets::clock(ui, &window, LOCAL_TIME, UTC_TIME, self.bg_color.plain_contrast());
+
+        // Link status
+        let (r, g, b) = self.link.status_rgb();
+        Text::new(self.link.status_text().as_str())
+            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 48.0)
+            .font_size(14)
+            .color(rgb(r, g, b))
+            .set(LINK_STATUS, ui);
+
+        let mission_timer_ids = MissionTimerIds {
+            label: MISSION_TIME_LABEL,
+            start_button: MISSION_START_BUTTON,
+            reset_button: MISSION_RESET_BUTTON,
+        };
+        widgets::mission_timer(ui, &window, &mission_timer_ids, &mut self.mission_time, self.bg_color.plain_contrast());
+
+        // Measured communication delay
+        Text::new(self.link.delay_text().as_str())
+            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 150.0)
             .font_size(16)
             .color(self.bg_color.plain_contrast())
-            .set(UTC_TIME, ui);
-        
-        // Mission time label
-        let mission_time =
-            match self.mission_time {
-                MissionTime::Paused(t) => t,
-                MissionTime::Running(start_time, extra_time) =>
-                    (time::now() - start_time) + extra_time
-            };
-        let total_days = mission_time.num_days();
-        let total_hours = mission_time.num_hours();
-        let total_minutes = mission_time.num_minutes();
-        let total_seconds = mission_time.num_seconds();
-        
-        let days = total_days;
-        let hours = total_hours - total_days*24;
-        let minutes = total_minutes - total_hours*60;
-        let seconds = total_seconds - total_minutes*60;
-        Label::new(format!("Mission Time: {}:{}:{}:{}", days, hours, minutes, seconds).as_str())
-            .xy((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 70.0)
-            .font_size(20)
-            .color(self.bg_color.plain_contrast())
-            .set(MISSION_TIME_LABEL, ui);
-        
-        // Mission start/pause button
-        let mission_start_text =
-            match self.mission_time {
-                MissionTime::Paused(_) => "Start",
-                MissionTime::Running(_, _) => "Pause",
-            };
-        Button::new()
-            .dimensions(100.0, 30.0)
-            .xy((-ui.win_w / 2.0) + 55.0, (ui.win_h / 2.0) - 100.0)
-            .rgb(0.3, 0.8, 0.3)
-            .frame(1.0)
-            .label(mission_start_text)
-            .react(|| {
-                match self.mission_time {
-                    MissionTime::Paused(current_time) => {
-                        self.mission_time = MissionTime::Running(time::now(), current_time);
-                    },
-                    MissionTime::Running(start_time, extra_time) => {
-                        self.mission_time = MissionTime::Paused((time::now() - start_time) + extra_time);
-                    },
-                };
-            })
-            .set(MISSION_START_BUTTON, ui);
-        
-        // Mission reset button
-        Button::new()
-            .dimensions(100.0, 30.0)
-            .xy((-ui.win_w / 2.0) + 160.0, (ui.win_h / 2.0) - 100.0)
-            .rgb(0.3, 0.8, 0.3)
-            .frame(1.0)
-            .label("Reset")
-            .react(|| {
-                self.mission_time = MissionTime::Paused(time::Duration::zero());
-            })
-            .set(MISSION_RESET_BUTTON, ui);
-        
-        // Time delay
-        Label::new("Time Delay: 0s")
-            .xy((-ui.win_w / 2.0) + 70.0, (ui.win_h / 2.0) - 150.0)
-            .font_size(18)
-            .color(self.bg_color.plain_contrast())
             .set(TIME_DELAY, ui);
-        
+
+        // Packets that failed their checksum in either direction
+        let corrupt_color =
+            if self.integrity.corrupted_in() > 0 || self.integrity.corrupted_out() > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(self.integrity.text().as_str())
+            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 170.0)
+            .font_size(14)
+            .color(corrupt_color)
+            .set(CORRUPT_PACKETS, ui);
+
         ////////////////////////////////////////////////////////////////////////////////////////////
         // Blade
-        Label::new("Blade")
-            .xy(300.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 540.0)
+        Text::new("Blade")
+            .x_y(300.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 540.0)
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(BLADE_LABEL, ui);
-        Button::new()
-            .xy(370.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 540.0)
-            .dimensions(60.0, 30.0)
+        if Button::new()
+            .x_y(370.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 540.0)
+            .w_h(60.0, 30.0)
             .rgb(0.3, 0.8, 0.3)
-            .frame(1.0)
+            .border(1.0)
             .label("Up")
-            .react(|| { self.blade = 100.0; self.send_blade(); })
-            .set(BLADE_UP, ui);
-        Button::new()
-            .xy(435.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 540.0)
-            .dimensions(60.0, 30.0)
+            .set(BLADE_UP, ui)
+            .was_clicked()
+        {
+            self.blade = 100.0;
+            self.send_blade();
+        }
+        if Button::new()
+            .x_y(435.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 540.0)
+            .w_h(60.0, 30.0)
             .rgb(0.3, 0.8, 0.3)
-            .frame(1.0)
+            .border(1.0)
             .label("Down")
-            .react(|| { self.blade = -100.0; self.send_blade(); })
-            .set(BLADE_DOWN, ui);
-        
-        // Left status RPM
-        /*Label::new(self.l_rpm_status.as_str())
-            .xy(110.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 60.0)
-            .font_size(32)
-            .color(self.bg_color.plain_contrast())
-            .set(L_RPM_STATUS, ui);
-        
-        // Right status RPM
-        Label::new(self.r_rpm_status.as_str())
-            .xy((ui.win_w / 2.0) - 110.0, (ui.win_h / 2.0) - 60.0)
-            .font_size(32)
-            .color(self.bg_color.plain_contrast())
-            .set(R_RPM_STATUS, ui);
-        
-        // Blade slider
-        Slider::new(self.blade, -10.0, 10.0)
-            .dimensions(200.0, 30.0)
-            .xy(110.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 160.0)
-            .rgb(0.5, 0.3, 0.6)
-            .frame(1.0)
-            .label("Blade")
-            .label_color(white())
-            .react(|new_blade| {
-                self.try_update_blade(new_blade);
-            })
-            .set(BLADE_SLIDER, ui);
-        
-        // 12v bus label
-        Label::new("12v Bus")
-            .xy((-ui.win_w / 2.0) + 100.0, (ui.win_h / 2.0) - 215.0)
-            .font_size(32)
-            .color(self.bg_color.plain_contrast())
-            .set(VOLTAGE_12_LABEL, ui);
-        */
-
-        // Draw our UI!
-        ui.draw(c, gl);
+            .set(BLADE_DOWN, ui)
+            .was_clicked()
+        {
+            self.blade = -100.0;
+            self.send_blade();
+        }
     }
     
+    /// Record every packet we send to the rover.
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture.clone());
+        self.link.set_capture(capture);
+    }
+
+    pub fn handle_dropped(&mut self, error: FrameError) {
+        println!("WARNING: Dropped datagram: {}", error);
+        self.integrity.on_dropped(&error);
+    }
+
+    pub fn handle_net_error(&mut self, error: String) {
+        println!("WARNING: {}", error);
+        self.link.on_error(error);
+    }
+
+    /// Replace our socket after it has kept failing.
+    pub fn rebind(&mut self, receiver: &mut PacketReceiver) {
+        match receiver.rebind(&mut self.socket) {
+            Ok(()) => {
+                println!("Rebound the rover socket");
+                self.link.clear_error();
+            },
+            Err(e) => self.handle_net_error(format!("Failed to rebind socket: {}", e)),
+        }
+    }
+
     pub fn handle_packet(&mut self, packet: String) {
-        let packets = packet.split("|");
-        
-        for packet in packets {
-            let packet_parts: Vec<String> = packet.split(":").map(|s| s.to_string()).collect();
-            
-            match packet_parts[0].as_str() {
-                _ => { println!("WARNING: Unknown packet ID: {}", packet_parts[0]) },
+        self.link.on_packet();
+
+        for msg in parse_telemetry(packet.as_str()) {
+            match msg {
+                Ok(TelemetryMsg::Pong { seq, sent_ms, rover_ms }) => self.link.on_pong(seq, sent_ms, rover_ms),
+                Ok(TelemetryMsg::BadCommands(count)) => self.integrity.on_rover_report(count),
+                Ok(_) => { },
+                Err(e) => { println!("WARNING: Malformed packet: {}", e) },
             }
         }
     }
     
-    pub fn on_key_pressed(&mut self, key: input::Key) {
+    pub fn on_key_pressed(&mut self, key: Key) {
         match key {
             _ => { },
         }
     }
     
-    pub fn on_key_released(&mut self, key: input::Key) {
+    pub fn on_key_released(&mut self, key: Key) {
         match key {
             _ => { },
         }
@@ -236,34 +237,31 @@ impl BladeUi {
     }
     
     pub fn send_blade(&self) -> io::Result<usize> {
-        let packet = format!("F{}", self.blade as i32);
-        self.socket.send_to(packet.as_bytes(), ("10.10.153.8", 30001))
+        let mut packet = Command::Blade(self.blade as i32).encode();
+        if self.checksums {
+            packet = add_checksum(&packet);
+        }
+        let bytes_written = try!(self.socket.send_to(packet.as_slice(),
+                                                     (self.rover_addr.0.as_str(), self.rover_addr.1)));
+        if let Some(ref capture) = self.capture {
+            capture.record(Direction::Out, &packet);
+        }
+        Ok(bytes_written)
     }
 }
 
-// Widget IDs
-const LOCAL_TIME: WidgetId = 0;
-const UTC_TIME: WidgetId = LOCAL_TIME + 1;
-const MISSION_TIME_LABEL: WidgetId = UTC_TIME + 1;
-const MISSION_START_BUTTON: WidgetId = MISSION_TIME_LABEL + 1;
-const MISSION_RESET_BUTTON: WidgetId = MISSION_START_BUTTON + 1;
-const TIME_DELAY: WidgetId = MISSION_RESET_BUTTON + 1;
-const IMU_LABEL: WidgetId = TIME_DELAY + 1;
-const GPS_LABEL: WidgetId = IMU_LABEL + 1;
-const LONGITUDE_LABEL: WidgetId = GPS_LABEL + 1;
-const LATITUDE_LABEL: WidgetId = LONGITUDE_LABEL + 1;
-const VELOCITY_LABEL: WidgetId = LATITUDE_LABEL + 1;
-const L_RPM_SLIDER: WidgetId = VELOCITY_LABEL + 1;
-const R_RPM_SLIDER: WidgetId = L_RPM_SLIDER + 1;
-const STOP_BUTTON: WidgetId = R_RPM_SLIDER + 1;
-const F_PAN_SLIDER: WidgetId = STOP_BUTTON + 1;
-const F_TILT_SLIDER: WidgetId = F_PAN_SLIDER + 1;
-
-const BLADE_LABEL: WidgetId = F_TILT_SLIDER + 1;
-const BLADE_UP: WidgetId = BLADE_LABEL + 1;
-const BLADE_DOWN: WidgetId = BLADE_UP + 1;
+widget_ids! {
+    CANVAS,
+    LOCAL_TIME,
+    UTC_TIME,
+    LINK_STATUS,
+    CORRUPT_PACKETS,
+    MISSION_TIME_LABEL,
+    MISSION_START_BUTTON,
+    MISSION_RESET_BUTTON,
+    TIME_DELAY,
 
-/*const L_RPM_STATUS: WidgetId = STOP_BUTTON + 1;
-const R_RPM_STATUS: WidgetId = L_RPM_STATUS + 1;
-const BLADE_SLIDER: WidgetId = R_RPM_STATUS + 1;
-const VOLTAGE_12_LABEL: WidgetId = BLADE_SLIDER + 1;*/
+    BLADE_LABEL,
+    BLADE_UP,
+    BLADE_DOWN,
+}
@@ -1,101 +1,439 @@
-use graphics::{Context, Graphics};
-use graphics::character::CharacterCache;
+use std::collections::VecDeque;
+use std::collections::vec_deque;
+
+use time;
+
+use rover_common::limits::RygLimit;
+
+// Tick spacings to pick from, in seconds
+const TICK_STEPS: [f64; 17] = [1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0,
+                               3600.0, 7200.0, 4.0 * 3600.0, 8.0 * 3600.0, 24.0 * 3600.0];
+pub const MAX_TICKS: usize = 5;
+
+// Points kept per line. A day of values at 2 Hz.
+const DEFAULT_CAPACITY: usize = 24 * 3600 * 2;
+// Length of the slices of time each line also keeps the smallest and largest value of, so zoomed
+// out graphs don't have to go through every point
+const SUMMARY_S: f64 = 10.0;
+
+/// How the time axis is labelled. Points are always added with their UTC time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeAxis {
+    Utc,
+    MissionElapsed(f64), // UTC time in seconds at which the mission elapsed time was zero
+}
+
+impl TimeAxis {
+    pub fn value(&self, t: f64) -> f64 {
+        match *self {
+            TimeAxis::Utc => t,
+            TimeAxis::MissionElapsed(zero) => t - zero,
+        }
+    }
+
+    pub fn label(&self, t: f64) -> String {
+        let value = self.value(t).round() as i64;
+        match *self {
+            TimeAxis::Utc => time::at_utc(time::Timespec::new(value, 0)).strftime("%H:%M:%S").unwrap().to_string(),
+            TimeAxis::MissionElapsed(_) => {
+                let sign = if value < 0 { "-" } else { "" };
+                let value = value.abs();
+                format!("{}{}:{:02}:{:02}", sign, value / 3600, (value / 60) % 60, value % 60)
+            },
+        }
+    }
+
+    /// Times of the ticks between `start` and `end`, at round values of the axis and at most
+    /// `MAX_TICKS` of them.
+    pub fn ticks(&self, start: f64, end: f64) -> Vec<f64> {
+        let step = TICK_STEPS.iter().cloned()
+                             .find(|&step| (end - start) / step <= MAX_TICKS as f64)
+                             .unwrap_or(TICK_STEPS[TICK_STEPS.len() - 1]);
+        let offset = self.value(start) % step;
+        let mut tick = start - offset + if offset > 0.0 { step } else { 0.0 };
+        let mut ticks = vec![];
+        while tick <= end && ticks.len() < MAX_TICKS {
+            ticks.push(tick);
+            tick += step;
+        }
+        ticks
+    }
+}
 
 struct Line {
+    name: String,
     color: [f32; 4],
-    points: Vec<(f64, f64)>,
+    points: VecDeque<(f64, f64)>, // UTC time in seconds and value, oldest first
+    summaries: VecDeque<Summary>, // Of the same points, SUMMARY_S each
+}
+
+// The smallest and largest value taken in a slice of time
+#[derive(Clone, Copy)]
+struct Summary {
+    index: i64, // Start time over SUMMARY_S
+    min: (f64, f64),
+    max: (f64, f64),
 }
 
 impl Line {
-    fn new(color: [f32; 4], points: Vec<(f64, f64)>) -> Line {
+    fn new(name: &str, color: [f32; 4]) -> Line {
         Line {
+            name: name.to_string(),
             color: color,
-            points: points,
+            points: VecDeque::new(),
+            summaries: VecDeque::new(),
         }
     }
+
+    fn push(&mut self, point: (f64, f64), capacity: usize) {
+        if self.points.len() >= capacity {
+            self.pop_front();
+        }
+        self.points.push_back(point);
+
+        let index = (point.0 / SUMMARY_S).floor() as i64;
+        if let Some(summary) = self.summaries.back_mut() {
+            if summary.index == index {
+                if point.1 < summary.min.1 {
+                    summary.min = point;
+                }
+                if point.1 > summary.max.1 {
+                    summary.max = point;
+                }
+                return;
+            }
+        }
+        self.summaries.push_back(Summary { index: index, min: point, max: point });
+    }
+
+    // Summaries only cover whole slices of time, so the oldest one can still include dropped points
+    fn pop_front(&mut self) {
+        self.points.pop_front();
+        match self.points.front() {
+            Some(first) => {
+                let first_index = (first.0 / SUMMARY_S).floor() as i64;
+                while self.summaries.front().map_or(false, |summary| summary.index < first_index) {
+                    self.summaries.pop_front();
+                }
+            },
+            None => self.summaries.clear(),
+        }
+    }
+
+    // Indices of the items from just before `start` to just after `end`, out of `len` sorted by
+    // the time `time_of` gives
+    fn range<F: Fn(usize) -> f64>(len: usize, start: f64, end: f64, time_of: F) -> (usize, usize) {
+        let index_of = |t: f64| {
+            let (mut low, mut high) = (0, len);
+            while low < high {
+                let mid = (low + high) / 2;
+                if time_of(mid) < t {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            low
+        };
+        let first = index_of(start).saturating_sub(1);
+        let last = (index_of(end) + 1).min(len);
+        (first, last.max(first))
+    }
+}
+
+// Reduces points to the smallest and largest value in each column
+struct Decimator {
+    start: f64,
+    column_s: f64,
+    column: Option<(i64, (f64, f64), (f64, f64))>, // Index, min and max point
+    points: Vec<(f64, f64)>,
 }
 
+impl Decimator {
+    fn new(start: f64, end: f64, columns: usize) -> Decimator {
+        Decimator {
+            start: start,
+            column_s: (end - start) / columns.max(1) as f64,
+            column: None,
+            points: Vec::with_capacity(2 * columns + 4),
+        }
+    }
+
+    fn add(&mut self, point: (f64, f64)) {
+        let index = ((point.0 - self.start) / self.column_s).floor() as i64;
+        self.column =
+            match self.column {
+                Some((current, min, max)) if current == index => {
+                    Some((current,
+                          if point.1 < min.1 { point } else { min },
+                          if point.1 > max.1 { point } else { max }))
+                },
+                Some((_, min, max)) => {
+                    self.push(min, max);
+                    Some((index, point, point))
+                },
+                None => Some((index, point, point)),
+            };
+    }
+
+    fn finish(mut self) -> Vec<(f64, f64)> {
+        if let Some((_, min, max)) = self.column.take() {
+            self.push(min, max);
+        }
+        self.points
+    }
+
+    // In the order they were taken
+    fn push(&mut self, min: (f64, f64), max: (f64, f64)) {
+        if min.0 < max.0 {
+            self.points.push(min);
+            self.points.push(max);
+        } else if max.0 < min.0 {
+            self.points.push(max);
+            self.points.push(min);
+        } else {
+            self.points.push(min);
+        }
+    }
+}
+
+/// Values over time for a trend graph. The graph shows the last `window_s` seconds up to the newest
+/// time it has seen, so every line scrolls with time rather than with its number of points. Older
+/// values are kept up to `capacity` points per line, so the operator can pan back to them and they
+/// can be exported at full resolution, but zooming out stops at `max_window_s`. Drawn by
+/// `TrendGraph`, with gridlines every `y_step` when `gridlines` is set and the levels of `limit`
+/// shaded behind the lines.
 pub struct LineGraph {
     lines: Vec<Line>,
-    pub size: (f64, f64),
-    pub x_interval: (f64, f64),
+    pub window_s: f64,
+    pub max_window_s: f64,
     pub y_interval: (f64, f64),
+    pub y_step: f64,
+    pub gridlines: bool,
+    pub limit: Option<RygLimit>,
+    capacity: usize,
+    end: f64, // Newest time seen, UTC seconds
 }
 
 impl LineGraph {
-    pub fn new(size: (f64, f64), x_interval: (f64, f64), y_interval: (f64, f64), line_colors: Vec<[f32; 4]>) -> LineGraph {
+    /// A graph of a line for each name and color in `lines`.
+    pub fn new(window_s: f64, y_interval: (f64, f64), y_step: f64, lines: Vec<(&str, [f32; 4])>) -> LineGraph {
         LineGraph {
-            lines: line_colors.into_iter().map(|c| Line::new(c, Vec::new())).collect(),
-            size: size,
-            x_interval: x_interval,
+            lines: lines.into_iter().map(|(name, color)| Line::new(name, color)).collect(),
+            window_s: window_s,
+            max_window_s: 2.0 * window_s,
             y_interval: y_interval,
+            y_step: y_step,
+            gridlines: true,
+            limit: None,
+            capacity: DEFAULT_CAPACITY,
+            end: 0.0,
         }
     }
-    
-    pub fn draw<G: Graphics, C>(&self, c: Context, g: &mut G, character_cache: &mut C)
-                                                where C: CharacterCache<Texture=G::Texture> {
-        use graphics::*;
-        
-        Rectangle::new([0.3, 0.3, 1.0, 1.0])
-            .draw([0.0, 0.0, self.size.0, self.size.1],
-                  &c.draw_state, c.transform,
-                  g);
 
-        {
-            // Draw upper scale
-            let c = c.trans(2.0, 2.0+12.0);
-            Text::new_color([1.0; 4], 12).draw(format!("{}", self.y_interval.1).as_str(),
-                                             character_cache,
-                                             &c.draw_state, c.transform,
-                                             g);
+    /// Keep at most `capacity` points per line, dropping the oldest.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        for line in &mut self.lines {
+            while line.points.len() > capacity {
+                line.pop_front();
+            }
+            line.points.shrink_to_fit();
         }
-        {
-            // Draw lower scale
-            let c = c.trans(2.0, self.size.1 - 2.0);
-            Text::new_color([1.0; 4], 12).draw(format!("{}", self.y_interval.0).as_str(),
-                                             character_cache,
-                                             &c.draw_state, c.transform,
-                                             g);
-        }
-        
-        for line in &self.lines {
-            for i in 1..line.points.len() {
-                let (x, y) = line.points[i];
-                let (last_x, last_y) = line.points[i - 1];
-                
-                let x_norm = (x - self.x_interval.0)/(self.x_interval.1 - self.x_interval.0);
-                let y_norm = (y - self.y_interval.0)/(self.y_interval.1 - self.y_interval.0);
-                
-                let last_x_norm = (last_x - self.x_interval.0)/(self.x_interval.1 - self.x_interval.0);
-                let last_y_norm = (last_y - self.y_interval.0)/(self.y_interval.1 - self.y_interval.0);
-                
-                if last_x >= self.x_interval.0 && x <= self.x_interval.1 {
-                    Line::new(line.color, 1.0)
-                        .draw([last_x_norm * self.size.0, self.size.1 - last_y_norm*self.size.1,
-                               x_norm * self.size.0, self.size.1 - y_norm*self.size.1],
-                              &c.draw_state, c.transform,
-                              g);
+    }
+
+    /// The times shown when following the newest values, oldest first.
+    pub fn x_interval(&self) -> (f64, f64) {
+        (self.end - self.window_s, self.end)
+    }
+
+    /// Newest time seen, UTC seconds.
+    pub fn end(&self) -> f64 {
+        self.end
+    }
+
+    pub fn num_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn name(&self, line_index: usize) -> &str {
+        &self.lines[line_index].name
+    }
+
+    pub fn color(&self, line_index: usize) -> [f32; 4] {
+        self.lines[line_index].color
+    }
+
+    /// Every point kept for a line, oldest first, at full resolution.
+    pub fn history(&self, line_index: usize) -> vec_deque::Iter<(f64, f64)> {
+        self.lines[line_index].points.iter()
+    }
+
+    /// The points of a line between `start` and `end` reduced to the smallest and largest value in
+    /// each of `columns` equal slices of time, e.g. one per pixel of the graph, so drawing them
+    /// costs the same however many points there are. The points just outside are included so the
+    /// line runs to the edges of the graph.
+    pub fn decimated(&self, line_index: usize, start: f64, end: f64, columns: usize) -> Vec<(f64, f64)> {
+        let ref line = self.lines[line_index];
+        let mut decimator = Decimator::new(start, end, columns);
+        if (end - start) / columns.max(1) as f64 >= SUMMARY_S {
+            let (first, last) = Line::range(line.summaries.len(), start, end,
+                                            |i| line.summaries[i].index as f64 * SUMMARY_S);
+            for i in first..last {
+                let summary = line.summaries[i];
+                let (a, b) = if summary.min.0 <= summary.max.0 { (summary.min, summary.max) } else { (summary.max, summary.min) };
+                decimator.add(a);
+                if b.0 > a.0 {
+                    decimator.add(b);
                 }
             }
+        } else {
+            let (first, last) = Line::range(line.points.len(), start, end, |i| line.points[i].0);
+            for i in first..last {
+                decimator.add(line.points[i]);
+            }
         }
+        decimator.finish()
+    }
+
+    /// The point of a line closest in time to `t`.
+    pub fn nearest(&self, line_index: usize, t: f64) -> Option<(f64, f64)> {
+        let ref points = self.lines[line_index].points;
+        let (first, last) = Line::range(points.len(), t, t, |i| points[i].0);
+        (first..last).map(|i| points[i])
+                     .min_by_key(|p| ((p.0 - t).abs() * 1000.0) as u64)
     }
-    
-    pub fn add_point(&mut self, line_index: usize, x: f64, y: f64) {
+
+    /// Add a value taken at `t`, UTC seconds. Points older than the line's last one are ignored.
+    pub fn add_point(&mut self, line_index: usize, t: f64, y: f64) {
         {
-            let ref mut points = self.lines[line_index].points;
-            if points.len() == 0 || points[points.len()-1].0 < x {
-                points.push((x, y));
+            let ref mut line = self.lines[line_index];
+            if line.points.back().map_or(true, |last| last.0 < t) {
+                line.push((t, y), self.capacity);
             }
         }
+        self.scroll_to(t);
+    }
 
-        let x_points = (self.x_interval.1 - self.x_interval.0) as usize;
-        if self.num_points(0) > x_points {
-            self.x_interval = ((self.num_points(0) - x_points) as f64,
-                                self.num_points(0) as f64);
+    /// Move the window up to `t` if it's newer than anything shown, e.g. to keep the graph moving
+    /// while no values arrive.
+    pub fn scroll_to(&mut self, t: f64) {
+        if t > self.end {
+            self.end = t;
         }
     }
-    
+
     pub fn num_points(&self, line_index: usize) -> usize {
         self.lines[line_index].points.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(t: f64) -> f64 {
+        (t * 7.3).sin() * 100.0 + t * 0.01
+    }
+
+    #[test]
+    fn ticks_at_round_values() {
+        assert_eq!(TimeAxis::Utc.ticks(1003.0, 1019.0), vec![1005.0, 1010.0, 1015.0]);
+        assert_eq!(TimeAxis::Utc.ticks(1000.0, 1010.0), vec![1000.0, 1002.0, 1004.0, 1006.0, 1008.0]);
+
+        // Rounded to the mission's clock, not UTC's
+        let axis = TimeAxis::MissionElapsed(1001.0);
+        assert_eq!(axis.ticks(1003.0, 1019.0), vec![1006.0, 1011.0, 1016.0]);
+    }
+
+    #[test]
+    fn ticks_before_the_mission_started() {
+        // -7 s to 13 s of mission time
+        let axis = TimeAxis::MissionElapsed(1000.0);
+        assert_eq!(axis.ticks(993.0, 1013.0), vec![995.0, 1000.0, 1005.0, 1010.0]);
+    }
+
+    #[test]
+    fn caps_the_number_of_ticks() {
+        // Six round values fit, one too many
+        assert_eq!(TimeAxis::Utc.ticks(0.0, 25.0).len(), MAX_TICKS);
+        // Longer than the largest step allows
+        let ticks = TimeAxis::Utc.ticks(0.0, 30.0 * 24.0 * 3600.0);
+        assert_eq!(ticks.len(), MAX_TICKS);
+        assert_eq!(ticks[1] - ticks[0], 24.0 * 3600.0);
+    }
+
+    #[test]
+    fn labels_times() {
+        assert_eq!(TimeAxis::Utc.label(86400.0 + 3661.4), "01:01:01");
+
+        let axis = TimeAxis::MissionElapsed(100.0);
+        assert_eq!(axis.label(100.0 + 3725.0), "1:02:05");
+        assert_eq!(axis.label(100.0 + 30.0 * 3600.0), "30:00:00");
+        assert_eq!(axis.label(100.0 - 65.0), "-0:01:05");
+        assert_eq!(axis.label(100.0 - 0.4), "0:00:00");
+    }
+
+    #[test]
+    fn evicts_at_capacity_and_prunes_summaries() {
+        let mut graph = LineGraph::new(60.0, (0.0, 10.0), 1.0, vec![("a", [1.0; 4])]);
+        graph.set_capacity(5);
+        for i in 0..11 {
+            graph.add_point(0, i as f64 * 2.0, i as f64);
+        }
+        assert_eq!(graph.num_points(0), 5);
+        assert_eq!(graph.history(0).next(), Some(&(12.0, 6.0)));
+
+        // The slice from 0 s went with its last point, the one from 10 s still has some
+        let ref line = graph.lines[0];
+        let indices: Vec<i64> = line.summaries.iter().map(|summary| summary.index).collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert_eq!(line.summaries[0].min, (10.0, 5.0)); // Kept from before the eviction
+        assert_eq!(line.summaries[0].max, (18.0, 9.0));
+    }
+
+    #[test]
+    fn finds_range_around_times() {
+        let times = [0.0, 10.0, 20.0, 30.0, 40.0];
+        assert_eq!(Line::range(times.len(), 15.0, 25.0, |i| times[i]), (1, 4));
+        assert_eq!(Line::range(times.len(), 20.0, 20.0, |i| times[i]), (1, 3));
+        assert_eq!(Line::range(times.len(), -10.0, 100.0, |i| times[i]), (0, 5));
+        assert_eq!(Line::range(times.len(), 100.0, 200.0, |i| times[i]), (4, 5));
+        assert_eq!(Line::range(0, 0.0, 10.0, |_| 0.0), (0, 0));
+    }
+
+    #[test]
+    fn keeps_extremes_of_a_column_in_time_order() {
+        let mut decimator = Decimator::new(0.0, 20.0, 2);
+        for &point in &[(1.0, 5.0), (2.0, -1.0), (3.0, 9.0), (4.0, 0.0),
+                        (11.0, 3.0), (12.0, 8.0), (13.0, -4.0),
+                        (25.0, 1.0)] {
+            decimator.add(point);
+        }
+        assert_eq!(decimator.finish(),
+                   vec![(2.0, -1.0), (3.0, 9.0),
+                        (12.0, 8.0), (13.0, -4.0),
+                        (25.0, 1.0)]);
+    }
+
+    #[test]
+    fn summaries_give_the_same_extremes_as_points() {
+        let mut graph = LineGraph::new(60.0, (0.0, 10.0), 1.0, vec![("a", [1.0; 4])]);
+        for i in 0..2000 {
+            let t = i as f64 * 0.5;
+            graph.add_point(0, t, value(t));
+        }
+
+        // 40 s columns go through the summaries, 4 s ones through every point
+        let (start, end) = (100.0, 900.0);
+        let summarized = graph.decimated(0, start, end, 20);
+        let mut decimator = Decimator::new(start, end, 20);
+        for &point in graph.history(0).filter(|point| point.0 >= start && point.0 < end) {
+            decimator.add(point);
+        }
+        let inside: Vec<(f64, f64)> =
+            summarized.into_iter().filter(|point| point.0 >= start && point.0 < end).collect();
+        assert_eq!(inside, decimator.finish());
+        assert_eq!(inside.len(), 40);
+    }
+}
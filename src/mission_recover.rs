@@ -0,0 +1,129 @@
+// Repairs what a crashed ground station left in a mission folder. Recordings still named
+// `.partial` are re-encoded up to their last readable frame and given their final name, logs and
+// packet captures are cut back to their last complete row or record.
+
+use std::env;
+use std::fs;
+use std::io;
+
+extern crate ffmpeg;
+extern crate rover_common;
+
+use ffmpeg::media;
+
+use rover_common::capture;
+use rover_common::data_log;
+
+use recorder::{Recorder, PARTIAL_EXT};
+
+mod recorder;
+
+fn main() {
+    let folders: Vec<String> = env::args().skip(1).collect();
+    if folders.is_empty() {
+        println!("Usage: mission-recover <mission folder>...");
+        return;
+    }
+
+    ffmpeg::init().unwrap();
+
+    for folder in &folders {
+        recover_folder(folder);
+    }
+}
+
+fn recover_folder(folder: &str) {
+    let mut paths: Vec<String> =
+        match fs::read_dir(folder) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok())
+                                  .filter_map(|entry| entry.path().to_str().map(|path| path.to_string()))
+                                  .collect(),
+            Err(e) => {
+                println!("Failed to read mission folder {}: {}", folder, e);
+                return;
+            },
+        };
+    paths.sort();
+
+    for path in paths {
+        let result =
+            if path.ends_with(&format!(".{}", PARTIAL_EXT)) {
+                recover_recording(&path).map(Some)
+            } else if path.ends_with(".csv") || path.ends_with(".jsonl") {
+                data_log::repair(&path).map(|repaired| if repaired { Some("removed an unfinished row".to_string()) } else { None })
+            } else if path.ends_with(".cap") {
+                capture::repair(&path).map(|repaired| if repaired { Some("removed an unfinished record".to_string()) } else { None })
+            } else {
+                continue;
+            };
+
+        match result {
+            Ok(Some(msg)) => println!("{}: {}", path, msg),
+            Ok(None) => { },
+            Err(e) => println!("WARNING: Failed to recover {}: {}", path, e),
+        }
+    }
+}
+
+// Re-encode an unfinished recording into a new file with its final name. The original is kept
+// as `.damaged` until that succeeds, and put back as `.partial` if it fails so another run can
+// try again.
+fn recover_recording(partial_path: &str) -> io::Result<String> {
+    let path = &partial_path[..partial_path.len() - PARTIAL_EXT.len() - 1];
+    let damaged_path = format!("{}.damaged", path);
+    try!(fs::rename(partial_path, &damaged_path));
+
+    match reencode(&damaged_path, path) {
+        Ok(frames) => {
+            try!(fs::remove_file(&damaged_path));
+            Ok(format!("recovered {} frames to {}", frames, path))
+        },
+        Err(e) => {
+            // Whatever the new recording got to is no use, it may not even have a header
+            let _ = fs::remove_file(recorder::partial_path(path));
+            let _ = fs::remove_file(path);
+            try!(fs::rename(&damaged_path, partial_path));
+            Err(e)
+        },
+    }
+}
+
+fn reencode(in_path: &str, out_path: &str) -> io::Result<u64> {
+    let mut format_context = try!(ffmpeg::format::input(&in_path).map_err(ffmpeg_error));
+    let (index, stream_codec) =
+        match format_context.streams()
+                            .filter(|stream| stream.codec().medium() == media::Type::Video)
+                            .map(|stream| (stream.index(), stream.codec()))
+                            .next() {
+            Some(stream) => stream,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "no video stream")),
+        };
+    let codec_context = stream_codec.clone();
+    let mut decoder = try!(codec_context.decoder().video().map_err(ffmpeg_error));
+
+    let mut recorder = try!(Recorder::create(out_path, decoder.width(), decoder.height(), decoder.format())
+                                     .map_err(ffmpeg_error));
+    let mut frame = ffmpeg::frame::Video::empty();
+    let mut frames = 0;
+    for (stream, packet) in format_context.packets() {
+        if stream.index() != index {
+            continue;
+        }
+        match decoder.decode(&packet, &mut frame) {
+            Ok(true) => {
+                // Recordings are made at 10 frames per second
+                recorder.write(frames as i64, &frame);
+                frames += 1;
+            },
+            Ok(false) => { },
+            Err(_) => break, // Cut short by the crash
+        }
+    }
+
+    try!(recorder.finish());
+    Ok(frames)
+}
+
+fn ffmpeg_error(e: ffmpeg::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{}", e))
+}
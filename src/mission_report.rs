@@ -0,0 +1,725 @@
+// Builds an HTML report from the session folders of a mission: plots and statistics of the
+// telemetry logs, alarm events, the GPS track, a snapshot gallery and the commands sent to the
+// rover. Plots are inline SVG so the report is a single file, snapshots are linked.
+
+use std::cmp::Ordering;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+extern crate time;
+extern crate rover_common;
+
+use rover_common::capture::{CaptureReader, Direction, Record};
+use rover_common::data_log::{self, LogData};
+use rover_common::json;
+use rover_common::limits::{alarm_limit, Level, RygLimit};
+use rover_common::logger::CHANNELS;
+use rover_common::protocol::{decode_sequenced, strip_checksum, Command, HANDSHAKE};
+use rover_common::session::MANIFEST_FILE;
+
+const USAGE: &'static str = "Usage: mission-report [--out <report.html>] <session folder>...
+
+Writes report.html to the first folder unless --out is given.";
+
+// Plotted channels with their titles and units
+const PLOTS: [(&'static str, &'static str, &'static str); 3] = [
+    ("Voltage", "volt", "V"),
+    ("Current", "amp", "A"),
+    ("Temperature", "motor_temp", "C"),
+];
+
+const COLORS: [&'static str; 5] = ["#d62728", "#1f77b4", "#2ca02c", "#ff7f0e", "#9467bd"];
+const MAX_PLOT_POINTS: usize = 2000;
+const MAX_PACKET_ERRORS: usize = 200;
+
+struct Log {
+    file: String,
+    channel: &'static str,
+    data: LogData,
+}
+
+struct Snapshot {
+    src: String, // Relative to the report
+    file: String,
+    utc: Option<String>,
+    source: Option<String>,
+}
+
+struct SentCommand {
+    utc_s: f64,
+    text: String,
+    count: u32, // Sent this many times in a row
+}
+
+struct Alarm {
+    utc_s: f64,
+    column: String,
+    value: f64,
+    level: Level,
+}
+
+#[derive(Default)]
+struct Mission {
+    manifests: Vec<(String, Option<json::Value>)>, // Folder and its session manifest
+    logs: Vec<Log>,
+    snapshots: Vec<Snapshot>,
+    commands: Vec<SentCommand>,
+    packet_errors: Vec<String>,
+}
+
+fn main() {
+    let mut out_path = None;
+    let mut folders = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => {
+                match args.next() {
+                    Some(path) => out_path = Some(path),
+                    None => {
+                        println!("{}", USAGE);
+                        return;
+                    },
+                }
+            },
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => folders.push(arg),
+        }
+    }
+    if folders.is_empty() {
+        println!("{}", USAGE);
+        return;
+    }
+    let out_path = out_path.unwrap_or(format!("{}/report.html", folders[0]));
+    let out_dir = Path::new(&out_path).parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+
+    let mut mission = Mission::default();
+    for folder in &folders {
+        if let Err(e) = read_folder(&mut mission, folder, &out_dir) {
+            println!("Failed to read {}: {}", folder, e);
+            return;
+        }
+    }
+
+    let html = report(&mission);
+    match File::create(&out_path).and_then(|mut file| file.write_all(html.as_bytes())) {
+        Ok(()) => println!("Wrote {}", out_path),
+        Err(e) => println!("Failed to write {}: {}", out_path, e),
+    }
+}
+
+fn read_folder(mission: &mut Mission, folder: &str, out_dir: &Path) -> io::Result<()> {
+    let mut paths = vec![];
+    for entry in try!(fs::read_dir(folder)) {
+        paths.push(try!(entry).path());
+    }
+    paths.sort();
+
+    let manifest =
+        match read_text(&Path::new(folder).join(MANIFEST_FILE)) {
+            Ok(text) => {
+                match json::parse(&text) {
+                    Ok(manifest) => Some(manifest),
+                    Err(e) => {
+                        println!("WARNING: Failed to parse {}/{}: {}", folder, MANIFEST_FILE, e);
+                        None
+                    },
+                }
+            },
+            Err(_) => None, // Recorded before sessions had manifests
+        };
+
+    for path in paths {
+        let file = path.file_name().and_then(|name| name.to_str()).unwrap_or("").to_string();
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        match ext {
+            "csv" | "jsonl" => {
+                match data_log::read(&path) {
+                    Ok(data) => {
+                        match CHANNELS.iter().find(|channel| channel.1 == &data.columns[..]) {
+                            Some(&(channel, _)) => mission.logs.push(Log { file: file, channel: channel, data: data }),
+                            None => println!("WARNING: {} isn't a telemetry log", path.display()),
+                        }
+                    },
+                    Err(e) => println!("WARNING: Failed to read {}: {}", path.display(), e),
+                }
+            },
+            "cap" => read_commands(mission, &path),
+            "jpg" => {
+                let artifact = manifest.as_ref().and_then(|manifest| find_artifact(manifest, &file));
+                mission.snapshots.push(Snapshot {
+                    src: relative_path(&path, out_dir),
+                    utc: artifact.and_then(|a| a.get("started_utc")).and_then(|utc| utc.as_str()).map(|s| s.to_string()),
+                    source: artifact.and_then(|a| a.get("source")).and_then(|source| source.as_str()).map(|s| s.to_string()),
+                    file: file,
+                });
+            },
+            "log" if file == "packet_errors.log" => {
+                match read_text(&path) {
+                    Ok(text) => mission.packet_errors.extend(text.lines().map(|line| line.to_string())),
+                    Err(e) => println!("WARNING: Failed to read {}: {}", path.display(), e),
+                }
+            },
+            _ => { },
+        }
+    }
+
+    mission.manifests.push((folder.to_string(), manifest));
+    Ok(())
+}
+
+// Every command sent to the rover, with repeats (held keys, retransmissions) collapsed
+fn read_commands(mission: &mut Mission, path: &Path) {
+    let reader =
+        match CaptureReader::open(path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                println!("WARNING: Failed to read {}: {}", path.display(), e);
+                return;
+            },
+        };
+
+    for record in reader {
+        let record =
+            match record {
+                Ok(record) => record,
+                Err(e) => {
+                    println!("WARNING: Failed to read {}: {}", path.display(), e);
+                    break;
+                },
+            };
+        add_command(&mut mission.commands, &record);
+    }
+}
+
+// Add a captured packet to `commands` if it's a command we sent, counting it as a repeat if it's
+// the same as the last one
+fn add_command(commands: &mut Vec<SentCommand>, record: &Record) {
+    if record.direction != Direction::Out || record.data == HANDSHAKE {
+        return;
+    }
+
+    let text =
+        match strip_checksum(&record.data).map(decode_sequenced) {
+            Ok(Ok((_, Command::Ping(_, _)))) => return,
+            Ok(Ok((_, command))) => format!("{:?}", command),
+            _ => String::from_utf8_lossy(&record.data).trim_right_matches('\0').to_string(),
+        };
+    let utc_s = record.timestamp_us as f64 / 1e6;
+    match commands.last_mut() {
+        Some(ref mut last) if last.text == text => {
+            last.count += 1;
+            return;
+        },
+        _ => { },
+    }
+    commands.push(SentCommand { utc_s: utc_s, text: text, count: 1 });
+}
+
+fn find_artifact<'a>(manifest: &'a json::Value, file: &str) -> Option<&'a json::Value> {
+    manifest.get("artifacts")
+            .and_then(|artifacts| artifacts.as_array())
+            .and_then(|artifacts| artifacts.iter().find(|a| a.get("file").and_then(|f| f.as_str()) == Some(file)))
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    Ok(text)
+}
+
+// `path` relative to the report's folder so the report can be moved along with the mission data
+fn relative_path(path: &Path, out_dir: &Path) -> String {
+    let (path, out_dir) =
+        match (fs::canonicalize(path), fs::canonicalize(out_dir)) {
+            (Ok(path), Ok(out_dir)) => (path, out_dir),
+            _ => return path.to_string_lossy().into_owned(),
+        };
+    if let Ok(relative) = path.strip_prefix(&out_dir) {
+        return relative.to_string_lossy().into_owned();
+    }
+    match (out_dir.parent(), path.strip_prefix(out_dir.parent().unwrap_or(&out_dir))) {
+        (Some(_), Ok(relative)) => format!("../{}", relative.to_string_lossy()),
+        _ => path.to_string_lossy().into_owned(),
+    }
+}
+
+// The log with the most rows for a channel
+fn channel_log<'a>(mission: &'a Mission, channel: &str) -> Option<&'a Log> {
+    mission.logs.iter().filter(|log| log.channel == channel).max_by_key(|log| log.data.rows.len())
+}
+
+fn report(mission: &Mission) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>PISCES mission report</title>\n");
+    html.push_str("<style>\n\
+                   body { font-family: sans-serif; margin: 2em; }\n\
+                   table { border-collapse: collapse; margin-bottom: 1em; }\n\
+                   th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n\
+                   td.num { text-align: right; }\n\
+                   tr.yellow { background: #fff3b0; }\n\
+                   tr.red { background: #ffc0c0; }\n\
+                   tr.green { background: #d0f0d0; }\n\
+                   .gallery figure { display: inline-block; margin: 0.5em; }\n\
+                   .gallery img { width: 350px; }\n\
+                   </style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>PISCES mission report</h1>\n<p>Generated {}</p>\n",
+                           data_log::utc_text(&time::now_utc())));
+
+    sessions_section(&mut html, mission);
+    plots_section(&mut html, mission);
+    statistics_section(&mut html, mission);
+    alarms_section(&mut html, mission);
+    gps_section(&mut html, mission);
+    snapshots_section(&mut html, mission);
+    commands_section(&mut html, mission);
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn sessions_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>Sessions</h2>\n<table>\n<tr><th>Folder</th><th>Session</th><th>Binary</th><th>Version</th>\
+                   <th>Started (UTC)</th><th>Stopped (UTC)</th><th>Config file</th></tr>\n");
+    for &(ref folder, ref manifest) in &mission.manifests {
+        let field = |key: &str| {
+            manifest.as_ref().and_then(|m| m.get(key)).and_then(|value| value.as_str()).map(escape)
+        };
+        let config_file =
+            manifest.as_ref()
+                    .and_then(|m| m.get("config"))
+                    .and_then(|config| config.get("config_file"))
+                    .and_then(|file| file.as_str())
+                    .map(escape);
+        let stopped =
+            match manifest {
+                Some(_) => field("stopped_utc").unwrap_or("did not exit cleanly".to_string()),
+                None => "".to_string(),
+            };
+        html.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                               escape(folder), field("session_id").unwrap_or("no manifest".to_string()),
+                               field("binary").unwrap_or_default(), field("software_version").unwrap_or_default(),
+                               field("started_utc").unwrap_or_default(), stopped, config_file.unwrap_or_default()));
+    }
+    html.push_str("</table>\n");
+}
+
+fn plots_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>Plots</h2>\n");
+    for &(title, channel, unit) in &PLOTS {
+        html.push_str(&format!("<h3>{} ({})</h3>\n", title, unit));
+        let log =
+            match channel_log(mission, channel) {
+                Some(log) if !log.data.rows.is_empty() => log,
+                _ => {
+                    html.push_str("<p>No data.</p>\n");
+                    continue;
+                },
+            };
+
+        let series: Vec<(String, Vec<(f64, f64)>)> =
+            log.data.columns.iter().enumerate().map(|(i, column)| {
+                let points = log.data.rows.iter()
+                                          .filter_map(|row| row.values[i].map(|value| (row.utc_s, value)))
+                                          .collect();
+                (column.clone(), points)
+            }).collect();
+        let mut limits = vec![];
+        for column in &log.data.columns {
+            if let Some(limit) = alarm_limit(channel, column) {
+                let (red, yellow) =
+                    match limit {
+                        RygLimit::LessThan(r, y) | RygLimit::GreaterThan(r, y) => (r, y),
+                    };
+                for &(value, level) in &[(red, Level::Red), (yellow, Level::Yellow)] {
+                    if !limits.contains(&(value, level)) {
+                        limits.push((value, level));
+                    }
+                }
+            }
+        }
+        html.push_str(&line_plot(&series, &limits));
+        html.push_str(&format!("<p>From {}</p>\n", escape(&log.file)));
+    }
+}
+
+// A time series plot with a line per series and dashed lines at the alarm limits
+fn line_plot(series: &[(String, Vec<(f64, f64)>)], limits: &[(f64, Level)]) -> String {
+    const W: f64 = 800.0;
+    const H: f64 = 240.0;
+    const LEFT: f64 = 60.0;
+    const RIGHT: f64 = 120.0; // Room for the legend
+    const TOP: f64 = 10.0;
+    const BOTTOM: f64 = 30.0;
+
+    let points = series.iter().flat_map(|s| s.1.iter());
+    let (mut x_min, mut x_max, mut y_min, mut y_max) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+    for &(x, y) in points {
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    if x_min > x_max {
+        return "<p>No data.</p>\n".to_string();
+    }
+    if x_max - x_min < 1.0 {
+        x_max = x_min + 1.0;
+    }
+    let pad = ((y_max - y_min) * 0.05).max(0.5);
+    y_min -= pad;
+    y_max += pad;
+
+    let px = |x: f64| LEFT + (x - x_min) / (x_max - x_min) * (W - LEFT - RIGHT);
+    let py = |y: f64| TOP + (y_max - y) / (y_max - y_min) * (H - TOP - BOTTOM);
+
+    let mut svg = format!("<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\" font-size=\"11\">\n", W, H);
+    svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#888\"/>\n",
+                          LEFT, TOP, W - LEFT - RIGHT, H - TOP - BOTTOM));
+    for i in 0..5 {
+        let x = x_min + (x_max - x_min) * i as f64 / 4.0;
+        svg.push_str(&format!("<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                              px(x), H - 10.0, clock_text(x)));
+        let y = y_min + (y_max - y_min) * i as f64 / 4.0;
+        svg.push_str(&format!("<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{:.1}</text>\n",
+                              LEFT - 5.0, py(y) + 4.0, y));
+    }
+    for &(value, level) in limits {
+        if value > y_min && value < y_max {
+            let color = if level == Level::Red { "#d00" } else { "#cc0" };
+            svg.push_str(&format!("<line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"{}\" stroke-dasharray=\"4 4\"/>\n",
+                                  LEFT, py(value), W - RIGHT, py(value), color));
+        }
+    }
+    for (i, &(ref label, ref points)) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let step = points.len() / MAX_PLOT_POINTS + 1;
+        let coords: Vec<String> =
+            points.iter().enumerate()
+                  .filter(|&(i, _)| i % step == 0)
+                  .map(|(_, &(x, y))| format!("{:.1},{:.1}", px(x), py(y)))
+                  .collect();
+        svg.push_str(&format!("<polyline fill=\"none\" stroke=\"{}\" points=\"{}\"/>\n", color, coords.join(" ")));
+        let legend_y = TOP + 15.0 + 18.0 * i as f64;
+        svg.push_str(&format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"3\"/>\n",
+                              W - RIGHT + 10.0, legend_y - 4.0, W - RIGHT + 30.0, legend_y - 4.0, color));
+        svg.push_str(&format!("<text x=\"{}\" y=\"{}\">{}</text>\n", W - RIGHT + 35.0, legend_y, escape(label)));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn statistics_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>Statistics</h2>\n<table>\n<tr><th>Log</th><th>Column</th><th>Samples</th>\
+                   <th>Min</th><th>Avg</th><th>Max</th></tr>\n");
+    for log in &mission.logs {
+        for (i, column) in log.data.columns.iter().enumerate() {
+            let values: Vec<f64> = log.data.rows.iter().filter_map(|row| row.values[i]).collect();
+            if values.is_empty() {
+                html.push_str(&format!("<tr><td>{}</td><td>{}</td><td class=\"num\">0</td><td></td><td></td><td></td></tr>\n",
+                                       escape(&log.file), escape(column)));
+                continue;
+            }
+            let min = values.iter().cloned().fold(f64::MAX, f64::min);
+            let max = values.iter().cloned().fold(f64::MIN, f64::max);
+            let avg = values.iter().sum::<f64>() / values.len() as f64;
+            let precision = if log.channel == "gps" { 6 } else { 2 }; // Latitude and longitude in degrees
+            html.push_str(&format!("<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.*}</td>\
+                                    <td class=\"num\">{:.*}</td><td class=\"num\">{:.*}</td></tr>\n",
+                                   escape(&log.file), escape(column), values.len(),
+                                   precision, min, precision, avg, precision, max));
+        }
+    }
+    html.push_str("</table>\n");
+}
+
+fn alarms_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>Alarms</h2>\n");
+    let alarms = find_alarms(mission);
+
+    if alarms.is_empty() {
+        html.push_str("<p>No alarms.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Time (UTC)</th><th>Reading</th><th>Value</th><th>Level</th></tr>\n");
+        for alarm in &alarms {
+            let level =
+                match alarm.level {
+                    Level::Red => "red",
+                    Level::Yellow => "yellow",
+                    Level::Green => "green",
+                };
+            html.push_str(&format!("<tr class=\"{0}\"><td>{1}</td><td>{2}</td><td class=\"num\">{3:.2}</td><td>{0}</td></tr>\n",
+                                   level, clock_text(alarm.utc_s), escape(&alarm.column), alarm.value));
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str(&format!("<h3>Packet errors</h3>\n<p>{} logged.</p>\n", mission.packet_errors.len()));
+    if !mission.packet_errors.is_empty() {
+        html.push_str("<table>\n<tr><th>Time (UTC)</th><th>Direction</th><th>Error</th></tr>\n");
+        for line in mission.packet_errors.iter().take(MAX_PACKET_ERRORS) {
+            let fields: Vec<&str> = line.splitn(3, '\t').collect();
+            html.push_str("<tr>");
+            for field in &fields {
+                html.push_str(&format!("<td>{}</td>", escape(field)));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+        if mission.packet_errors.len() > MAX_PACKET_ERRORS {
+            html.push_str(&format!("<p>... and {} more.</p>\n", mission.packet_errors.len() - MAX_PACKET_ERRORS));
+        }
+    }
+}
+
+// Every change of level of a reading with alarm limits, in time order
+fn find_alarms(mission: &Mission) -> Vec<Alarm> {
+    let mut alarms = vec![];
+    for &(channel, _) in CHANNELS {
+        let log =
+            match channel_log(mission, channel) {
+                Some(log) => log,
+                None => continue,
+            };
+        for (i, column) in log.data.columns.iter().enumerate() {
+            let limit =
+                match alarm_limit(channel, column) {
+                    Some(limit) => limit,
+                    None => continue,
+                };
+            let mut level = Level::Green;
+            for row in &log.data.rows {
+                if let Some(value) = row.values[i] {
+                    if limit.level(value) != level {
+                        level = limit.level(value);
+                        alarms.push(Alarm { utc_s: row.utc_s, column: column.clone(), value: value, level: level });
+                    }
+                }
+            }
+        }
+    }
+    alarms.sort_by(|a, b| a.utc_s.partial_cmp(&b.utc_s).unwrap_or(Ordering::Equal));
+    alarms
+}
+
+fn gps_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>GPS track</h2>\n");
+    let track: Vec<(f64, f64)> =
+        match channel_log(mission, "gps") {
+            Some(log) => log.data.rows.iter().filter_map(|row| {
+                match (row.values[0], row.values[1]) {
+                    (Some(latitude), Some(longitude)) => Some((latitude, longitude)),
+                    _ => None,
+                }
+            }).collect(),
+            None => vec![],
+        };
+    if track.is_empty() {
+        html.push_str("<p>No fixes.</p>\n");
+        return;
+    }
+
+    const SIZE: f64 = 500.0;
+    const MARGIN: f64 = 20.0;
+    // Equirectangular projection, fine over the distances the rover covers
+    let mean_latitude = track.iter().map(|p| p.0).sum::<f64>() / track.len() as f64;
+    let scale_x = mean_latitude.to_radians().cos();
+    let xs: Vec<f64> = track.iter().map(|p| p.1 * scale_x).collect();
+    let ys: Vec<f64> = track.iter().map(|p| p.0).collect();
+    let (x_min, x_max) = (xs.iter().cloned().fold(f64::MAX, f64::min), xs.iter().cloned().fold(f64::MIN, f64::max));
+    let (y_min, y_max) = (ys.iter().cloned().fold(f64::MAX, f64::min), ys.iter().cloned().fold(f64::MIN, f64::max));
+    let span = (x_max - x_min).max(y_max - y_min).max(1e-6);
+    let px = |x: f64| MARGIN + (x - x_min) / span * (SIZE - 2.0 * MARGIN);
+    let py = |y: f64| SIZE - MARGIN - (y - y_min) / span * (SIZE - 2.0 * MARGIN);
+
+    let step = track.len() / MAX_PLOT_POINTS + 1;
+    let coords: Vec<String> =
+        xs.iter().zip(&ys).enumerate()
+          .filter(|&(i, _)| i % step == 0)
+          .map(|(_, (&x, &y))| format!("{:.1},{:.1}", px(x), py(y)))
+          .collect();
+    html.push_str(&format!("<svg width=\"{0}\" height=\"{0}\" xmlns=\"http://www.w3.org/2000/svg\">\n\
+                            <rect width=\"{0}\" height=\"{0}\" fill=\"#f4f4f4\" stroke=\"#888\"/>\n\
+                            <polyline fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"2\" points=\"{1}\"/>\n\
+                            <circle cx=\"{2:.1}\" cy=\"{3:.1}\" r=\"5\" fill=\"#2ca02c\"/>\n\
+                            <circle cx=\"{4:.1}\" cy=\"{5:.1}\" r=\"5\" fill=\"#d62728\"/>\n</svg>\n",
+                           SIZE, coords.join(" "), px(xs[0]), py(ys[0]), px(xs[xs.len() - 1]), py(ys[ys.len() - 1])));
+
+    let distance_m: f64 = track.windows(2).map(|pair| distance_m(pair[0], pair[1])).sum();
+    let (start, end) = (track[0], track[track.len() - 1]);
+    html.push_str(&format!("<p>Start (green) {:.6}, {:.6}, end (red) {:.6}, {:.6}. {} fixes, {:.0} m travelled.</p>\n",
+                           start.0, start.1, end.0, end.1, track.len(), distance_m));
+}
+
+// Great circle distance between two (latitude, longitude) points
+fn distance_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+    let (lat_a, lat_b) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+fn snapshots_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>Snapshots</h2>\n");
+    if mission.snapshots.is_empty() {
+        html.push_str("<p>No snapshots.</p>\n");
+        return;
+    }
+    html.push_str("<div class=\"gallery\">\n");
+    for snapshot in &mission.snapshots {
+        let mut caption = escape(&snapshot.file);
+        if let Some(ref source) = snapshot.source {
+            caption.push_str(&format!(", {}", escape(source)));
+        }
+        if let Some(ref utc) = snapshot.utc {
+            caption.push_str(&format!(", {}", escape(utc)));
+        }
+        html.push_str(&format!("<figure><a href=\"{0}\"><img src=\"{0}\"></a><figcaption>{1}</figcaption></figure>\n",
+                               escape(&snapshot.src), caption));
+    }
+    html.push_str("</div>\n");
+}
+
+fn commands_section(html: &mut String, mission: &Mission) {
+    html.push_str("<h2>Commands sent</h2>\n");
+    if mission.commands.is_empty() {
+        html.push_str("<p>No commands.</p>\n");
+        return;
+    }
+    html.push_str("<table>\n<tr><th>Time (UTC)</th><th>Command</th><th>Times sent</th></tr>\n");
+    for command in &mission.commands {
+        html.push_str(&format!("<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>\n",
+                               clock_text(command.utc_s), escape(&command.text), command.count));
+    }
+    html.push_str("</table>\n");
+}
+
+fn clock_text(utc_s: f64) -> String {
+    let tm = time::at_utc(time::Timespec::new(utc_s.floor() as i64, 0));
+    tm.strftime("%H:%M:%S").unwrap().to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use rover_common::capture::{Direction, Record};
+    use rover_common::data_log::{LogData, Row};
+    use rover_common::limits::Level;
+    use rover_common::link::timestamp_ms;
+    use rover_common::protocol::{add_checksum, encode_sequenced, Command, HANDSHAKE};
+
+    fn out(timestamp_us: u64, data: Vec<u8>) -> Record {
+        Record { timestamp_us: timestamp_us, direction: Direction::Out, data: data }
+    }
+
+    fn volt_log(rows: &[(f64, f64)]) -> Log {
+        Log {
+            file: "volt.csv".to_string(),
+            channel: "volt",
+            data: LogData {
+                columns: vec!["h48_v".to_string(), "p12e_v".to_string()],
+                rows: rows.iter().map(|&(utc_s, h48_v)| {
+                    Row { utc_s: utc_s, mission_elapsed_s: 0.0, values: vec![Some(h48_v), None] }
+                }).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn measures_distances() {
+        assert_eq!(distance_m((45.0, -122.0), (45.0, -122.0)), 0.0);
+        // A degree of latitude is about 111 km anywhere
+        assert!((distance_m((0.0, 0.0), (1.0, 0.0)) - 111_195.0).abs() < 1.0);
+        // A degree of longitude shrinks with the cosine of the latitude
+        assert!((distance_m((60.0, 10.0), (60.0, 11.0)) - 55_597.0).abs() < 100.0);
+        assert_eq!(distance_m((1.0, 2.0), (3.0, 4.0)), distance_m((3.0, 4.0), (1.0, 2.0)));
+    }
+
+    #[test]
+    fn links_relative_to_the_report() {
+        let root = env::temp_dir().join(format!("mission_report_{}", timestamp_ms()));
+        let session = root.join("session");
+        fs::create_dir_all(&session).unwrap();
+        fs::create_dir_all(root.join("reports")).unwrap();
+        let snapshot = session.join("snapshot_0.jpg");
+        File::create(&snapshot).unwrap();
+
+        assert_eq!(relative_path(&snapshot, &session), "snapshot_0.jpg");
+        assert_eq!(relative_path(&snapshot, &root.join("reports")), "../session/snapshot_0.jpg");
+        // Left as it is when there's no such folder
+        assert_eq!(relative_path(&snapshot, Path::new("/no/such/folder")), snapshot.to_string_lossy());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn collapses_repeated_commands() {
+        let mut commands = vec![];
+        add_command(&mut commands, &out(1_000_000, HANDSHAKE.to_vec()));
+        add_command(&mut commands, &out(1_000_000, Command::Ping(1, 1000).encode()));
+        add_command(&mut commands, &out(2_000_000, Command::LRpm(10).encode()));
+        add_command(&mut commands, &out(2_100_000, Command::LRpm(10).encode()));
+        // A retransmission and a checksummed copy are still the same command
+        add_command(&mut commands, &out(2_200_000, encode_sequenced(7, &Command::LRpm(10))));
+        add_command(&mut commands, &out(2_300_000, add_checksum(&Command::LRpm(10).encode())));
+        add_command(&mut commands, &out(3_000_000, Command::Brake.encode()));
+        add_command(&mut commands, &out(4_000_000, Command::LRpm(10).encode()));
+        add_command(&mut commands, &Record { timestamp_us: 5_000_000, direction: Direction::In, data: b"x".to_vec() });
+
+        let sent: Vec<(f64, &str, u32)> = commands.iter().map(|c| (c.utc_s, c.text.as_str(), c.count)).collect();
+        assert_eq!(sent, vec![(2.0, "LRpm(10)", 4), (3.0, "Brake", 1), (4.0, "LRpm(10)", 1)]);
+    }
+
+    #[test]
+    fn reports_changes_of_alarm_level() {
+        let mut mission = Mission::default();
+        mission.logs.push(volt_log(&[(1.0, 50.0), (2.0, 47.0), (3.0, 46.0), (4.0, 44.0), (5.0, 44.5),
+                                     (6.0, 49.0), (7.0, 49.5)]));
+
+        let alarms = find_alarms(&mission);
+        let alarms: Vec<(f64, &str, f64, Level)> =
+            alarms.iter().map(|a| (a.utc_s, a.column.as_str(), a.value, a.level)).collect();
+        assert_eq!(alarms, vec![(2.0, "h48_v", 47.0, Level::Yellow),
+                                (4.0, "h48_v", 44.0, Level::Red),
+                                (6.0, "h48_v", 49.0, Level::Green)]);
+    }
+
+    #[test]
+    fn sorts_alarms_with_bad_times() {
+        let mut mission = Mission::default();
+        mission.logs.push(volt_log(&[(::std::f64::NAN, 40.0), (2.0, 50.0), (1.0, 40.0)]));
+        assert_eq!(find_alarms(&mission).len(), 3);
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+        assert_eq!(escape("plain"), "plain");
+    }
+
+    #[test]
+    fn plots_empty_and_single_point_series() {
+        assert_eq!(line_plot(&[], &[]), "<p>No data.</p>\n");
+        assert_eq!(line_plot(&[("h48_v".to_string(), vec![])], &[]), "<p>No data.</p>\n");
+
+        let svg = line_plot(&[("h48_v".to_string(), vec![(1000.0, 48.0)])], &[(45.0, Level::Red)]);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<polyline"));
+        assert!(!svg.contains("NaN") && !svg.contains("inf"), "{}", svg);
+    }
+}
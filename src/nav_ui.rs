@@ -1,10 +1,11 @@
 use std::ascii::AsciiExt;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
 use std::net::UdpSocket;
 use std::ops::DerefMut;
 use std::sync::mpsc::Sender;
+use std::time::Duration;
 
 use conrod::{
     self,
@@ -33,13 +34,66 @@ use gfx_device_gl;
 use piston_window::{self, Glyphs, Key};
 use time;
 
+use rover_common::{MissionTime, OutQueue};
+use rover_common::capture::Capture;
+use rover_common::frame::FrameError;
+use rover_common::gps::{format_latitude, format_longitude};
+use rover_common::imu::pitch_roll_heading;
+use rover_common::integrity::IntegrityStats;
+use rover_common::layout::{Area, Column, Layout, Row};
+use rover_common::link::LinkMonitor;
+use rover_common::protocol::{add_checksum, parse_telemetry, Command, TelemetryMsg};
+use rover_common::receiver::PacketReceiver;
+use rover_common::replay::{Replay, ReplayUpdate};
+use rover_common::session::Session;
+use rover_common::widgets::{self, LayoutEditorEvent, LayoutEditorIds, MissionTimerEvent, MissionTimerIds, ReplayControlIds};
+
 use conrod_config;
 use imu;
-use video_stream::VideoMsg;
+use reliable::ReliableSender;
+use video_stream::{self, VideoMsg};
+
+const DEAD_MAN_RESEND_MS: i64 = 200; // Re-send the drive command this often while driving
+const DEAD_MAN_TTL_MS: u32 = 600; // The rover stops if it hears nothing for this long
+const RECORDING_FINISH_TIMEOUT_MS: u64 = 5000; // How long to wait for recordings when closing
+
+/// Panels the videos are drawn in. Clicking the second or third swaps its video with the main one.
+pub const VIDEO_PANELS: [&'static str; 3] = ["main_video", "second_video", "third_video"];
+
+const PANELS: [&'static str; 11] = ["status", "imu", "gps", "drive", "camera", "sadl", "command", "history",
+                                    "main_video", "second_video", "third_video"];
+
+// Status and readings on the left, the controls next to them and the videos on the right, with the
+// command line and its history below
+fn default_layout() -> Layout {
+    fn column(width: f64, panels: &[&str]) -> Column {
+        Column { width: width, panels: panels.iter().map(|panel| panel.to_string()).collect() }
+    }
 
-enum MissionTime {
-    Paused(time::Duration),
-    Running(time::Tm, time::Duration),
+    Layout {
+        rows: vec![Row { height: 0.6,
+                         columns: vec![column(0.36, &["status", "imu"]),
+                                       column(0.2, &["gps", "drive", "camera", "sadl"]),
+                                       column(0.44, &["main_video"])] },
+                   Row { height: 0.4,
+                         columns: vec![column(0.36, &["command", "history"]),
+                                       column(0.32, &["second_video"]),
+                                       column(0.32, &["third_video"])] }],
+    }
+}
+
+// The videos and the command history take whatever their column has left
+fn panel_height(panel: &str) -> Option<f64> {
+    match panel {
+        "status" => Some(205.0),
+        "imu" => Some(175.0),
+        "gps" => Some(125.0),
+        "drive" => Some(105.0),
+        "camera" => Some(75.0),
+        "sadl" => Some(40.0),
+        "command" => Some(95.0),
+        _ => None,
+    }
 }
 
 pub struct NavigationUi {
@@ -53,6 +107,9 @@ pub struct NavigationUi {
     roll: imu::Roll,
     heading: imu::Heading,
 
+    // Packets that could not be parsed
+    malformed_packets: u64,
+
     // GPS
     latitude: Option<f64>,
     longitude: Option<f64>,
@@ -64,6 +121,8 @@ pub struct NavigationUi {
     pub l_rpm: f32,
     pub r_rpm: f32,
     pub max_rpm: f32,
+    pub dead_man: bool, // Drive with expiring commands that are re-sent while driving
+    last_drive_time: time::Tm,
 
     pub motor_speed: f32,
 
@@ -85,36 +144,58 @@ pub struct NavigationUi {
     pub command_mode: bool,
     command_history: Vec<String>,
 
+    link: LinkMonitor,
+    reliable: ReliableSender,
+    checksums: bool, // Append a checksum to every command
+    integrity: IntegrityStats,
     client: UdpSocket,
+    rover_addr: (String, u16),
     vid0_t: Sender<VideoMsg>,
     vid1_t: Sender<VideoMsg>,
     vid2_t: Sender<VideoMsg>,
-    mission_folder: String,
+    session: Session,
     vid_num: u16,
+    recording: bool,
 
-    out_queue: VecDeque<(time::Tm, time::Duration, Vec<u8>, (String, u16))>, // Outbound packet queue
-    delay: time::Duration,
-    delay_str: String,
+    out_queue: OutQueue,
+    sim_delay: time::Duration, // Artificial delay added to outbound packets
+    sim_delay_str: String,
+    replaying: bool, // Showing a replay, so nothing is sent to the rover
+
+    layout: Layout,
+    layout_path: String, // Where the layout editor saves to
+    panels: Vec<(String, Area)>, // Where the layout put each panel last frame
+    editing_layout: bool,
+    selected_panel: usize, // Index into the layout's panels while editing
 
     image_map: conrod::image::Map<<piston_window::G2d<'static> as Graphics>::Texture>,
 }
 
 impl NavigationUi {
     pub fn new(client: UdpSocket,
+               rover_addr: (String, u16),
                vid0_t: Sender<VideoMsg>,
                vid1_t: Sender<VideoMsg>,
                vid2_t: Sender<VideoMsg>,
-               mission_folder: String) -> NavigationUi {
+               session: Session,
+               command_checksums: bool) -> NavigationUi {
+        let mut link = LinkMonitor::new(rover_addr.clone());
+        link.set_checksums(command_checksums);
+        session.add_artifact("packet_errors.log", "packet_errors", "rover link");
+        let integrity = IntegrityStats::new(Some(session.path("packet_errors.log").as_str()));
+
         NavigationUi {
             bg_color: rgb(0.2, 0.35, 0.45),
 
-            mission_time: MissionTime::Paused(time::Duration::zero()),
+            mission_time: MissionTime::new(),
 
             pitch_roll_heading: None,
             pitch: imu::Roll::new(),
             roll: imu::Roll::new(),
             heading: imu::Heading::new(),
 
+            malformed_packets: 0,
+
             latitude: None,
             longitude: None,
             speed: None,
@@ -124,6 +205,8 @@ impl NavigationUi {
             l_rpm: 0.0,
             r_rpm: 0.0,
             max_rpm: 100.0,
+            dead_man: false,
+            last_drive_time: time::now(),
 
             motor_speed: 1.0,
 
@@ -144,16 +227,29 @@ impl NavigationUi {
             command_mode: false,
             command_history: vec![],
 
+            link: link,
+            reliable: ReliableSender::new(),
+            checksums: command_checksums,
+            integrity: integrity,
             client: client,
+            rover_addr: rover_addr,
             vid0_t: vid0_t,
             vid1_t: vid1_t,
             vid2_t: vid2_t,
-            mission_folder: mission_folder,
+            session: session,
             vid_num: 0,
+            recording: false,
 
-            out_queue: VecDeque::new(),
-            delay: time::Duration::seconds(0),
-            delay_str: "".to_string(),
+            out_queue: OutQueue::new(),
+            sim_delay: time::Duration::seconds(0),
+            sim_delay_str: "".to_string(),
+            replaying: false,
+
+            layout: default_layout(),
+            layout_path: String::new(),
+            panels: vec![],
+            editing_layout: false,
+            selected_panel: 0,
 
             image_map: conrod::image::Map::new(),
         }
@@ -165,15 +261,31 @@ impl NavigationUi {
         self.f_pan += self.f_panning*180.0*dt; // 180 degrees per second
         self.f_tilt += self.f_tilting*90.0*dt; // 90 degrees per second
 
+        let driving = self.l_rpm != 0.0 || self.r_rpm != 0.0;
+        if self.dead_man && driving &&
+           (time::now() - self.last_drive_time).num_milliseconds() >= DEAD_MAN_RESEND_MS {
+            self.send_lr_rpm();
+        }
+
+        let delay = self.sim_delay;
+        for packet in self.reliable.retransmissions(delay) {
+            self.queue_packet(delay, packet);
+        }
+
         self.flush_out_queue();
+
+        if let Err(e) = self.link.update(&self.client) {
+            println!("WARNING: Failed to send link heartbeat: {}", e);
+        }
     }
 
     pub fn draw_ui<'a>(&mut self, c: Context,
                           g: &mut gfx_graphics::GfxGraphics<'a, gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
-                          glyph_cache: &mut conrod::backend::piston_window::GlyphCache, ui: &mut conrod_config::Ui) {
+                          glyph_cache: &mut conrod::backend::piston_window::GlyphCache, ui: &mut conrod_config::Ui,
+                          replay: Option<&mut Replay>) -> Option<ReplayUpdate> {
         use graphics::Transformed;
 
-        self.set_widgets(&mut ui.set_widgets());
+        let replay_update = self.set_widgets(&mut ui.set_widgets(), replay);
 
         // Draw our UI!
         conrod::backend::piston_window::draw(c, g, ui.draw(),
@@ -182,139 +294,255 @@ impl NavigationUi {
                                              |img| img);
 
         // Draw other stuff
-        self.pitch.draw(c.trans(20.0, 215.0), g);
-        self.roll.draw(c.trans(170.0, 215.0), g);
-        self.heading.draw(c.trans(320.0, 215.0), g);
+        if let Some(area) = self.panel_area("imu") {
+            let rect = area.to_window(ui.win_w, ui.win_h);
+            self.pitch.draw(c.trans(rect[0] + 20.0, rect[1] + 30.0), g);
+            self.roll.draw(c.trans(rect[0] + 170.0, rect[1] + 30.0), g);
+            self.heading.draw(c.trans(rect[0] + 320.0, rect[1] + 30.0), g);
+        }
+
+        replay_update
     }
 
-    pub fn set_widgets(&mut self, ui: &mut conrod_config::UiCell) {
-        use std::cmp;
+    /// Where a panel was last laid out, e.g. to draw a video in it. None if the layout doesn't
+    /// have it.
+    pub fn panel_area(&self, panel: &str) -> Option<Area> {
+        self.panels.iter().find(|&&(ref name, _)| name == panel).map(|&(_, area)| area)
+    }
 
-        let time_now = time::now();
+    /// Arrange the panels by `layout`, or the default one if None. The layout editor (F2) saves to
+    /// `path`.
+    pub fn set_layout(&mut self, layout: Option<Layout>, path: String) {
+        let mut layout = layout.unwrap_or_else(default_layout);
+        let unknown: Vec<String> =
+            layout.panels().into_iter().filter(|panel| !PANELS.contains(panel)).map(|panel| panel.to_string()).collect();
+        for panel in unknown {
+            println!("WARNING: Unknown navigation panel '{}', expected one of {}", panel, PANELS.join(", "));
+            layout.remove(&panel);
+        }
+        self.layout = layout;
+        self.layout_path = path;
+    }
 
+    fn save_layout(&self) {
+        match self.layout.save(&self.layout_path, "navigation") {
+            Ok(()) => println!("Saved the layout to {}", self.layout_path),
+            Err(e) => println!("WARNING: Failed to save the layout to {}: {}", self.layout_path, e),
+        }
+    }
+
+    /// Returns the packets to play when the replay controls were used.
+    pub fn set_widgets(&mut self, ui: &mut conrod_config::UiCell,
+                       replay: Option<&mut Replay>) -> Option<ReplayUpdate> {
         // Draw the background.
         widget::Canvas::new()
             .color(self.bg_color)
             .set(CANVAS, ui);
 
-        // Local time
-        Text::new(format!("{}", time_now.strftime("Local  %x  %X").unwrap()).as_str())
-            .x_y((-ui.win_w / 2.0) + 100.0, (ui.win_h / 2.0) - 10.0)
-            .font_size(16)
-            .color(self.bg_color.plain_contrast())
-            .set(LOCAL_TIME, ui);
-
-        // UTC time
-        Text::new(format!("{}", time_now.to_utc().strftime("%Z  %x  %X").unwrap()).as_str())
-            .x_y((-ui.win_w / 2.0) + 104.0, (ui.win_h / 2.0) - 30.0)
-            .font_size(16)
-            .color(self.bg_color.plain_contrast())
-            .set(UTC_TIME, ui);
-
-        // Mission time label
-        let mission_time =
-            match self.mission_time {
-                MissionTime::Paused(t) => t,
-                MissionTime::Running(start_time, extra_time) =>
-                    (time::now() - start_time) + extra_time
-            };
-        let total_days = mission_time.num_days();
-        let total_hours = mission_time.num_hours();
-        let total_minutes = mission_time.num_minutes();
-        let total_seconds = mission_time.num_seconds();
-
-        let days = total_days;
-        let hours = total_hours - total_days*24;
-        let minutes = total_minutes - total_hours*60;
-        let seconds = total_seconds - total_minutes*60;
-        Text::new(format!("Mission Time: {}:{}:{}:{}", days, hours, minutes, seconds).as_str())
-            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 70.0)
-            .font_size(20)
-            .color(self.bg_color.plain_contrast())
-            .set(MISSION_TIME_LABEL, ui);
-
-        // Mission start/pause button
-        let mission_start_text =
-            match self.mission_time {
-                MissionTime::Paused(_) => "Start",
-                MissionTime::Running(_, _) => "Pause",
+        // The layout editor's toolbar gets a strip along the bottom, the panels the rest
+        let (area, editor_area) =
+            if self.editing_layout {
+                let (rest, strip) = Area::window(ui.win_w, ui.win_h).cut_bottom(40.0);
+                (rest, Some(strip))
+            } else {
+                (Area::window(ui.win_w, ui.win_h), None)
             };
-        if Button::new()
-            .w_h(100.0, 30.0)
-            .x_y((-ui.win_w / 2.0) + 55.0, (ui.win_h / 2.0) - 100.0)
-            .rgb(0.3, 0.8, 0.3)
-            .border(1.0)
-            .label(mission_start_text)
-            .set(MISSION_START_BUTTON, ui)
-            .was_clicked()
-        {
-            match self.mission_time {
-                MissionTime::Paused(current_time) => {
-                    self.mission_time = MissionTime::Running(time::now(), current_time);
-
-                    self.vid0_t.send(VideoMsg::Start(format!("mission_data/{}/forward{}.mp4", self.mission_folder, self.vid_num)));
-                    self.vid1_t.send(VideoMsg::Start(format!("mission_data/{}/reverse{}.mkv", self.mission_folder, self.vid_num)));
-                    self.vid2_t.send(VideoMsg::Start(format!("mission_data/{}/hazard{}.mkv", self.mission_folder, self.vid_num)));
-
-                    self.vid_num += 1;
+        self.panels = self.layout.arrange(area, panel_height);
+
+        // A replay has nothing to send, so its controls take the place of the command line
+        let mut replay = replay;
+        let mut replay_update = None;
+        for (panel, area) in self.panels.clone() {
+            match panel.as_str() {
+                "status" => self.set_status_panel(ui, &area),
+                "imu" => self.set_imu_panel(ui, &area),
+                "gps" => self.set_gps_panel(ui, &area),
+                "drive" => self.set_drive_panel(ui, &area),
+                "camera" => self.set_camera_panel(ui, &area),
+                "sadl" => self.set_sadl_panel(ui, &area),
+                "command" => {
+                    match replay.take() {
+                        Some(replay) => {
+                            let replay_control_ids = ReplayControlIds {
+                                play_button: REPLAY_PLAY_BUTTON,
+                                step_button: REPLAY_STEP_BUTTON,
+                                speed_button: REPLAY_SPEED_BUTTON,
+                                scrub_slider: REPLAY_SCRUB_SLIDER,
+                                label: REPLAY_LABEL,
+                            };
+                            replay_update = widgets::replay_controls(ui, &area, &replay_control_ids, replay,
+                                                                     self.bg_color.plain_contrast());
+                        },
+                        None => self.set_command_widgets(ui, &area),
+                    }
                 },
-                MissionTime::Running(start_time, extra_time) => {
-                    self.mission_time = MissionTime::Paused((time::now() - start_time) + extra_time);
+                "history" => self.set_history_panel(ui, &area),
+                _ => { }, // The videos are drawn by piston
+            }
+        }
 
-                    self.vid0_t.send(VideoMsg::Stop);
-                    self.vid1_t.send(VideoMsg::Stop);
-                    self.vid2_t.send(VideoMsg::Stop);
-                },
+        if let Some(editor_area) = editor_area {
+            let layout_editor_ids = LayoutEditorIds {
+                outline: LAYOUT_OUTLINE,
+                label: LAYOUT_PANEL_LABEL,
+                next_button: LAYOUT_NEXT_BUTTON,
+                left_button: LAYOUT_LEFT_BUTTON,
+                right_button: LAYOUT_RIGHT_BUTTON,
+                up_button: LAYOUT_UP_BUTTON,
+                down_button: LAYOUT_DOWN_BUTTON,
+                save_button: LAYOUT_SAVE_BUTTON,
+                done_button: LAYOUT_DONE_BUTTON,
             };
+            match widgets::layout_editor(ui, &editor_area, &layout_editor_ids, &mut self.layout, &self.panels,
+                                         &mut self.selected_panel, self.bg_color.plain_contrast()) {
+                Some(LayoutEditorEvent::Save) => self.save_layout(),
+                Some(LayoutEditorEvent::Done) => self.editing_layout = false,
+                None => { },
+            }
         }
 
-        // Mission reset button
-        if Button::new()
-            .w_h(100.0, 30.0)
-            .x_y((-ui.win_w / 2.0) + 160.0, (ui.win_h / 2.0) - 100.0)
-            .rgb(0.3, 0.8, 0.3)
-            .border(1.0)
-            .label("Reset")
-            .set(MISSION_RESET_BUTTON, ui)
-            .was_clicked()
-        {
-            self.mission_time = MissionTime::Paused(time::Duration::zero());
+        replay_update
+    }
+
+    fn set_status_panel(&mut self, ui: &mut conrod_config::UiCell, area: &Area) {
+        // Local and UTC time
+        widgets::clock(ui, area, LOCAL_TIME, UTC_TIME, self.bg_color.plain_contrast());
+
+        // Link status
+        let (r, g, b) = self.link.status_rgb();
+        Text::new(self.link.status_text().as_str())
+            .xy(area.at(150.0, 48.0))
+            .font_size(14)
+            .color(rgb(r, g, b))
+            .set(LINK_STATUS, ui);
+
+        let mission_timer_ids = MissionTimerIds {
+            label: MISSION_TIME_LABEL,
+            start_button: MISSION_START_BUTTON,
+            reset_button: MISSION_RESET_BUTTON,
+        };
+        match widgets::mission_timer(ui, area, &mission_timer_ids, &mut self.mission_time, self.bg_color.plain_contrast()) {
+            Some(MissionTimerEvent::Started) => {
+                self.start_recording(&self.vid0_t, "forward", "mp4");
+                self.start_recording(&self.vid1_t, "reverse", "mkv");
+                self.start_recording(&self.vid2_t, "hazard", "mkv");
+
+                self.vid_num += 1;
+                self.recording = true;
+            },
+            Some(MissionTimerEvent::Paused) => self.stop_recording(),
+            Some(MissionTimerEvent::Reset) | None => { },
         }
 
-        // Time delay
-        Text::new("Time Delay:")
-            .x_y((-ui.win_w / 2.0) + 70.0, (ui.win_h / 2.0) - 150.0)
+        // Measured communication delay
+        Text::new(self.link.delay_text().as_str())
+            .xy(area.at(150.0, 128.0))
+            .font_size(14)
+            .color(self.bg_color.plain_contrast())
+            .set(COMM_DELAY, ui);
+
+        // Simulated time delay
+        Text::new("Sim Delay (s):")
+            .xy(area.at(70.0, 150.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
-            .set(TIME_DELAY, ui);
+            .set(SIM_DELAY_LABEL, ui);
 
-        let mut new_delay = false;
-        for event in TextBox::new(&mut self.delay_str)
+        for event in TextBox::new(&mut self.sim_delay_str)
             .font_size(16)
             .w_h(50.0, 20.0)
-            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 150.0)
+            .xy(area.at(150.0, 150.0))
             .border(1.0)
             .border_color(self.bg_color.invert().plain_contrast())
             .color(self.bg_color.invert())
-            .set(TIME_DELAY_VALUE, ui)
+            .set(SIM_DELAY_VALUE, ui)
         {
             match event {
                 widget::text_box::Event::Enter => {
                     if self.command_mode {
-                        if let Ok(delay) = self.delay_str.parse() {
-                            self.delay = time::Duration::seconds(delay);
+                        if let Ok(delay) = self.sim_delay_str.parse() {
+                            self.sim_delay = time::Duration::seconds(delay);
                         }
                     }
                 },
-                widget::text_box::Event::Update(string) => self.delay_str = string,
+                widget::text_box::Event::Update(string) => self.sim_delay_str = string,
             }
         }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // IMU section
+        // Dead-man mode toggle
+        let dead_man_label = if self.dead_man { "Dead-man: On" } else { "Dead-man: Off" };
+        if Button::new()
+            .w_h(110.0, 20.0)
+            .xy(area.at(250.0, 150.0))
+            .rgb(0.3, 0.8, 0.3)
+            .border(1.0)
+            .label(dead_man_label)
+            .label_font_size(14)
+            .set(DEAD_MAN_TOGGLE_BUTTON, ui)
+            .was_clicked()
+        {
+            self.dead_man = !self.dead_man;
+        }
 
+        // Malformed packets
+        let packet_errors_color =
+            if self.malformed_packets > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(format!("Bad Packets: {}", self.malformed_packets).as_str())
+            .xy(area.at(70.0, 170.0))
+            .font_size(14)
+            .color(packet_errors_color)
+            .set(PACKET_ERRORS, ui);
+
+        // Reliable delivery toggle and unacknowledged commands
+        let reliable_label = if self.reliable.enabled() { "Reliable: On" } else { "Reliable: Off" };
+        if Button::new()
+            .w_h(110.0, 20.0)
+            .xy(area.at(200.0, 170.0))
+            .rgb(0.3, 0.8, 0.3)
+            .border(1.0)
+            .label(reliable_label)
+            .label_font_size(14)
+            .set(RELIABLE_TOGGLE_BUTTON, ui)
+            .was_clicked()
+        {
+            let enabled = !self.reliable.enabled();
+            self.reliable.set_enabled(enabled);
+        }
+
+        let unacked_color =
+            if self.reliable.unacked() > 0 || self.reliable.failed() > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(format!("Unacked: {}  Failed: {}", self.reliable.unacked(), self.reliable.failed()).as_str())
+            .xy(area.at(100.0, 190.0))
+            .font_size(14)
+            .color(unacked_color)
+            .set(UNACKED_COMMANDS, ui);
+
+        // Packets that failed their checksum in either direction
+        let corrupt_color =
+            if self.integrity.corrupted_in() > 0 || self.integrity.corrupted_out() > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(self.integrity.text().as_str())
+            .xy(area.at(260.0, 190.0))
+            .font_size(14)
+            .color(corrupt_color)
+            .set(CORRUPT_PACKETS, ui);
+    }
+
+    // The pitch, roll and heading dials are drawn by draw_ui, between the label and the values
+    fn set_imu_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("IMU")
-            .x_y((-ui.win_w / 2.0) + 100.0, (ui.win_h / 2.0) - 190.0)
+            .xy(area.at(100.0, 10.0))
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(IMU_LABEL, ui);
@@ -332,13 +560,13 @@ impl NavigationUi {
         // IMU pitch
 
         Text::new(format!("Pitch").as_str())
-            .x_y((-ui.win_w / 2.0) + 40.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(40.0, 165.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(IMU_PITCH_LABEL, ui);
 
         Text::new(pitch.as_str())
-            .x_y((-ui.win_w / 2.0) + 120.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(120.0, 165.0))
             .font_size(16)
             .color(imu_color)
             .set(IMU_PITCH_VALUE, ui);
@@ -346,13 +574,13 @@ impl NavigationUi {
         // IMU roll
 
         Text::new(format!("Roll").as_str())
-            .x_y((-ui.win_w / 2.0) + 190.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(190.0, 165.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(IMU_ROLL_LABEL, ui);
 
         Text::new(roll.as_str())
-            .x_y((-ui.win_w / 2.0) + 250.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(250.0, 165.0))
             .font_size(16)
             .color(imu_color)
             .set(IMU_ROLL_VALUE, ui);
@@ -360,22 +588,21 @@ impl NavigationUi {
         // IMU heading
 
         Text::new("Heading")
-            .x_y((-ui.win_w / 2.0) + 340.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(340.0, 165.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(IMU_HEADING_LABEL, ui);
 
         Text::new(heading.as_str())
-            .x_y((-ui.win_w / 2.0) + 420.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(420.0, 165.0))
             .font_size(16)
             .color(imu_color)
             .set(IMU_HEADING_VALUE, ui);
+    }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // GPS section
-
+    fn set_gps_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("GPS")
-            .x_y((-ui.win_w / 2.0) + 400.0, (ui.win_h / 2.0) - 10.0)
+            .xy(area.at(120.0, 10.0))
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(GPS_LABEL, ui);
@@ -383,14 +610,11 @@ impl NavigationUi {
         // Latitude label
         let (latitude, latitude_color) =
             match self.latitude {
-                Some(lat) => {
-                    let (deg, min, sec) = gps_degrees_to_dms(lat);
-                    (format!("{}  {}' {:.*}\" N", deg, min, 2, sec), rgb(0.0, 1.0, 0.0))
-                },
+                Some(lat) => (format_latitude(lat), rgb(0.0, 1.0, 0.0)),
                 None => ("NO DATA".to_string(), rgb(1.0, 0.0, 0.0)),
             };
         Text::new(latitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 420.0, (ui.win_h / 2.0) - 35.0)
+            .xy(area.at(120.0, 35.0))
             .font_size(16)
             .color(latitude_color)
             .set(LATITUDE_LABEL, ui);
@@ -398,15 +622,11 @@ impl NavigationUi {
         // Longitude label
         let (longitude, longitude_color) =
             match self.longitude {
-                Some(lng) => {
-                    //(format!("{0:.6} W", lng), rgb(0.0, 1.0, 0.0))
-                    let (deg, min, sec) = gps_degrees_to_dms(lng);
-                    (format!("{}  {}' {:.*}\" W", deg, min, 2, sec), rgb(0.0, 1.0, 0.0))
-                },
+                Some(lng) => (format_longitude(lng), rgb(0.0, 1.0, 0.0)),
                 None => ("NO DATA".to_string(), rgb(1.0, 0.0, 0.0)),
             };
         Text::new(longitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 420.0, (ui.win_h / 2.0) - 55.0)
+            .xy(area.at(120.0, 55.0))
             .font_size(16)
             .color(longitude_color)
             .set(LONGITUDE_LABEL, ui);
@@ -420,7 +640,7 @@ impl NavigationUi {
                 None => ("NO DATA".to_string(), rgb(1.0, 0.0, 0.0)),
             };
         Text::new(speed.as_str())
-            .x_y((-ui.win_w / 2.0) + 400.0, (ui.win_h / 2.0) - 75.0)
+            .xy(area.at(120.0, 75.0))
             .font_size(16)
             .color(speed_color)
             .set(SPEED_LABEL, ui);
@@ -434,7 +654,7 @@ impl NavigationUi {
                 None => ("NO DATA".to_string(), rgb(1.0, 0.0, 0.0)),
             };
         Text::new(altitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 400.0, (ui.win_h / 2.0) - 95.0)
+            .xy(area.at(120.0, 95.0))
             .font_size(16)
             .color(altitude_color)
             .set(ALTITUDE_LABEL, ui);
@@ -448,17 +668,17 @@ impl NavigationUi {
                 None => ("NO DATA".to_string(), rgb(1.0, 0.0, 0.0)),
             };
         Text::new(angle.as_str())
-            .x_y((-ui.win_w / 2.0) + 400.0, (ui.win_h / 2.0) - 115.0)
+            .xy(area.at(120.0, 115.0))
             .font_size(16)
             .color(angle_color)
             .set(ANGLE_LABEL, ui);
+    }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-
+    fn set_drive_panel(&mut self, ui: &mut conrod_config::UiCell, area: &Area) {
         // Left RPM slider
         if let Some(new_rpm) = Slider::new(self.l_rpm, -self.max_rpm, self.max_rpm)
             .w_h(150.0, 30.0)
-            .x_y(275.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 145.0)
+            .xy(area.at(80.0, 20.0))
             .rgb(0.5, 0.3, 0.6)
             .border(1.0)
             .label("L Motor")
@@ -471,7 +691,7 @@ impl NavigationUi {
         // Right RPM slider
         if let Some(new_rpm) = Slider::new(self.r_rpm, -self.max_rpm, self.max_rpm)
             .w_h(150.0, 30.0)
-            .x_y(275.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 185.0)
+            .xy(area.at(80.0, 55.0))
             .rgb(0.5, 0.3, 0.6)
             .border(1.0)
             .label("R Motor")
@@ -483,8 +703,8 @@ impl NavigationUi {
 
         // Stop button
         if Button::new()
-            .w_h(100.0, 30.0)
-            .x_y(455.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 145.0)
+            .w_h(80.0, 30.0)
+            .xy(area.at(205.0, 20.0))
             .rgb(1.0, 0.0, 0.0)
             .border(1.0)
             .label("Stop")
@@ -501,7 +721,7 @@ impl NavigationUi {
         // Motor speed slider
         if let Some(new_speed) = Slider::new(self.motor_speed, 0.0, 1.0)
             .w_h(150.0, 30.0)
-            .x_y(435.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 185.0)
+            .xy(area.at(80.0, 90.0))
             .rgb(0.5, 0.3, 0.6)
             .border(1.0)
             .label("Motor Speed")
@@ -510,12 +730,14 @@ impl NavigationUi {
         {
             self.motor_speed = new_speed;
         }
-        
+    }
+
+    fn set_camera_panel(&mut self, ui: &mut conrod_config::UiCell, area: &Area) {
         // Camera pan slider
         self.f_pan = self.f_pan.max(0.0).min(180.0);
         if let Some(new_pan) = Slider::new(self.f_pan, 0.0, 180.0)
             .w_h(150.0, 30.0)
-            .x_y((ui.win_w / 2.0) - 425.0, (ui.win_h / 2.0) - 425.0)
+            .xy(area.at(80.0, 20.0))
             .rgb(0.5, 0.3, 0.6)
             .border(1.0)
             .label("Pan")
@@ -529,7 +751,7 @@ impl NavigationUi {
         self.f_tilt = self.f_tilt.max(60.0).min(180.0);
         if let Some(new_tilt) = Slider::new(self.f_tilt, 60.0, 180.0)
             .w_h(150.0, 30.0)
-            .x_y((ui.win_w / 2.0) - 270.0, (ui.win_h / 2.0) - 425.0)
+            .xy(area.at(80.0, 55.0))
             .rgb(0.5, 0.3, 0.6)
             .border(1.0)
             .label("Tilt")
@@ -540,8 +762,8 @@ impl NavigationUi {
         }
 
         if Button::new()
-            .w_h(100.0, 30.0)
-            .x_y((ui.win_w / 2.0) - 350.0, (ui.win_h / 2.0) - 470.0)
+            .w_h(80.0, 30.0)
+            .xy(area.at(205.0, 38.0))
             .rgb(0.3, 0.8, 0.3)
             .border(1.0)
             .label("Snapshot")
@@ -550,16 +772,16 @@ impl NavigationUi {
         {
             self.want_snapshot = true;
         }
+    }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // SADL
+    fn set_sadl_panel(&mut self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("SADL")
-            .x_y((ui.win_w / 2.0) - 660.0, (ui.win_h / 2.0) - 465.0)
+            .xy(area.at(35.0, 20.0))
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(SADL_LABEL, ui);
         if Button::new()
-            .x_y((ui.win_w / 2.0) - 590.0, (ui.win_h / 2.0) - 465.0)
+            .xy(area.at(110.0, 20.0))
             .w_h(60.0, 30.0)
             .rgb(0.3, 0.8, 0.3)
             .border(1.0)
@@ -571,7 +793,7 @@ impl NavigationUi {
             self.send_sadl();
         }
         if Button::new()
-            .x_y((ui.win_w / 2.0) - 525.0, (ui.win_h / 2.0) - 465.0)
+            .xy(area.at(175.0, 20.0))
             .w_h(60.0, 30.0)
             .rgb(0.3, 0.8, 0.3)
             .border(1.0)
@@ -582,11 +804,31 @@ impl NavigationUi {
             self.sadl = -100.0;
             self.send_sadl();
         }
+    }
+
+    fn set_history_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
+        // Leave room for the scrollbar on the right
+        let list_w = (area.w - 10.0).max(0.0);
+        let (mut items, scrollbar) = widget::List::new(self.command_history.len(), 30.0)
+            .xy(area.at(list_w / 2.0, area.h / 2.0))
+            .w_h(list_w, area.h)
+            .scrollbar_next_to()
+            .set(COMMAND_HISTORY, ui);
+
+        while let Some(item) = items.next(ui) {
+            let i = item.i;
+            let text = Text::new(&self.command_history[self.command_history.len() - i - 1])
+                .color(WHITE)
+                .align_text_left();
+            item.set(text, ui);
+        }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // Command section
+        if let Some(s) = scrollbar { s.set(ui) }
+    }
+
+    fn set_command_widgets(&mut self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("Command")
-            .x_y(110.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 615.0)
+            .xy(area.at(110.0, 15.0))
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(COMMAND_LABEL, ui);
@@ -596,7 +838,7 @@ impl NavigationUi {
             //.enable(self.command_mode)
             .font_size(16)
             .w_h(320.0, 20.0)
-            .x_y(165.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 640.0)
+            .xy(area.at(165.0, 40.0))
             .border(1.0)
             .border_color(self.bg_color.invert().plain_contrast())
             .color(self.bg_color.invert())
@@ -611,7 +853,7 @@ impl NavigationUi {
 
         if Button::new()
             .w_h(100.0, 30.0)
-            .x_y(380.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 640.0)
+            .xy(area.at(380.0, 40.0))
             .rgb(0.3, 0.8, 0.3)
             .border(1.0)
             .label("Send")
@@ -627,13 +869,13 @@ impl NavigationUi {
                 false => "Real-time Mode",
             };
         Text::new(mode_label)
-            .x_y(200.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 675.0)
+            .xy(area.at(200.0, 75.0))
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(MODE_LABEL, ui);
         if Button::new()
             .w_h(150.0, 30.0)
-            .x_y(380.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 675.0)
+            .xy(area.at(380.0, 75.0))
             .rgb(0.3, 0.8, 0.3)
             .border(1.0)
             .label("Toggle Mode")
@@ -642,83 +884,130 @@ impl NavigationUi {
         {
             self.command_mode = !self.command_mode;
         }
+    }
 
-        let (mut items, scrollbar) = widget::List::new(self.command_history.len(), 30.0)
-            .x_y(200.0 - (ui.win_w / 2.0), (ui.win_h / 2.0) - 480.0)
-            .w_h(200.0, 200.0)
-            .scrollbar_next_to()
-            .set(COMMAND_HISTORY, ui);
+    // Tell a video stream to record to the session folder
+    fn start_recording(&self, vid_t: &Sender<VideoMsg>, camera: &str, ext: &str) {
+        let file = format!("{}{}.{}", camera, self.vid_num, ext);
+        vid_t.send(VideoMsg::Start(self.session.path(&file)));
+        self.session.add_artifact(&file, "video", &format!("{} camera", camera));
+    }
 
-        while let Some(item) = items.next(ui) {
-            let i = item.i;
-            let text = Text::new(&self.command_history[self.command_history.len() - i - 1])
-                .color(WHITE)
-                .align_text_left();
-            item.set(text, ui);
+    /// Tell the video streams to finish their recordings.
+    pub fn stop_recording(&mut self) {
+        if self.recording {
+            self.vid0_t.send(VideoMsg::Stop);
+            self.vid1_t.send(VideoMsg::Stop);
+            self.vid2_t.send(VideoMsg::Stop);
+            self.recording = false;
         }
+    }
 
-        if let Some(s) = scrollbar { s.set(ui) }
+    /// Stop recording and wait for the recordings to be written out. Returns false if some
+    /// weren't finished in time.
+    pub fn finish_recordings(&mut self) -> bool {
+        self.stop_recording();
+        video_stream::wait_for_recordings(Duration::from_millis(RECORDING_FINISH_TIMEOUT_MS))
+    }
+
+    /// Show a replay. Nothing is sent to the rover and packet errors aren't logged a second time.
+    pub fn set_replaying(&mut self) {
+        self.replaying = true;
+        self.integrity = IntegrityStats::new(None);
+    }
+
+    /// Forget everything heard from the rover, for starting a replay over.
+    pub fn clear_telemetry(&mut self) {
+        self.pitch_roll_heading = None;
+        self.pitch = imu::Roll::new();
+        self.roll = imu::Roll::new();
+        self.heading = imu::Heading::new();
+
+        self.malformed_packets = 0;
+        self.integrity = IntegrityStats::new(None);
+        self.link = LinkMonitor::new(self.rover_addr.clone());
+
+        self.latitude = None;
+        self.longitude = None;
+        self.speed = None;
+        self.altitude = None;
+        self.angle = None;
+    }
+
+    /// Record every packet we send to the rover.
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.out_queue.set_capture(capture.clone());
+        self.link.set_capture(capture);
+    }
+
+    pub fn handle_dropped(&mut self, error: FrameError) {
+        println!("WARNING: Dropped datagram: {}", error);
+        self.integrity.on_dropped(&error);
+    }
+
+    pub fn handle_net_error(&mut self, error: String) {
+        println!("WARNING: {}", error);
+        self.link.on_error(error);
+    }
+
+    /// Replace our socket after it has kept failing.
+    pub fn rebind(&mut self, receiver: &mut PacketReceiver) {
+        match receiver.rebind(&mut self.client) {
+            Ok(()) => {
+                println!("Rebound the rover socket");
+                self.link.clear_error();
+            },
+            Err(e) => self.handle_net_error(format!("Failed to rebind socket: {}", e)),
+        }
     }
 
     pub fn handle_packet(&mut self, packet: String) {
         //println!("{}", packet);
 
-        let packets = packet.split("|");
-
-        for packet in packets {
-            let packet_parts: Vec<String> = packet.split(":").map(|s| s.to_string()).collect();
+        self.link.on_packet();
 
-            match packet_parts[0].as_str() {
-                "GPS" => {
-                    if packet_parts.len() == 6 {
-                        self.latitude = packet_parts[1].parse().ok();
-                        self.longitude = packet_parts[2].parse().ok();
-                        self.speed = packet_parts[3].parse().ok();
-                        self.altitude = packet_parts[4].parse().ok();
-                        self.angle = packet_parts[5].parse().ok();
-                    }
-                },
-                "IMU" => {
-                    let ax: f64 = packet_parts[1].parse().unwrap();
-                    let ay: f64 = packet_parts[2].parse().unwrap();
-                    let az: f64 = packet_parts[3].parse().unwrap();
-
-                    let mx: f64 = packet_parts[7].parse().unwrap();
-                    let my: f64 = packet_parts[8].parse().unwrap();
-                    let mz: f64 = packet_parts[9].parse().unwrap();
-
-                    let (ax, ay, az) = (ay, -az, ax);
-                    let (mx, my, mz) = (my, -mz, mx);
-
-                    let roll = f64::atan2(ay, az);
-                    let pitch = f64::atan2(-ax, ay*f64::sin(roll) + az*f64::cos(roll));
-                    let heading = f64::atan2(mz*f64::sin(roll) - my*f64::cos(roll),
-                                             mx*f64::cos(pitch) + my*f64::sin(pitch)*f64::sin(roll) + mz*f64::sin(pitch)*f64::cos(roll));
-                    let mut roll = roll.to_degrees() + 180.0;
-                    let pitch = pitch.to_degrees();
-                    let heading = heading.to_degrees();
-
-                    let mut heading = heading;
-                    if heading < 0.0 {
-                        heading += 360.0;
-                    }
-                    heading = 360.0 - heading;
-                    if roll >= 180.0 {
-                        roll -= 360.0;
-                    }
-                    self.pitch_roll_heading = Some((pitch, roll, heading));
-                    self.pitch.set_angle(-pitch);
-                    self.roll.set_angle(roll);
-                    self.heading.set_angle(heading);
+        for msg in parse_telemetry(packet.as_str()) {
+            match msg {
+                Ok(msg) => self.handle_msg(msg),
+                Err(e) => {
+                    println!("WARNING: Malformed packet: {}", e);
+                    self.malformed_packets += 1;
                 },
-                _ => { /*println!("WARNING: Unknown packet ID: {}", packet_parts[0])*/ },
             }
         }
     }
 
+    fn handle_msg(&mut self, msg: TelemetryMsg) {
+        match msg {
+            TelemetryMsg::Gps { latitude, longitude, speed, altitude, angle } => {
+                self.latitude = latitude;
+                self.longitude = longitude;
+                self.speed = speed;
+                self.altitude = altitude;
+                self.angle = angle;
+            },
+            TelemetryMsg::Imu { accel, mag, .. } => {
+                let (pitch, roll, heading) = pitch_roll_heading(accel, mag);
+                self.pitch_roll_heading = Some((pitch, roll, heading));
+                self.pitch.set_angle(-pitch);
+                self.roll.set_angle(roll);
+                self.heading.set_angle(heading);
+            },
+            TelemetryMsg::Pong { seq, sent_ms, rover_ms } => self.link.on_pong(seq, sent_ms, rover_ms),
+            TelemetryMsg::Ack { seq: Some(seq), .. } => self.reliable.on_ack(seq),
+            TelemetryMsg::BadCommands(count) => self.integrity.on_rover_report(count),
+            _ => { },
+        }
+    }
+
     pub fn on_key_pressed<'a>(&mut self, key: Key) {
         use piston_window::Key::*;
 
+        if key == F2 {
+            self.editing_layout = !self.editing_layout;
+            return;
+        }
+
         if self.command_mode {
             return;
         }
@@ -844,6 +1133,23 @@ impl NavigationUi {
         }
     }
 
+    /// Stop anything that is moving because of a held key, the key release won't reach us once
+    /// the window has lost focus.
+    pub fn on_focus_lost(&mut self) {
+        if self.l_rpm != 0.0 || self.r_rpm != 0.0 {
+            println!("WARNING: Window lost focus, stopping the rover");
+            self.l_rpm = 0.0;
+            self.r_rpm = 0.0;
+            self.send_lr_rpm();
+        }
+        if self.sadl != 0.0 {
+            self.sadl = 0.0;
+            self.send_sadl();
+        }
+        self.f_panning = 0.0;
+        self.f_tilting = 0.0;
+    }
+
     pub fn try_update_l_rpm(&mut self, l_rpm: f32) {
         if (l_rpm - self.l_rpm).abs() > 5.0 {
             self.l_rpm = l_rpm;
@@ -880,35 +1186,45 @@ impl NavigationUi {
     }
 
     pub fn send_brake(&mut self) {
-        let delay = self.delay;
-        self.queue_packet(delay, vec![b'G'], ("10.10.153.8".to_string(), 30001));
+        let packet = self.reliable.send(Command::Brake, true);
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
     }
 
     pub fn send_l_rpm(&mut self) {
-        let packet = format!("A{}|", self.l_rpm as i32);
-        let delay = self.delay;
-        self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+        let packet = self.reliable.send(Command::LRpm(self.l_rpm as i32), false);
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
     }
 
     pub fn send_r_rpm(&mut self) {
-        let packet = format!("B{}|", self.r_rpm as i32);
-        let delay = self.delay;
-        self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+        let packet = self.reliable.send(Command::RRpm(self.r_rpm as i32), false);
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
     }
 
     pub fn send_lr_rpm(&mut self) {
-        let packet = format!("H{}|{}|", self.l_rpm as i32, self.r_rpm as i32);
-        let delay = self.delay;
-        self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+        // Stopping is critical, driving isn't
+        let stop = self.l_rpm == 0.0 && self.r_rpm == 0.0;
+        let command =
+            if self.dead_man && !stop {
+                Command::TimedDrive(self.l_rpm as i32, self.r_rpm as i32, DEAD_MAN_TTL_MS)
+            } else {
+                Command::LrRpm(self.l_rpm as i32, self.r_rpm as i32)
+            };
+        self.last_drive_time = time::now();
+        let packet = self.reliable.send(command, stop);
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
     }
 
     pub fn send_f_pan(&mut self) {
         let time_since = (time::now() - self.last_f_pan_time).num_milliseconds();
         if time_since >= 500 {
             self.last_f_pan_time = time::now();
-            let packet = format!("C{}|", self.f_pan as i32);
-            let delay = self.delay;
-            self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+            let packet = Command::FPan(self.f_pan as i32).encode();
+            let delay = self.sim_delay;
+            self.queue_packet(delay, packet);
         }
     }
 
@@ -916,71 +1232,53 @@ impl NavigationUi {
         let time_since = (time::now() - self.last_f_tilt_time).num_milliseconds();
         if time_since >= 500 {
             self.last_f_tilt_time = time::now();
-            let packet = format!("D{}|", self.f_tilt as i32);
-            let delay = self.delay;
-            self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+            let packet = Command::FTilt(self.f_tilt as i32).encode();
+            let delay = self.sim_delay;
+            self.queue_packet(delay, packet);
         }
     }
 
     pub fn send_sadl(&mut self) {
-        let packet = format!("E{}|", self.sadl as i32);
-        let delay = self.delay;
-        self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+        let packet = self.reliable.send(Command::Sadl(self.sadl as i32), true);
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
     }
 
     /*pub fn send_blade(&mut self) {
-        let packet = format!("F{}|", self.blade as i32);
-        let delay = self.delay;
-        self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+        let packet = Command::Blade(self.blade as i32).encode();
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
     }*/
 
     pub fn send_command(&mut self) {
-        let packet = format!("Z{}|{}|", self.command.to_uppercase(), self.motor_speed);
-        let delay = self.delay;
-        self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+        let packet = self.reliable.send(Command::Command(self.command.to_uppercase(), self.motor_speed), true);
+        let delay = self.sim_delay;
+        self.queue_packet(delay, packet);
 
         self.command_history.push(self.command.clone());
     }
 
-    pub fn queue_packet(&mut self, delay: time::Duration, mut data: Vec<u8>, addr: (String, u16)) {
-        data.push(0); // Null terminate all of our packets
-        self.out_queue.push_back((time::now(), delay, data, addr));
+    pub fn queue_packet(&mut self, delay: time::Duration, data: Vec<u8>) {
+        if self.replaying {
+            return;
+        }
+        let data = if self.checksums { add_checksum(&data) } else { data };
+        let addr = self.rover_addr.clone();
+        self.out_queue.push(delay, data, addr);
     }
 
     fn flush_out_queue(&mut self) -> io::Result<usize> {
-        use std::iter;
-
-        let mut bytes_written = 0;
-        while !self.out_queue.is_empty() {
-            if time::now()-self.out_queue[0].0 >= self.out_queue[0].1 {
-                let (_, _, mut data, addr) = self.out_queue.pop_front().unwrap();
-                let data_len = data.len();
-                bytes_written += try!(self.client.send_to(data.as_slice(), (addr.0.as_str(), addr.1)));
-                //data.extend(iter::repeat(b' ').take(64 - data_len)); // Pad the message to always be 64 bytes
-                //bytes_written += try!(self.client.write(data.as_slice()));
-            } else {
-                break;
-            }
-        }
-        Ok(bytes_written)
+        self.out_queue.flush(&self.client)
     }
 }
 
-fn gps_degrees_to_dms(degrees: f64) -> (i32, i32, f64) {
-    use std::f64;
-
-    let degrees = f64::abs(degrees);
-
-    let minutes = (degrees - f64::floor(degrees)) * 60.0; 
-    let seconds = (minutes - f64::floor(minutes)) * 60.0;
-    let degrees =
-        if degrees < 0.0 {
-            f64::ceil(degrees) as i32
-        } else {
-            f64::floor(degrees) as i32
-        };
-
-    (degrees, f64::floor(minutes) as i32, seconds)
+impl Drop for NavigationUi {
+    // Recordings are normally finished when the window closes, this catches a panic
+    fn drop(&mut self) {
+        if self.recording {
+            self.finish_recordings();
+        }
+    }
 }
 
 widget_ids! {
@@ -988,11 +1286,18 @@ widget_ids! {
 
     LOCAL_TIME,
     UTC_TIME,
+    LINK_STATUS,
     MISSION_TIME_LABEL,
     MISSION_START_BUTTON,
     MISSION_RESET_BUTTON,
-    TIME_DELAY,
-    TIME_DELAY_VALUE,
+    COMM_DELAY,
+    SIM_DELAY_LABEL,
+    SIM_DELAY_VALUE,
+    DEAD_MAN_TOGGLE_BUTTON,
+    PACKET_ERRORS,
+    CORRUPT_PACKETS,
+    RELIABLE_TOGGLE_BUTTON,
+    UNACKED_COMMANDS,
 
     // IMU section
     IMU_LABEL,
@@ -1032,4 +1337,20 @@ widget_ids! {
     SADL_LABEL,
     SADL_UP,
     SADL_DOWN,
+
+    REPLAY_PLAY_BUTTON,
+    REPLAY_STEP_BUTTON,
+    REPLAY_SPEED_BUTTON,
+    REPLAY_SCRUB_SLIDER,
+    REPLAY_LABEL,
+
+    LAYOUT_OUTLINE,
+    LAYOUT_PANEL_LABEL,
+    LAYOUT_NEXT_BUTTON,
+    LAYOUT_LEFT_BUTTON,
+    LAYOUT_RIGHT_BUTTON,
+    LAYOUT_UP_BUTTON,
+    LAYOUT_DOWN_BUTTON,
+    LAYOUT_SAVE_BUTTON,
+    LAYOUT_DONE_BUTTON,
 }
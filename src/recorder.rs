@@ -0,0 +1,106 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ffmpeg;
+use ffmpeg::format::Pixel;
+
+/// Extension of recordings that haven't been finished. Anything left with it after a crash can be
+/// fixed with mission-recover.
+pub const PARTIAL_EXT: &'static str = "partial";
+
+/// Encodes video frames to a file. The file is written as `<path>.partial` and only renamed to
+/// `path` once its trailer has been written, so an unfinished recording is easy to spot.
+pub struct Recorder {
+    path: String,
+    format: ffmpeg::format::context::Output,
+    video: ffmpeg::codec::encoder::video::Encoder,
+    converter: ffmpeg::software::scaling::Context,
+    packet: ffmpeg::Packet,
+    frame: ffmpeg::frame::Video,
+}
+
+impl Recorder {
+    pub fn create(path: &str, width: u32, height: u32, format: Pixel) -> Result<Recorder, ffmpeg::Error> {
+        let partial_path = partial_path(path);
+        let mut rec_format = try!(ffmpeg::format::output_as(&partial_path, container(path)));
+
+        let video = {
+            let mut stream = try!(rec_format.add_stream(ffmpeg::codec::Id::MPEG4));
+            let mut codec = try!(stream.codec().encoder().video());
+
+            codec.set_width(width);
+            codec.set_height(height);
+            codec.set_format(Pixel::YUV420P);
+            codec.set_time_base((1, 1000));
+            codec.set_flags(ffmpeg::codec::flag::GLOBAL_HEADER);
+
+            stream.set_time_base((1, 1000));
+
+            try!(codec.open_as(ffmpeg::codec::Id::MPEG4))
+        };
+
+        let converter = try!(ffmpeg::software::converter((width, height), format, Pixel::YUV420P));
+
+        // Fragmented MP4 so everything up to a crash can still be read back
+        let mut options = ffmpeg::Dictionary::new();
+        options.set("movflags", "frag_keyframe+empty_moov");
+        try!(rec_format.write_header_with(options));
+
+        Ok(Recorder {
+            path: path.to_string(),
+            format: rec_format,
+            video: video,
+            converter: converter,
+            packet: ffmpeg::Packet::empty(),
+            frame: ffmpeg::frame::Video::empty(),
+        })
+    }
+
+    /// Encode a frame. `pts` is in tenths of a second.
+    pub fn write(&mut self, pts: i64, input_frame: &ffmpeg::frame::Video) {
+        if let Err(e) = self.converter.run(input_frame, &mut self.frame) {
+            println!("WARNING: video software converter error: {}", e);
+        }
+        self.frame.set_pts(Some(pts));
+
+        match self.video.encode(&self.frame, &mut self.packet) {
+            Ok(_) => {
+                self.packet.set_stream(0);
+                self.packet.rescale_ts((1, 10), (1, 17500));
+                self.packet.write_interleaved(&mut self.format);
+            },
+            Err(e) => {
+                println!("WARNING: Failed to write video frame: {}", e);
+            },
+        }
+    }
+
+    /// Write the buffered frames and the trailer, then give the file its final name.
+    pub fn finish(mut self) -> io::Result<()> {
+        while let Ok(true) = self.video.flush(&mut self.packet) {
+            self.packet.set_stream(0);
+            self.packet.rescale_ts((1, 10), (1, 17500));
+            self.packet.write_interleaved(&mut self.format);
+        }
+
+        if let Err(e) = self.format.write_trailer() {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("{}", e)));
+        }
+        let Recorder { path, format, .. } = self;
+        drop(format);
+        fs::rename(partial_path(&path), &path)
+    }
+}
+
+pub fn partial_path(path: &str) -> String {
+    format!("{}.{}", path, PARTIAL_EXT)
+}
+
+// ffmpeg can't guess the container from a `.partial` name
+fn container(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("mkv") => "matroska",
+        _ => "mp4",
+    }
+}
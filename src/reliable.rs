@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+
+use time;
+
+use rover_common::protocol::{encode_sequenced, Command};
+
+const RETRANSMIT_MS: i64 = 300; // Resend an unacknowledged command this often, plus the simulated delay
+const MAX_ATTEMPTS: u32 = 10; // Give up on a command after this many sends
+
+struct Pending {
+    seq: u32,
+    command: Command,
+    last_sent: time::Tm,
+    attempts: u32,
+}
+
+/// Optional reliable channel for critical commands. Sequenced commands are kept until the rover
+/// acknowledges them and are retransmitted until it does or we give up. The packets are handed
+/// back to the caller so they still go through its outbound queue.
+pub struct ReliableSender {
+    enabled: bool,
+    next_seq: u32,
+    pending: VecDeque<Pending>,
+    failed: u64, // Commands that were never acknowledged
+}
+
+impl ReliableSender {
+    pub fn new() -> ReliableSender {
+        ReliableSender {
+            enabled: false,
+            next_seq: 0,
+            pending: VecDeque::new(),
+            failed: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.pending.clear();
+        }
+    }
+
+    /// Build the packet for `command`. Critical commands are sequenced while reliable delivery is
+    /// enabled. Any pending command that this one supersedes is dropped so a retransmission can't
+    /// undo it.
+    pub fn send(&mut self, command: Command, critical: bool) -> Vec<u8> {
+        self.send_at(time::now(), command, critical)
+    }
+
+    fn send_at(&mut self, now: time::Tm, command: Command, critical: bool) -> Vec<u8> {
+        self.pending.retain(|p| !supersedes(&command, &p.command));
+
+        if !self.enabled || !critical {
+            return command.encode();
+        }
+
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let data = encode_sequenced(seq, &command);
+        self.pending.push_back(Pending {
+            seq: seq,
+            command: command,
+            last_sent: now,
+            attempts: 1,
+        });
+        data
+    }
+
+    pub fn on_ack(&mut self, seq: u32) {
+        self.pending.retain(|p| p.seq != seq);
+    }
+
+    /// Packets that are due to be sent again. `delay` is the simulated delay the packets are
+    /// queued with, so we don't resend while the first copy is still sitting in the queue.
+    pub fn retransmissions(&mut self, delay: time::Duration) -> Vec<Vec<u8>> {
+        self.retransmissions_at(time::now(), delay)
+    }
+
+    fn retransmissions_at(&mut self, now: time::Tm, delay: time::Duration) -> Vec<Vec<u8>> {
+        let interval = time::Duration::milliseconds(RETRANSMIT_MS) + delay * 2;
+
+        let mut packets = vec![];
+        let mut failed = 0;
+        self.pending.retain(|p| {
+            if now - p.last_sent < interval {
+                true
+            } else if p.attempts >= MAX_ATTEMPTS {
+                println!("WARNING: Command {:?} was never acknowledged", p.command);
+                failed += 1;
+                false
+            } else {
+                true
+            }
+        });
+        for p in self.pending.iter_mut() {
+            if now - p.last_sent >= interval {
+                p.last_sent = now;
+                p.attempts += 1;
+                packets.push(encode_sequenced(p.seq, &p.command));
+            }
+        }
+        self.failed += failed;
+
+        packets
+    }
+
+    /// Number of commands waiting for an acknowledgement.
+    pub fn unacked(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.failed
+    }
+}
+
+// Whether sending `new` makes `old` obsolete. The drive commands all control the same motors.
+fn supersedes(new: &Command, old: &Command) -> bool {
+    new.class() == old.class()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time;
+
+    fn enabled() -> ReliableSender {
+        let mut reliable = ReliableSender::new();
+        reliable.set_enabled(true);
+        reliable
+    }
+
+    fn ms(ms: i64) -> time::Duration {
+        time::Duration::milliseconds(ms)
+    }
+
+    #[test]
+    fn sequences_critical_commands() {
+        let now = time::now();
+        let mut reliable = enabled();
+        assert_eq!(reliable.send_at(now, Command::Sadl(10), true), encode_sequenced(0, &Command::Sadl(10)));
+        assert_eq!(reliable.send_at(now, Command::FPan(90), false), Command::FPan(90).encode());
+        assert_eq!(reliable.send_at(now, Command::Brake, true), encode_sequenced(1, &Command::Brake));
+        assert_eq!(reliable.unacked(), 2);
+
+        reliable.next_seq = u32::max_value();
+        assert_eq!(reliable.send_at(now, Command::FTilt(1), true), encode_sequenced(u32::max_value(), &Command::FTilt(1)));
+        assert_eq!(reliable.send_at(now, Command::StereoSnapshot, true), encode_sequenced(0, &Command::StereoSnapshot));
+    }
+
+    #[test]
+    fn sends_plain_commands_when_disabled() {
+        let mut reliable = ReliableSender::new();
+        assert_eq!(reliable.send_at(time::now(), Command::Brake, true), Command::Brake.encode());
+        assert_eq!(reliable.unacked(), 0);
+
+        let mut reliable = enabled();
+        reliable.send_at(time::now(), Command::Brake, true);
+        reliable.set_enabled(false);
+        assert_eq!(reliable.unacked(), 0);
+    }
+
+    #[test]
+    fn acks_remove_pending_commands() {
+        let now = time::now();
+        let mut reliable = enabled();
+        reliable.send_at(now, Command::Sadl(10), true);
+        reliable.send_at(now, Command::Brake, true);
+
+        reliable.on_ack(5);
+        assert_eq!(reliable.unacked(), 2);
+        reliable.on_ack(0);
+        assert_eq!(reliable.unacked(), 1);
+        assert_eq!(reliable.retransmissions_at(now + ms(RETRANSMIT_MS), ms(0)),
+                   vec![encode_sequenced(1, &Command::Brake)]);
+        reliable.on_ack(1);
+        assert_eq!(reliable.unacked(), 0);
+        assert_eq!(reliable.failed(), 0);
+    }
+
+    #[test]
+    fn newer_commands_supersede_pending_ones() {
+        let now = time::now();
+        let mut reliable = enabled();
+        reliable.send_at(now, Command::Sadl(10), true);
+        reliable.send_at(now, Command::Sadl(20), true);
+        assert_eq!(reliable.retransmissions_at(now + ms(RETRANSMIT_MS), ms(0)),
+                   vec![encode_sequenced(1, &Command::Sadl(20))]);
+
+        // Any drive command replaces a pending one, even when it isn't sequenced itself
+        reliable.send_at(now, Command::Brake, true);
+        reliable.send_at(now, Command::LRpm(50), false);
+        assert_eq!(reliable.unacked(), 1);
+
+        // Other commands don't
+        reliable.send_at(now, Command::FPan(90), false);
+        assert_eq!(reliable.unacked(), 1);
+
+        assert!(supersedes(&Command::TimedDrive(10, 10, 500), &Command::LrRpm(0, 0)));
+        assert!(!supersedes(&Command::FPan(1), &Command::FTilt(1)));
+    }
+
+    #[test]
+    fn retransmits_after_the_interval_and_simulated_delay() {
+        let now = time::now();
+        let mut reliable = enabled();
+        reliable.send_at(now, Command::Brake, true);
+
+        assert!(reliable.retransmissions_at(now + ms(RETRANSMIT_MS - 1), ms(0)).is_empty());
+        // The first copy can still be in the delay queue, and so can its ack on the way back
+        assert!(reliable.retransmissions_at(now + ms(RETRANSMIT_MS), ms(1000)).is_empty());
+        assert!(reliable.retransmissions_at(now + ms(RETRANSMIT_MS + 2000 - 1), ms(1000)).is_empty());
+        assert_eq!(reliable.retransmissions_at(now + ms(RETRANSMIT_MS + 2000), ms(1000)),
+                   vec![encode_sequenced(0, &Command::Brake)]);
+
+        // Timed from the retransmission
+        let resent = now + ms(RETRANSMIT_MS + 2000);
+        assert!(reliable.retransmissions_at(resent + ms(RETRANSMIT_MS - 1), ms(0)).is_empty());
+        assert_eq!(reliable.retransmissions_at(resent + ms(RETRANSMIT_MS), ms(0)).len(), 1);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut now = time::now();
+        let mut reliable = enabled();
+        reliable.send_at(now, Command::Brake, true);
+
+        for _ in 1..MAX_ATTEMPTS {
+            now = now + ms(RETRANSMIT_MS);
+            assert_eq!(reliable.retransmissions_at(now, ms(0)).len(), 1);
+        }
+        assert_eq!(reliable.failed(), 0);
+
+        now = now + ms(RETRANSMIT_MS);
+        assert!(reliable.retransmissions_at(now, ms(0)).is_empty());
+        assert_eq!(reliable.unacked(), 0);
+        assert_eq!(reliable.failed(), 1);
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::env;
+use std::f64;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+extern crate time;
+extern crate rover_common;
+
+use rover_common::frame::FrameWriter;
+use rover_common::protocol::{add_checksum, decode_sequenced, encode_telemetry, strip_checksum, Command, TelemetryMsg, HANDSHAKE};
+
+// Simple rover model constants
+const MAX_SPEED: f64 = 1.0; // m/s at 100% rpm
+const TRACK_WIDTH: f64 = 1.2; // m between the left and right wheels
+const METERS_PER_DEG_LAT: f64 = 111_320.0;
+const AMBIENT_TEMP: f64 = 18.0;
+
+struct Rover {
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    heading: f64, // Degrees clockwise from north
+    speed: f64,
+    yaw_rate: f64, // Radians per second
+
+    l_rpm: f64,
+    r_rpm: f64,
+    drive_expiry: Option<f64>, // Time at which a timed drive command runs out
+
+    h_48_v: f64,
+    p_12_e_v: f64,
+    l_motor_temp: f64,
+    r_motor_temp: f64,
+    avionics_temp: f64,
+
+    noise: Noise,
+}
+
+impl Rover {
+    fn new() -> Rover {
+        Rover {
+            latitude: 19.6782,
+            longitude: -155.5645,
+            altitude: 2000.0,
+            heading: 0.0,
+            speed: 0.0,
+            yaw_rate: 0.0,
+
+            l_rpm: 0.0,
+            r_rpm: 0.0,
+            drive_expiry: None,
+
+            h_48_v: 50.4,
+            p_12_e_v: 12.6,
+            l_motor_temp: AMBIENT_TEMP,
+            r_motor_temp: AMBIENT_TEMP,
+            avionics_temp: AMBIENT_TEMP + 10.0,
+
+            noise: Noise::new(0x2545F491),
+        }
+    }
+
+    fn apply(&mut self, command: &Command, now: f64) {
+        match *command {
+            Command::LRpm(rpm) => {
+                self.l_rpm = clamp_rpm(rpm);
+                self.drive_expiry = None;
+            },
+            Command::RRpm(rpm) => {
+                self.r_rpm = clamp_rpm(rpm);
+                self.drive_expiry = None;
+            },
+            Command::LrRpm(l_rpm, r_rpm) => {
+                self.l_rpm = clamp_rpm(l_rpm);
+                self.r_rpm = clamp_rpm(r_rpm);
+                self.drive_expiry = None;
+            },
+            Command::TimedDrive(l_rpm, r_rpm, ttl_ms) => {
+                self.l_rpm = clamp_rpm(l_rpm);
+                self.r_rpm = clamp_rpm(r_rpm);
+                self.drive_expiry = Some(now + ttl_ms as f64 / 1000.0);
+            },
+            Command::Brake => {
+                self.l_rpm = 0.0;
+                self.r_rpm = 0.0;
+                self.drive_expiry = None;
+            },
+            _ => { },
+        }
+    }
+
+    fn step(&mut self, now: f64, dt: f64) {
+        // Dead-man: stop when the timed drive command wasn't renewed in time
+        if let Some(expiry) = self.drive_expiry {
+            if now >= expiry {
+                println!("Drive command expired, stopping");
+                self.l_rpm = 0.0;
+                self.r_rpm = 0.0;
+                self.drive_expiry = None;
+            }
+        }
+
+        // Differential drive kinematics
+        let v_l = self.l_rpm / 100.0 * MAX_SPEED;
+        let v_r = self.r_rpm / 100.0 * MAX_SPEED;
+        self.speed = (v_l + v_r) / 2.0;
+        self.yaw_rate = (v_l - v_r) / TRACK_WIDTH;
+
+        self.heading = (self.heading + self.yaw_rate.to_degrees() * dt) % 360.0;
+        if self.heading < 0.0 {
+            self.heading += 360.0;
+        }
+
+        let heading = self.heading.to_radians();
+        let north = self.speed * heading.cos() * dt;
+        let east = self.speed * heading.sin() * dt;
+        self.latitude += north / METERS_PER_DEG_LAT;
+        self.longitude += east / (METERS_PER_DEG_LAT * self.latitude.to_radians().cos());
+
+        // Batteries drain and motors heat up with load
+        let l_load = self.l_rpm.abs() / 100.0;
+        let r_load = self.r_rpm.abs() / 100.0;
+        self.h_48_v = (self.h_48_v - (0.0005 + 0.002 * (l_load + r_load)) * dt).max(40.0);
+        self.p_12_e_v = (self.p_12_e_v - 0.0002 * dt).max(10.0);
+        self.l_motor_temp += (AMBIENT_TEMP + 70.0 * l_load - self.l_motor_temp) * dt / 120.0;
+        self.r_motor_temp += (AMBIENT_TEMP + 70.0 * r_load - self.r_motor_temp) * dt / 120.0;
+        self.avionics_temp += (AMBIENT_TEMP + 12.0 - self.avionics_temp) * dt / 300.0;
+    }
+
+    fn imu(&mut self) -> TelemetryMsg {
+        // Level rover. These are the raw sensor axes that the ground station's pitch/roll/heading
+        // math maps back to pitch = 0, roll = 0 and our heading.
+        let heading = self.heading.to_radians();
+        let n = self.noise.next() * 0.01;
+        TelemetryMsg::Imu {
+            accel: (-1.0 + n, n, -n),
+            gyro: (0.0, 0.0, self.yaw_rate),
+            mag: (0.0, heading.cos(), heading.sin()),
+        }
+    }
+
+    fn telemetry(&mut self) -> Vec<TelemetryMsg> {
+        let l_load = self.l_rpm.abs() / 100.0;
+        let r_load = self.r_rpm.abs() / 100.0;
+        let course = if self.speed < 0.0 { (self.heading + 180.0) % 360.0 } else { self.heading };
+
+        vec![
+            TelemetryMsg::Volt { h_48_v: self.h_48_v + self.noise.next() * 0.05,
+                                 p_12_e_v: self.p_12_e_v + self.noise.next() * 0.02 },
+            TelemetryMsg::Amp { l_motor: 0.3 + 12.0 * l_load + self.noise.next() * 0.1,
+                                r_motor: 0.3 + 12.0 * r_load + self.noise.next() * 0.1,
+                                p_12_e: 2.1 + self.noise.next() * 0.05 },
+            TelemetryMsg::Gps { latitude: Some(self.latitude),
+                                longitude: Some(self.longitude),
+                                speed: Some(self.speed.abs()),
+                                altitude: Some(self.altitude),
+                                angle: Some(course) },
+            TelemetryMsg::LMotorTemp(self.l_motor_temp),
+            TelemetryMsg::RMotorTemp(self.r_motor_temp),
+            TelemetryMsg::AvionicsTemp(self.avionics_temp),
+            TelemetryMsg::WeatherTemp(AMBIENT_TEMP + self.noise.next() * 0.2),
+            TelemetryMsg::WeatherPressureAltitude { pressure: 795.0 + self.noise.next(),
+                                                    altitude: self.altitude * 3.28084 },
+            TelemetryMsg::WindSpeed(3.0 + self.noise.next() * 1.5),
+        ]
+    }
+}
+
+fn clamp_rpm(rpm: i32) -> f64 {
+    (rpm as f64).max(-100.0).min(100.0)
+}
+
+// Whether to apply a command given the last sequence number applied in each command class. A
+// retransmission, or a command older than one of the same class already applied, is ignored.
+// Sequence numbers wrap, so a seq up to 2^31 ahead of the last one counts as newer.
+fn should_apply(last_seqs: &HashMap<char, u32>, seq: Option<u32>, command: &Command) -> bool {
+    match (seq, last_seqs.get(&command.class())) {
+        (Some(seq), Some(&last)) => (seq.wrapping_sub(last) as i32) > 0,
+        _ => true,
+    }
+}
+
+// Small xorshift generator so the telemetry isn't perfectly flat
+struct Noise {
+    state: u32,
+}
+
+impl Noise {
+    fn new(seed: u32) -> Noise {
+        Noise { state: seed }
+    }
+
+    // Returns a value in [-1, 1]
+    fn next(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state as f64 / u32::max_value() as f64) * 2.0 - 1.0
+    }
+}
+
+/// Encodes outgoing telemetry, either framed or in the legacy one message per datagram text format.
+struct TelemetryEncoder {
+    legacy: bool,
+    checksums: bool, // Checksum legacy datagrams, frames always have a CRC
+    writer: FrameWriter,
+}
+
+impl TelemetryEncoder {
+    fn datagrams(&mut self, msgs: &[TelemetryMsg]) -> Vec<Vec<u8>> {
+        if self.legacy {
+            let checksums = self.checksums;
+            msgs.iter().map(|msg| {
+                let packet = encode_telemetry(&[msg.clone()]).into_bytes();
+                if checksums { add_checksum(&packet) } else { packet }
+            }).collect()
+        } else {
+            self.writer.telemetry(msgs)
+        }
+    }
+}
+
+fn main() {
+    let mut port: u16 = 30000;
+    let mut legacy = false;
+    let mut checksums = false;
+    for arg in env::args().skip(1) {
+        if arg == "--legacy" {
+            legacy = true;
+        } else if arg == "--checksum" {
+            checksums = true;
+        } else {
+            port = arg.parse().expect("Usage: rover-sim [port] [--legacy] [--checksum]");
+        }
+    }
+
+    let socket = UdpSocket::bind(("0.0.0.0", port)).ok().expect("Failed to open UDP socket");
+    socket.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    println!("Simulated rover listening on port {}{}", port, if legacy { " (legacy telemetry)" } else { "" });
+
+    let mut encoder = TelemetryEncoder { legacy: legacy, checksums: checksums, writer: FrameWriter::new() };
+    let mut bad_commands: u64 = 0; // Commands rejected because of a bad checksum
+
+    let mut rover = Rover::new();
+    let mut clients: Vec<SocketAddr> = vec![];
+    let mut last_seqs: HashMap<SocketAddr, HashMap<char, u32>> = HashMap::new(); // Per client and command class
+
+    let mut last_step = time::precise_time_s();
+    let mut last_imu = last_step;
+    let mut last_telemetry = last_step;
+
+    let mut buf = [0u8; 512];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((bytes_read, addr)) => {
+                let data = &buf[0..bytes_read];
+                if data == HANDSHAKE {
+                    // A restarted ground station numbers its commands from zero again
+                    last_seqs.remove(&addr);
+                    if !clients.contains(&addr) {
+                        println!("Client connected: {}", addr);
+                        clients.push(addr);
+                    }
+                } else {
+                    match strip_checksum(data) {
+                        Err(e) => {
+                            // Not acknowledged, so a reliable command will be retransmitted
+                            bad_commands += 1;
+                            println!("WARNING: Corrupted command from {}: {} ({:?})", addr, e,
+                                     String::from_utf8_lossy(data));
+                            for datagram in encoder.datagrams(&[TelemetryMsg::BadCommands(bad_commands)]) {
+                                if let Err(e) = socket.send_to(&datagram, addr) {
+                                    println!("WARNING: Failed to send bad command count to {}: {}", addr, e);
+                                }
+                            }
+                        },
+                        Ok(data) => {
+                            match decode_sequenced(data) {
+                                Ok((_, Command::Ping(seq, sent_ms))) => {
+                                    let now = time::get_time();
+                                    let rover_ms = (now.sec as u64) * 1000 + (now.nsec as u64) / 1_000_000;
+                                    let pong = TelemetryMsg::Pong { seq: seq, sent_ms: sent_ms, rover_ms: rover_ms };
+                                    for datagram in encoder.datagrams(&[pong]) {
+                                        if let Err(e) = socket.send_to(&datagram, addr) {
+                                            println!("WARNING: Failed to send pong to {}: {}", addr, e);
+                                        }
+                                    }
+                                },
+                                Ok((seq, command)) => {
+                                    // Stale commands are acknowledged but not applied
+                                    let client_seqs = last_seqs.entry(addr).or_insert_with(HashMap::new);
+                                    if should_apply(client_seqs, seq, &command) {
+                                        println!("{} -> {:?}", addr, command);
+                                        rover.apply(&command, time::precise_time_s());
+                                        if let Some(seq) = seq {
+                                            client_seqs.insert(command.class(), seq);
+                                        }
+                                    } else {
+                                        println!("{} -> {:?} (stale, ignored)", addr, command);
+                                    }
+                                    let ack = TelemetryMsg::Ack { opcode: command.opcode(), seq: seq };
+                                    for datagram in encoder.datagrams(&[ack]) {
+                                        if let Err(e) = socket.send_to(&datagram, addr) {
+                                            println!("WARNING: Failed to send ack to {}: {}", addr, e);
+                                        }
+                                    }
+                                },
+                                Err(e) => {
+                                    println!("WARNING: Bad command from {}: {} ({:?})", addr, e,
+                                             String::from_utf8_lossy(data));
+                                },
+                            }
+                        },
+                    }
+                }
+            },
+            Err(_) => { }, // Read timeout
+        }
+
+        let now = time::precise_time_s();
+        rover.step(now, now - last_step);
+        last_step = now;
+
+        let mut msgs = vec![];
+        if now - last_imu >= 0.1 {
+            last_imu = now;
+            msgs.push(rover.imu());
+        }
+        if now - last_telemetry >= 1.0 {
+            last_telemetry = now;
+            msgs.extend(rover.telemetry());
+            if bad_commands > 0 {
+                msgs.push(TelemetryMsg::BadCommands(bad_commands));
+            }
+        }
+        for datagram in encoder.datagrams(&msgs) {
+            for client in &clients {
+                if let Err(e) = socket.send_to(&datagram, client) {
+                    println!("WARNING: Failed to send telemetry to {}: {}", client, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drive(rover: &mut Rover, start: f64, seconds: f64) {
+        let dt = 0.1;
+        let mut now = start;
+        while now < start + seconds - 1e-9 {
+            now += dt;
+            rover.step(now, dt);
+        }
+    }
+
+    #[test]
+    fn equal_rpms_drive_straight() {
+        let mut rover = Rover::new();
+        let (latitude, longitude) = (rover.latitude, rover.longitude);
+        rover.apply(&Command::LrRpm(100, 100), 0.0);
+        drive(&mut rover, 0.0, 10.0);
+
+        assert_eq!(rover.heading, 0.0);
+        assert_eq!(rover.yaw_rate, 0.0);
+        assert!((rover.speed - MAX_SPEED).abs() < 1e-9);
+        assert!(((rover.latitude - latitude) * METERS_PER_DEG_LAT - 10.0 * MAX_SPEED).abs() < 1e-6);
+        assert!((rover.longitude - longitude).abs() < 1e-12);
+    }
+
+    #[test]
+    fn opposite_rpms_turn_in_place() {
+        let mut rover = Rover::new();
+        let (latitude, longitude) = (rover.latitude, rover.longitude);
+        rover.apply(&Command::LrRpm(50, -50), 0.0);
+        drive(&mut rover, 0.0, 1.0);
+
+        // Left track forward turns clockwise
+        let expected = (MAX_SPEED / TRACK_WIDTH).to_degrees();
+        assert_eq!(rover.speed, 0.0);
+        assert!((rover.heading - expected).abs() < 1e-6, "{}", rover.heading);
+        assert_eq!((rover.latitude, rover.longitude), (latitude, longitude));
+
+        // Turning back past north wraps the heading
+        rover.apply(&Command::LrRpm(-50, 50), 1.0);
+        drive(&mut rover, 1.0, 2.0);
+        assert!((rover.heading - (360.0 - expected)).abs() < 1e-6, "{}", rover.heading);
+    }
+
+    #[test]
+    fn timed_drive_expires_to_a_stop() {
+        let mut rover = Rover::new();
+        rover.apply(&Command::TimedDrive(60, 60, 500), 10.0);
+        rover.step(10.4, 0.4);
+        assert!(rover.speed > 0.0);
+
+        rover.step(10.5, 0.1);
+        assert_eq!((rover.l_rpm, rover.r_rpm, rover.speed), (0.0, 0.0, 0.0));
+        assert_eq!(rover.drive_expiry, None);
+
+        // A plain drive command doesn't expire
+        rover.apply(&Command::TimedDrive(60, 60, 500), 20.0);
+        rover.apply(&Command::LrRpm(60, 60), 20.1);
+        rover.step(30.0, 0.1);
+        assert!(rover.speed > 0.0);
+    }
+
+    #[test]
+    fn clamps_rpms_and_brakes() {
+        let mut rover = Rover::new();
+        rover.apply(&Command::LrRpm(150, -150), 0.0);
+        assert_eq!((rover.l_rpm, rover.r_rpm), (100.0, -100.0));
+        rover.apply(&Command::LRpm(20), 0.0);
+        rover.apply(&Command::RRpm(30), 0.0);
+        assert_eq!((rover.l_rpm, rover.r_rpm), (20.0, 30.0));
+        rover.apply(&Command::Brake, 0.0);
+        assert_eq!((rover.l_rpm, rover.r_rpm), (0.0, 0.0));
+    }
+
+    fn applied(seqs: &[(u32, Command)]) -> HashMap<char, u32> {
+        let mut last_seqs = HashMap::new();
+        for &(seq, ref command) in seqs {
+            last_seqs.insert(command.class(), seq);
+        }
+        last_seqs
+    }
+
+    #[test]
+    fn applies_a_lost_command_after_a_newer_one_of_another_class() {
+        // Brake R5 was lost, SADL R6 arrived, then the brake is retransmitted
+        let last_seqs = applied(&[(4, Command::LrRpm(50, 50)), (6, Command::Sadl(100))]);
+        assert!(should_apply(&last_seqs, Some(5), &Command::Brake));
+        assert!(!should_apply(&last_seqs, Some(5), &Command::Sadl(0)));
+    }
+
+    #[test]
+    fn ignores_retransmissions_and_older_commands() {
+        let last_seqs = applied(&[(7, Command::LrRpm(50, 50))]);
+        assert!(!should_apply(&last_seqs, Some(7), &Command::LrRpm(50, 50)));
+        assert!(!should_apply(&last_seqs, Some(6), &Command::Brake));
+        assert!(should_apply(&last_seqs, Some(8), &Command::Brake));
+        assert!(should_apply(&last_seqs, None, &Command::LRpm(0)));
+        assert!(should_apply(&HashMap::new(), Some(0), &Command::Brake));
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let last_seqs = applied(&[(u32::max_value(), Command::Brake)]);
+        assert!(should_apply(&last_seqs, Some(0), &Command::LrRpm(10, 10)));
+        assert!(should_apply(&last_seqs, Some(5), &Command::LrRpm(10, 10)));
+        assert!(!should_apply(&last_seqs, Some(u32::max_value() - 1), &Command::LrRpm(10, 10)));
+
+        let last_seqs = applied(&[(2, Command::Brake)]);
+        assert!(!should_apply(&last_seqs, Some(u32::max_value()), &Command::LrRpm(10, 10)));
+    }
+}
@@ -2,83 +2,122 @@ use std::cell::RefCell;
 use std::fs;
 use std::io::{Read, Write};
 use std::mem;
-use std::net::UdpSocket;
 use std::path::Path;
 use std::rc::Rc;
-use std::sync::mpsc::channel;
-use std::thread;
 
 extern crate time;
 extern crate piston_window;
 extern crate graphics;
 extern crate image;
+extern crate gfx_graphics;
+extern crate gfx_device_gl;
 #[macro_use] extern crate conrod;
 #[macro_use] extern crate ffmpeg;
+extern crate toml;
+extern crate rover_common;
 
 use conrod::Theme;
 use piston_window::{EventLoop, Glyphs, PistonWindow, WindowSettings};
 
+use config::Config;
 use conrod_config::Ui;
 use stereo_ui::StereoUi;
 use video_stream::{init_ffmpeg, start_video_stream, VideoMsg};
 
+use rover_common::capture::{Capture, Direction};
+use rover_common::protocol::HANDSHAKE;
+use rover_common::receiver::{NetEvent, PacketReceiver};
+use rover_common::session::Session;
+
+mod config;
 mod conrod_config;
 mod stereo_ui;
+mod recorder;
 mod video_stream;
 mod imu;
 
 fn main() {
+    let config =
+        match Config::load("stereo") {
+            Ok(config) => config,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            },
+        };
+
     init_ffmpeg();
 
     let ref mut window: PistonWindow = WindowSettings::new("PISCES Navigation".to_string(),
                                                            [1280, 700]).exit_on_esc(true)
                                                                        .build().unwrap();
 
+    let font_path = Path::new("./assets/fonts/NotoSans-Regular.ttf");
+    let mut glyph_cache = conrod::backend::piston_window::GlyphCache::new(window, 1280, 700);
     let mut ui = {
-        let font_path = Path::new("./assets/fonts/NotoSans-Regular.ttf");
         let theme = Theme::default();
-        let glyph_cache = Glyphs::new(&font_path, window.factory.clone());
-        Ui::new(glyph_cache.unwrap(), theme)
+        conrod::UiBuilder::new().theme(theme).build()
     };
-    
-    // Create a UDP socket to talk to the rover
-    let client = UdpSocket::bind("0.0.0.0:30002").unwrap();
-    client.send_to(b"connect me plz", ("10.10.153.8", 30001));
-    
-    let client_in = client.try_clone().unwrap();
-    let (packet_t, packet_r) = channel();
 
-    /*let mut client = TcpStream::connect("10.10.153.8:30001").unwrap();
-    client.write(b"connect me plz");
+    ui.fonts.insert_from_file(font_path).unwrap();
     
-    let mut client_in = client.try_clone().unwrap();
-    let (packet_t, packet_r) = channel();*/
-    
-    thread::Builder::new()
-        .name("packet_in".to_string())
-        .spawn(move || {
-            let mut buf = [0u8; 512];
-            loop {
-                let (bytes_read, _) = client_in.recv_from(&mut buf).unwrap();
-                //let bytes_read = client_in.read(&mut buf).unwrap();
-                if let Ok(msg) = String::from_utf8(buf[0..bytes_read].iter().cloned().collect()) {
-                    packet_t.send(msg).unwrap();
-                }
-            }
-        }).unwrap();
+    // Everything written during a run goes in a session folder listed in its manifest
+    let session =
+        match Session::create(config.mission_data_dir.as_str(), "stereo", env!("CARGO_PKG_VERSION"),
+                              config.to_json()) {
+            Ok(session) => session,
+            Err(e) => {
+                println!("Failed to create session folder in {}: {}", config.mission_data_dir, e);
+                return;
+            },
+        };
+    println!("Session {}", session.id());
+
+    // Record every packet to and from the rover
+    let capture =
+        match Capture::create(session.path("stereo.cap")) {
+            Ok(capture) => {
+                session.add_artifact("stereo.cap", "capture", "rover link");
+                Some(capture)
+            },
+            Err(e) => {
+                println!("WARNING: Failed to create packet capture: {}", e);
+                None
+            },
+        };
+
+    // Create a UDP socket to talk to the rover
+    let (client, mut receiver) =
+        match PacketReceiver::bind(config.bind_port, capture.clone()) {
+            Ok(bound) => bound,
+            Err(e) => {
+                println!("Failed to open UDP socket on port {}: {}", config.bind_port, e);
+                return;
+            },
+        };
+    // Not fatal if the network is down, the link monitor keeps retrying the handshake
+    if let Err(e) = client.send_to(HANDSHAKE, (config.rover_addr.0.as_str(), config.rover_addr.1)) {
+        println!("WARNING: Failed to send handshake: {}", e);
+    } else if let Some(ref capture) = capture {
+        capture.record(Direction::Out, HANDSHAKE);
+    }
 
     ////////////////////////////////////////////////////////////////////////////////////////
     
+    let video0 = config.video("left").expect("No left video source configured").clone();
+    let video1 = config.video("right").expect("No right video source configured").clone();
+
     let (video0_texture, video0_image) =
-        //start_video_stream(window, None, "rtsp://10.10.153.9/axis-media/media.amp");
-        //start_video_stream(window, None, "/dev/video1", 480);
-        start_video_stream(window, None, "rtsp://10.10.153.8/stereo0", 1944);
+        start_video_stream(window, None, video0.url.as_str(), video0.size);
     let (video1_texture, video1_image) =
-        start_video_stream(window, None, "rtsp://10.10.153.8/stereo1", 1944);
+        start_video_stream(window, None, video1.url.as_str(), video1.size);
 
     ///////////////////////////////////////////////////////////////////////////////////////
     
-    let mut stereo_ui = StereoUi::new(client);
+    let mut stereo_ui = StereoUi::new(client, config.rover_addr.clone(), &session, config.command_checksums);
+    if let Some(ref capture) = capture {
+        stereo_ui.set_capture(capture.clone());
+    }
     stereo_ui.send_pan();
     stereo_ui.send_tilt();
 
@@ -97,7 +136,10 @@ fn main() {
     while let Some(e) = window.next() {
         use piston_window::{Button, PressEvent, ReleaseEvent, UpdateEvent, MouseCursorEvent};
 
-        ui.handle_event(&e);
+        // Convert the piston event to a conrod event.
+        if let Some(e) = conrod::backend::piston_window::convert_event(e.clone(), window) {
+            ui.handle_event(e);
+        }
 
         e.mouse_cursor(|x, y| {
             mouse_x = x;
@@ -122,8 +164,15 @@ fn main() {
         e.update(|u_args| {
             stereo_ui.update(u_args.dt);
 
-            while let Ok(packet) = packet_r.try_recv() {
-                stereo_ui.handle_packet(packet);
+            while let Some(event) = receiver.try_next() {
+                match event {
+                    NetEvent::Packet(packet) => stereo_ui.handle_packet(packet),
+                    NetEvent::Dropped(e) => stereo_ui.handle_dropped(e),
+                    NetEvent::Error(e) => stereo_ui.handle_net_error(e),
+                }
+            }
+            if receiver.needs_rebind() {
+                stereo_ui.rebind(&mut receiver);
             }
             
             let video0_image = video0_image.lock().unwrap();
@@ -137,25 +186,32 @@ fn main() {
         window.draw_2d(&e, |c, g| {
             use graphics::*;
 
-            ui.set_widgets(|ref mut ui| {
-                stereo_ui.set_widgets(ui);
-            });
-
-            stereo_ui.draw_ui(c, g, &mut ui);
+            stereo_ui.draw_ui(c, g, &mut glyph_cache, &mut ui);
 
             Rectangle::new([0.0, 0.0, 0.4, 1.0])
                 .draw([5.0, 80.0, 630.0, 480.0],
                       &c.draw_state, c.transform,
                       g);
+            let size = video0.size as f64;
             image(&vid_textures[0],
-                  c.trans(5.0, 80.0).scale(630.0/1944.0, 480.0/1944.0).transform, g);
+                  c.trans(5.0, 80.0).scale(630.0/size, 480.0/size).transform, g);
             
             Rectangle::new([0.0, 0.0, 0.4, 1.0])
                 .draw([1280.0 - 630.0 - 5.0, 80.0, 630.0, 480.0],
                       &c.draw_state, c.transform,
                       g);
+            let size = video1.size as f64;
             image(&vid_textures[1],
-                  c.trans(1280.0 - 630.0 - 5.0, 80.0).scale(630.0/1944.0, 480.0/1944.0).transform, g);
+                  c.trans(1280.0 - 630.0 - 5.0, 80.0).scale(630.0/size, 480.0/size).transform, g);
         });
     }
+
+    // The window has closed
+    receiver.shutdown();
+    if let Some(ref capture) = capture {
+        if let Err(e) = capture.flush() {
+            println!("WARNING: Failed to write packet capture: {}", e);
+        }
+    }
+    session.finish();
 }
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
 use std::net::UdpSocket;
@@ -7,33 +7,40 @@ use std::sync::mpsc::Sender;
 
 use conrod::{
     self,
-    Background,
-    Button,
     Color,
+
+    Borderable,
     Colorable,
-    Frameable,
-    Text,
     Labelable,
     Positionable,
-    Slider,
     Sizeable,
-    TextBox,
-    Ui,
+};
+use conrod::widget::{
+    self,
+    Button,
+    Text,
+    Slider,
     Widget,
 };
 use conrod::color::{rgb, WHITE};
 use graphics::{Context, Graphics};
+use gfx_graphics;
+use gfx_device_gl;
 use piston_window::{self, Key};
 use time;
 
-use conrod_config;
-use imu;
-use video_stream::VideoMsg;
+use rover_common::{MissionTime, OutQueue};
+use rover_common::capture::Capture;
+use rover_common::frame::FrameError;
+use rover_common::integrity::IntegrityStats;
+use rover_common::layout::Area;
+use rover_common::link::LinkMonitor;
+use rover_common::protocol::{add_checksum, parse_telemetry, Command, TelemetryMsg};
+use rover_common::receiver::PacketReceiver;
+use rover_common::session::Session;
+use rover_common::widgets::{self, MissionTimerIds};
 
-enum MissionTime {
-    Paused(time::Duration),
-    Running(time::Tm, time::Duration),
-}
+use conrod_config;
 
 pub struct StereoUi {
     bg_color: Color,
@@ -48,19 +55,30 @@ pub struct StereoUi {
     pub tilting: f32,
     pub last_tilt_time: time::Tm,
 
+    link: LinkMonitor,
+    checksums: bool, // Append a checksum to every command
+    integrity: IntegrityStats,
     client: UdpSocket,
+    rover_addr: (String, u16),
     
-    out_queue: VecDeque<(time::Tm, time::Duration, Vec<u8>, (String, u16))>, // Outbound packet queue
-    delay: time::Duration,
-    delay_str: String,
+    out_queue: OutQueue,
+    sim_delay: time::Duration, // Artificial delay added to outbound packets
+    sim_delay_str: String,
+
+    image_map: conrod::image::Map<<piston_window::G2d<'static> as Graphics>::Texture>,
 }
 
 impl StereoUi {
-    pub fn new(client: UdpSocket) -> StereoUi {
+    pub fn new(client: UdpSocket, rover_addr: (String, u16), session: &Session, command_checksums: bool) -> StereoUi {
+        let mut link = LinkMonitor::new(rover_addr.clone());
+        link.set_checksums(command_checksums);
+        session.add_artifact("packet_errors.log", "packet_errors", "rover link");
+        let integrity = IntegrityStats::new(Some(session.path("packet_errors.log").as_str()));
+
         StereoUi {
             bg_color: rgb(0.2, 0.35, 0.45),
 
-            mission_time: MissionTime::Paused(time::Duration::zero()),
+            mission_time: MissionTime::new(),
 
             pan: 90.0,
             panning: 0.0,
@@ -69,11 +87,17 @@ impl StereoUi {
             tilting: 0.0,
             last_tilt_time: time::now(),
 
+            link: link,
+            checksums: command_checksums,
+            integrity: integrity,
             client: client,
+            rover_addr: rover_addr,
 
-            out_queue: VecDeque::new(),
-            delay: time::Duration::seconds(0),
-            delay_str: "".to_string(),
+            out_queue: OutQueue::new(),
+            sim_delay: time::Duration::seconds(0),
+            sim_delay_str: "".to_string(),
+
+            image_map: conrod::image::Map::new(),
         }
     }
 
@@ -84,95 +108,162 @@ impl StereoUi {
         self.tilt += self.tilting*90.0*dt; // 90 degrees per second
 
         self.flush_out_queue();
-    }
 
-    pub fn draw_ui<'a, G>(&mut self, c: Context, g: &mut G, ui: &mut conrod_config::Ui)
-                          where G: Graphics<Texture=<piston_window::G2d<'static> as conrod::Graphics>::Texture> {
-        use graphics::Transformed;
+        if let Err(e) = self.link.update(&self.client) {
+            println!("WARNING: Failed to send link heartbeat: {}", e);
+        }
+    }
 
-        // Draw the background.
-        Background::new().color(self.bg_color).set(ui);
+    pub fn draw_ui<'a>(&mut self, c: Context,
+                          g: &mut gfx_graphics::GfxGraphics<'a, gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
+                          glyph_cache: &mut conrod::backend::piston_window::GlyphCache, ui: &mut conrod_config::Ui) {
+        self.set_widgets(&mut ui.set_widgets());
 
         // Draw our UI!
-        ui.draw(c, g);
+        conrod::backend::piston_window::draw(c, g, ui.draw(),
+                                             glyph_cache,
+                                             &self.image_map,
+                                             |img| img);
     }
 
     pub fn set_widgets(&mut self, ui: &mut conrod_config::UiCell) {
-        let time_now = time::now();
-
-        // Local time
-        Text::new(format!("{}", time_now.strftime("Local  %x  %X").unwrap()).as_str())
-            .x_y((-ui.win_w / 2.0) + 100.0, (ui.win_h / 2.0) - 10.0)
-            .font_size(16)
+        // Draw the background.
+        widget::Canvas::new()
+            .color(self.bg_color)
+            .set(CANVAS, ui);
+
+        let window = Area::window(ui.win_w, ui.win_h);
+
+        // Local and UTC time
+        widgets::clock(ui, &window, LOCAL_TIME, UTC_TIME, self.bg_color.plain_contrast());
+
+        // Link status
+        let (r, g, b) = self.link.status_rgb();
+        Text::new(self.link.status_text().as_str())
+            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 48.0)
+            .font_size(14)
+            .color(rgb(r, g, b))
+            .set(LINK_STATUS, ui);
+
+        // Packets that failed their checksum in either direction
+        let corrupt_color =
+            if self.integrity.corrupted_in() > 0 || self.integrity.corrupted_out() > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(self.integrity.text().as_str())
+            .x_y((ui.win_w / 2.0) - 150.0, (ui.win_h / 2.0) - 48.0)
+            .font_size(14)
+            .color(corrupt_color)
+            .set(CORRUPT_PACKETS, ui);
+
+        // Measured communication delay
+        Text::new(self.link.delay_text().as_str())
+            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 128.0)
+            .font_size(14)
             .color(self.bg_color.plain_contrast())
-            .set(LOCAL_TIME, ui);
+            .set(COMM_DELAY, ui);
 
-        // UTC time
-        Text::new(format!("{}", time_now.to_utc().strftime("%Z  %x  %X").unwrap()).as_str())
-            .x_y((-ui.win_w / 2.0) + 104.0, (ui.win_h / 2.0) - 30.0)
-            .font_size(16)
-            .color(self.bg_color.plain_contrast())
-            .set(UTC_TIME, ui);
+        let mission_timer_ids = MissionTimerIds {
+            label: MISSION_TIME_LABEL,
+            start_button: MISSION_START_BUTTON,
+            reset_button: MISSION_RESET_BUTTON,
+        };
+        widgets::mission_timer(ui, &window, &mission_timer_ids, &mut self.mission_time, self.bg_color.plain_contrast());
 
         ////////////////////////////////////////////////////////////////////////////////////////////
         
         // Camera pan slider
-        Slider::new(self.pan, 0.0, 180.0)
+        if let Some(new_pan) = Slider::new(self.pan, 0.0, 180.0)
             .w_h(150.0, 30.0)
             .x_y(-80.0, (ui.win_h / 2.0) - 600.0)
             .rgb(0.5, 0.3, 0.6)
-            .frame(1.0)
+            .border(1.0)
             .label("Pan")
             .label_color(WHITE)
-            .react(|new_pan| {
-                self.try_update_pan(new_pan);
-            })
-            .set(F_PAN_SLIDER, ui);
+            .set(F_PAN_SLIDER, ui)
+        {
+            self.try_update_pan(new_pan);
+        }
 
         // Camera tilt slider
-        Slider::new(self.tilt, 0.0, 180.0)
+        if let Some(new_tilt) = Slider::new(self.tilt, 0.0, 180.0)
             .w_h(150.0, 30.0)
             .x_y(80.0, (ui.win_h / 2.0) - 600.0)
             .rgb(0.5, 0.3, 0.6)
-            .frame(1.0)
+            .border(1.0)
             .label("Tilt")
             .label_color(WHITE)
-            .react(|new_tilt| {
-                self.try_update_tilt(new_tilt);
-            })
-            .set(F_TILT_SLIDER, ui);
+            .set(F_TILT_SLIDER, ui)
+        {
+            self.try_update_tilt(new_tilt);
+        }
 
         ////////////////////////////////////////////////////////////////////////////////////////////
        
-        Button::new()
+        if Button::new()
             .w_h(120.0, 30.0)
             .x_y(- 80.0, (ui.win_h / 2.0) - 645.0)
             .rgb(0.3, 0.8, 0.3)
-            .frame(1.0)
+            .border(1.0)
             .label("Snapshot")
-            .react(|| { self.send_snapshot(); })
-            .set(SNAPSHOT_BUTTON, ui);
+            .set(SNAPSHOT_BUTTON, ui)
+            .was_clicked()
+        {
+            self.send_snapshot();
+        }
 
-        Button::new()
+        if Button::new()
             .w_h(120.0, 30.0)
             .x_y(80.0, (ui.win_h / 2.0) - 645.0)
             .rgb(0.3, 0.8, 0.3)
-            .frame(1.0)
+            .border(1.0)
             .label("Panorama")
-            .react(|| { self.send_panorama(); })
-            .set(PANORAMA_BUTTON, ui);
+            .set(PANORAMA_BUTTON, ui)
+            .was_clicked()
+        {
+            self.send_panorama();
+        }
+    }
+
+    /// Record every packet we send to the rover.
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.out_queue.set_capture(capture.clone());
+        self.link.set_capture(capture);
+    }
+
+    pub fn handle_dropped(&mut self, error: FrameError) {
+        println!("WARNING: Dropped datagram: {}", error);
+        self.integrity.on_dropped(&error);
+    }
+
+    pub fn handle_net_error(&mut self, error: String) {
+        println!("WARNING: {}", error);
+        self.link.on_error(error);
+    }
+
+    /// Replace our socket after it has kept failing.
+    pub fn rebind(&mut self, receiver: &mut PacketReceiver) {
+        match receiver.rebind(&mut self.client) {
+            Ok(()) => {
+                println!("Rebound the rover socket");
+                self.link.clear_error();
+            },
+            Err(e) => self.handle_net_error(format!("Failed to rebind socket: {}", e)),
+        }
     }
 
     pub fn handle_packet(&mut self, packet: String) {
         //println!("{}", packet);
 
-        let packets = packet.split("|");
-
-        for packet in packets {
-            let packet_parts: Vec<String> = packet.split(":").map(|s| s.to_string()).collect();
+        self.link.on_packet();
 
-            match packet_parts[0].as_str() {
-                _ => { /*println!("WARNING: Unknown packet ID: {}", packet_parts[0])*/ },
+        for msg in parse_telemetry(packet.as_str()) {
+            match msg {
+                Ok(TelemetryMsg::Pong { seq, sent_ms, rover_ms }) => self.link.on_pong(seq, sent_ms, rover_ms),
+                Ok(TelemetryMsg::BadCommands(count)) => self.integrity.on_rover_report(count),
+                _ => { },
             }
         }
     }
@@ -235,9 +326,9 @@ impl StereoUi {
         let time_since = (time::now() - self.last_pan_time).num_milliseconds();
         if time_since >= 500 {
             self.last_pan_time = time::now();
-            let packet = format!("K|");
-            let delay = self.delay;
-            self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+            let packet = Command::StereoSnapshot.encode();
+            let delay = self.sim_delay;
+            self.queue_packet(delay, packet);
         }
     }
     
@@ -245,9 +336,9 @@ impl StereoUi {
         let time_since = (time::now() - self.last_pan_time).num_milliseconds();
         if time_since >= 500 {
             self.last_pan_time = time::now();
-            let packet = format!("L|");
-            let delay = self.delay;
-            self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+            let packet = Command::StereoPanorama.encode();
+            let delay = self.sim_delay;
+            self.queue_packet(delay, packet);
         }
     }
     
@@ -255,9 +346,9 @@ impl StereoUi {
         let time_since = (time::now() - self.last_pan_time).num_milliseconds();
         if time_since >= 500 {
             self.last_pan_time = time::now();
-            let packet = format!("I{}|", self.pan as i32);
-            let delay = self.delay;
-            self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+            let packet = Command::StereoPan(self.pan as i32).encode();
+            let delay = self.sim_delay;
+            self.queue_packet(delay, packet);
         }
     }
 
@@ -265,42 +356,33 @@ impl StereoUi {
         let time_since = (time::now() - self.last_tilt_time).num_milliseconds();
         if time_since >= 500 {
             self.last_tilt_time = time::now();
-            let packet = format!("J{}|", self.tilt as i32);
-            let delay = self.delay;
-            self.queue_packet(delay, packet.into_bytes(), ("10.10.153.8".to_string(), 30001));
+            let packet = Command::StereoTilt(self.tilt as i32).encode();
+            let delay = self.sim_delay;
+            self.queue_packet(delay, packet);
         }
     }
 
-    pub fn queue_packet(&mut self, delay: time::Duration, mut data: Vec<u8>, addr: (String, u16)) {
-        data.push(0); // Null terminate all of our packets
-        self.out_queue.push_back((time::now(), delay, data, addr));
+    pub fn queue_packet(&mut self, delay: time::Duration, data: Vec<u8>) {
+        let data = if self.checksums { add_checksum(&data) } else { data };
+        let addr = self.rover_addr.clone();
+        self.out_queue.push(delay, data, addr);
     }
 
     fn flush_out_queue(&mut self) -> io::Result<usize> {
-        use std::iter;
-
-        let mut bytes_written = 0;
-        while !self.out_queue.is_empty() {
-            if time::now()-self.out_queue[0].0 >= self.out_queue[0].1 {
-                let (_, _, mut data, addr) = self.out_queue.pop_front().unwrap();
-                let data_len = data.len();
-                bytes_written += try!(self.client.send_to(data.as_slice(), (addr.0.as_str(), addr.1)));
-                //data.extend(iter::repeat(b' ').take(64 - data_len)); // Pad the message to always be 64 bytes
-                //bytes_written += try!(self.client.write(data.as_slice()));
-            } else {
-                break;
-            }
-        }
-        Ok(bytes_written)
+        self.out_queue.flush(&self.client)
     }
 }
 
 widget_ids! {
+    CANVAS,
     LOCAL_TIME,
     UTC_TIME,
+    LINK_STATUS,
+    CORRUPT_PACKETS,
     MISSION_TIME_LABEL,
-    TIME_DELAY,
-    TIME_DELAY_VALUE,
+    MISSION_START_BUTTON,
+    MISSION_RESET_BUTTON,
+    COMM_DELAY,
 
     F_PAN_SLIDER,
     F_TILT_SLIDER,
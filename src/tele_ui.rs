@@ -1,6 +1,6 @@
-use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
+use std::net::UdpSocket;
 
 use conrod::{
     self,
@@ -18,49 +18,66 @@ use conrod::widget::{
     Text,
     Widget,
 };
-use conrod::color::rgb;
+use conrod::color::{self, rgb};
 use graphics::{Context, Graphics};
 use gfx_graphics;
 use gfx_device_gl;
 use piston_window;
 use time;
 
+use rover_common::MissionTime;
+use rover_common::capture::Capture;
+use rover_common::frame::FrameError;
+use rover_common::gps::{format_latitude, format_longitude};
+use rover_common::imu::pitch_roll_heading;
+use rover_common::integrity::IntegrityStats;
+use rover_common::layout::{Area, Layout};
+use rover_common::limits::{self, Level, RygLimit};
+use rover_common::link::LinkMonitor;
+use rover_common::logger::Logger;
+use rover_common::protocol::{parse_telemetry, TelemetryMsg};
+use rover_common::receiver::PacketReceiver;
+use rover_common::replay::{Replay, ReplayUpdate};
+use rover_common::session::Session;
+use rover_common::widgets::{self, LayoutEditorEvent, LayoutEditorIds, MissionTimerIds, ReplayControlIds};
+
 use avg_val::AvgVal;
 use conrod_config;
-use line_graph::LineGraph;
+use config::GraphTime;
+use line_graph::{LineGraph, TimeAxis};
+use trend_graph::TrendGraph;
+
+const DEFAULT_GRAPH_WINDOW_S: f64 = 4.0 * 3600.0;
+// Where export_graphs writes to in the session folder
+const GRAPHS_FILE: &'static str = "graphs.csv";
+
+const PANELS: [&'static str; 7] = ["status", "power", "gps", "temp", "weather", "imu", "graphs"];
 
-enum MissionTime {
-    Paused(time::Duration),
-    Running(time::Tm, time::Duration),
+// Status and power down the left, the readings next to them and the trend graphs on the right
+fn default_layout() -> Layout {
+    Layout::columns(&[(0.24, &["status", "power"]),
+                      (0.24, &["gps", "temp", "weather", "imu"]),
+                      (0.52, &["graphs"])])
 }
 
-pub enum RygLimit {
-    LessThan(f64, f64),
-    GreaterThan(f64, f64),
+// The graphs take whatever their column has left
+fn panel_height(panel: &str) -> Option<f64> {
+    match panel {
+        "status" => Some(180.0),
+        "power" => Some(370.0),
+        "gps" => Some(135.0),
+        "temp" => Some(100.0),
+        "weather" => Some(120.0),
+        "imu" => Some(100.0),
+        _ => None,
+    }
 }
 
-impl RygLimit {
-    pub fn get_color(&self, value: f64) -> Color {
-        match *self {
-            RygLimit::LessThan(r, y) => {
-                if value < r {
-                    rgb(1.0, 0.0, 0.0)
-                } else if value < y {
-                    rgb(1.0, 1.0, 0.0)
-                } else {
-                    rgb(0.0, 1.0, 0.0)
-                }
-            },
-            RygLimit::GreaterThan(r, y) => {
-                if value > r {
-                    rgb(1.0, 0.0, 0.0)
-                } else if value > y {
-                    rgb(1.0, 1.0, 0.0)
-                } else {
-                    rgb(0.0, 1.0, 0.0)
-                }
-            },
-        }
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Red => rgb(1.0, 0.0, 0.0),
+        Level::Yellow => rgb(1.0, 1.0, 0.0),
+        Level::Green => rgb(0.0, 1.0, 0.0),
     }
 }
 
@@ -110,76 +127,68 @@ pub struct TelemetryUi {
     // IMU
     pitch_roll_heading: Option<(f64, f64, f64)>,
 
-    log_files: HashMap<String, BufWriter<File>>,
+    // Packets that could not be parsed
+    malformed_packets: u64,
+    integrity: IntegrityStats,
+
+    logger: Logger<BufWriter<File>>,
+
+    graph_time: GraphTime,
+    clock_s: f64, // Newest time seen, UTC seconds. The capture's time when replaying.
+
+    layout: Layout,
+    layout_path: String, // Where the layout editor saves to
+    editing_layout: bool,
+    selected_panel: usize, // Index into the layout's panels while editing
+
+    link: LinkMonitor,
+    client: UdpSocket,
+
     image_map: conrod::image::Map<<piston_window::G2d<'static> as Graphics>::Texture>,
 }
 
 impl TelemetryUi {
-    pub fn new(mission_folder: &str) -> TelemetryUi {
-        let v48_graph = LineGraph::new((400.0, 150.0), (0.0, 4.0 * 3600.0 * 2.0), (0.0, 80.0), vec![[1.0, 0.0, 0.0, 1.0]]);
-        let v12_graph = LineGraph::new((400.0, 150.0), (0.0, 4.0 * 3600.0 * 2.0), (8.0, 14.0), vec![[1.0, 0.0, 0.0, 1.0]]);
-        let avionics_temp_graph = LineGraph::new((400.0, 150.0), (0.0, 4.0 * 3600.0 * 2.0), (0.0, 60.0), vec![[1.0, 0.0, 0.0, 1.0]]);
-        let motor_temp_graph = LineGraph::new((400.0, 150.0),
-                                              (0.0, 4.0 * 3600.0 * 2.0),
-                                              (0.0, 100.0),
-                                              vec![[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]);
-
-        // Create the log files
-        let mut log_files = HashMap::new();
-        log_files.insert("imu".to_string(),
-                         BufWriter::new(File::create(format!("mission_data/{}/imu",
-                                                             mission_folder).as_str()).unwrap()));
-        log_files.insert("gps".to_string(),
-                         BufWriter::new(File::create(format!("mission_data/{}/gps",
-                                                             mission_folder).as_str()).unwrap()));
-        log_files.insert("volt".to_string(),
-                         BufWriter::new(File::create(format!("mission_data/{}/volt",
-                                                             mission_folder).as_str()).unwrap()));
-        log_files.insert("amp".to_string(),
-                         BufWriter::new(File::create(format!("mission_data/{}/amp",
-                                                             mission_folder).as_str()).unwrap()));
-        log_files.insert("temp".to_string(),
-                         BufWriter::new(File::create(format!("mission_data/{}/motor_temp",
-                                                             mission_folder).as_str()).unwrap()));
-        log_files.insert("weather".to_string(),
-                         BufWriter::new(File::create(format!("mission_data/{}/weather",
-                                                             mission_folder).as_str()).unwrap()));
-        // Write log headers
-        log_files.get_mut("imu").unwrap().write_all("#pitch\troll\theading\n".as_bytes()).unwrap();
-        log_files.get_mut("gps")
-                 .unwrap()
-                 .write_all("#latitude\tlongitude\tspeed\taltitude\tangle\n".as_bytes())
-                 .unwrap();
-        log_files.get_mut("volt")
-                 .unwrap()
-                 .write_all("#H-48v\tP-12v E\n".as_bytes())
-                 .unwrap();
-        log_files.get_mut("amp")
-                 .unwrap()
-                 .write_all("#P-12v E\ttL motor\tR motor\n".as_bytes())
-                 .unwrap();
-        log_files.get_mut("temp")
-                 .unwrap()
-                 .write_all("#L motor\tR motor\tAvionics\n".as_bytes())
-                 .unwrap();
-        log_files.get_mut("weather")
-                 .unwrap()
-                 .write_all("#wind speed\tpressure\taltitude\ttemp\n".as_bytes())
-                 .unwrap();
+    /// Packet errors are logged to the session folder. Without a session, e.g. when replaying a
+    /// capture, nothing is logged.
+    pub fn new(session: Option<&Session>, client: UdpSocket, rover_addr: (String, u16),
+               command_checksums: bool) -> TelemetryUi {
+        let mut v48_graph = LineGraph::new(DEFAULT_GRAPH_WINDOW_S, (0.0, 80.0), 10.0,
+                                           vec![("H-48 V", [1.0, 0.0, 0.0, 1.0])]);
+        v48_graph.limit = Some(limits::H48_V);
+        let mut v12_graph = LineGraph::new(DEFAULT_GRAPH_WINDOW_S, (8.0, 14.0), 1.0,
+                                           vec![("P-12 E V", [1.0, 0.0, 0.0, 1.0])]);
+        v12_graph.limit = Some(limits::P12E_V);
+        let mut avionics_temp_graph = LineGraph::new(DEFAULT_GRAPH_WINDOW_S, (0.0, 60.0), 10.0,
+                                                     vec![("Avionics", [1.0, 0.0, 0.0, 1.0])]);
+        avionics_temp_graph.limit = Some(limits::AVIONICS_TEMP);
+        let mut motor_temp_graph = LineGraph::new(DEFAULT_GRAPH_WINDOW_S,
+                                                  (0.0, 100.0),
+                                                  10.0,
+                                                  vec![("L Motor", [1.0, 0.0, 0.0, 1.0]), ("R Motor", [0.2, 0.6, 1.0, 1.0])]);
+        motor_temp_graph.limit = Some(limits::MOTOR_TEMP);
+
+        let mut link = LinkMonitor::new(rover_addr);
+        link.set_checksums(command_checksums);
+
+        let integrity_log =
+            session.map(|session| {
+                session.add_artifact("packet_errors.log", "packet_errors", "rover link");
+                session.path("packet_errors.log")
+            });
 
         TelemetryUi {
             bg_color: rgb(0.2, 0.35, 0.45),
 
-            mission_time: MissionTime::Paused(time::Duration::zero()),
+            mission_time: MissionTime::new(),
 
             v48_graph: v48_graph,
             h_48_v: AvgVal::new(60),
-            h_48_v_limits: RygLimit::LessThan(45.0, 48.0),
+            h_48_v_limits: limits::H48_V,
 
             v12_graph: v12_graph,
             p_12_e_v: AvgVal::new(60),
             p_12_e_a: AvgVal::new(30),
-            p_12_e_v_limits: RygLimit::LessThan(10.0, 12.0),
+            p_12_e_v_limits: limits::P12E_V,
 
             l_motor_amp: AvgVal::new(30),
             r_motor_amp: AvgVal::new(30),
@@ -194,11 +203,11 @@ impl TelemetryUi {
             motor_temp_graph: motor_temp_graph,
             l_motor_temp: AvgVal::new(40),
             r_motor_temp: AvgVal::new(40),
-            l_motor_temp_limits: RygLimit::GreaterThan(80.0, 60.0),
-            r_motor_temp_limits: RygLimit::GreaterThan(80.0, 60.0),
+            l_motor_temp_limits: limits::MOTOR_TEMP,
+            r_motor_temp_limits: limits::MOTOR_TEMP,
 
             avionics_temp: AvgVal::new(30),
-            avionics_temp_limits: RygLimit::GreaterThan(50.0, 40.0),
+            avionics_temp_limits: limits::AVIONICS_TEMP,
             avionics_temp_graph: avionics_temp_graph,
 
             wind_speed: AvgVal::new(20),
@@ -208,49 +217,90 @@ impl TelemetryUi {
 
             pitch_roll_heading: None,
 
-            log_files: log_files,
+            malformed_packets: 0,
+            integrity: IntegrityStats::new(integrity_log.as_ref().map(|path| path.as_str())),
+
+            logger: Logger::new(1000),
+
+            graph_time: GraphTime::Mission,
+            clock_s: 0.0,
+
+            layout: default_layout(),
+            layout_path: String::new(),
+            editing_layout: false,
+            selected_panel: 0,
+
+            link: link,
+            client: client,
+
             image_map: conrod::image::Map::new(),
         }
     }
 
-    pub fn log_data(&mut self) {
-        // imu
-        match self.pitch_roll_heading {
-            Some((pitch, roll, heading)) => {
-                write!(&mut self.log_files.get_mut("imu").unwrap(),
-                       "{}\t{}\t{}\n", pitch, roll, heading).unwrap();
-            },
-            None => { write!(&mut self.log_files.get_mut("imu").unwrap(), "none").unwrap(); },
+    pub fn update(&mut self) {
+        if let Err(e) = self.link.update(&self.client) {
+            println!("WARNING: Failed to send link heartbeat: {}", e);
+        }
+
+        // Keep the graphs moving when nothing arrives
+        let now = now_s();
+        self.clock_s = now;
+        for graph in self.graphs_mut().iter_mut() {
+            graph.scroll_to(now);
+        }
+    }
+
+    /// Label the graphs' time axis with `graph_time` and show the last `window_s` seconds, letting
+    /// the operator zoom out to twice that. `gridlines` turns the graphs' gridlines on or off.
+    pub fn set_graphs(&mut self, graph_time: GraphTime, window_s: f64, gridlines: bool) {
+        self.graph_time = graph_time;
+        for graph in self.graphs_mut().iter_mut() {
+            graph.window_s = window_s;
+            graph.max_window_s = 2.0 * window_s;
+            graph.gridlines = gridlines;
+        }
+    }
+
+    /// Write every point the graphs kept, at full resolution, to `graphs.csv` in the session folder.
+    pub fn export_graphs(&self, session: &Session) -> io::Result<()> {
+        let mut out = BufWriter::new(try!(File::create(session.path(GRAPHS_FILE))));
+        try!(writeln!(out, "line,utc,value"));
+        for graph in [&self.v48_graph, &self.v12_graph, &self.motor_temp_graph, &self.avionics_temp_graph].iter() {
+            for i in 0..graph.num_lines() {
+                for &(t, y) in graph.history(i) {
+                    try!(writeln!(out, "{},{:.3},{}", graph.name(i), t, y));
+                }
+            }
         }
-        // gps
-        write!(&mut self.log_files.get_mut("gps").unwrap(),
-               "{:?}\t{:?}\t{:?}\t{:?}\t{:?}\n", self.latitude, self.longitude,
-               self.speed, self.gps_altitude, self.angle).unwrap();
-        // volt
-        write!(&mut self.log_files.get_mut("volt").unwrap(),
-               "{:?}\t{:?}\n", self.h_48_v.get(), self.p_12_e_v.get()).unwrap();
-        // amp
-        write!(&mut self.log_files.get_mut("amp").unwrap(),
-               "{:?}\t{:?}\t{:?}\n", self.p_12_e_a.get(),
-               self.l_motor_amp.get(), self.r_motor_amp.get()).unwrap();
-        // temp
-        write!(&mut self.log_files.get_mut("temp").unwrap(),
-               "{:?}\t{:?}\t{:?}\n", self.l_motor_temp.get(), self.r_motor_temp.get(),
-               self.avionics_temp.get()).unwrap();
-        // weather
-        write!(&mut self.log_files.get_mut("weather").unwrap(),
-               "{:?}\t{:?}\t{:?}\t{:?}\n", self.wind_speed.get(), self.pressure,
-               self.altitude, self.temp).unwrap();
+        try!(out.flush());
+        session.add_artifact(GRAPHS_FILE, "graphs", "telemetry");
+        Ok(())
+    }
+
+    fn graphs_mut(&mut self) -> [&mut LineGraph; 4] {
+        [&mut self.v48_graph, &mut self.v12_graph, &mut self.motor_temp_graph, &mut self.avionics_temp_graph]
+    }
+
+    /// Log telemetry to `logger` from now on.
+    pub fn set_logger(&mut self, logger: Logger<BufWriter<File>>) {
+        self.logger = logger;
+    }
+
+    /// Write the logs that are due.
+    pub fn update_logs(&mut self) {
+        self.logger.update(self.mission_time.elapsed());
+    }
+
+    pub fn flush_logs(&mut self) -> io::Result<()> {
+        self.logger.flush()
     }
 
     pub fn draw_ui<'a>(&mut self, c: Context,
                           g: &mut gfx_graphics::GfxGraphics<'a, gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
                           glyph_cache: &mut conrod::backend::piston_window::GlyphCache,
-                          char_cache: &mut piston_window::Glyphs,
-                          ui: &mut conrod_config::Ui) {
-        use graphics::Transformed;
-
-        self.set_widgets(&mut ui.set_widgets());
+                          ui: &mut conrod_config::Ui,
+                          replay: Option<&mut Replay>) -> Option<ReplayUpdate> {
+        let replay_update = self.set_widgets(&mut ui.set_widgets(), replay);
 
         // Draw our UI!
         conrod::backend::piston_window::draw(c, g, ui.draw(),
@@ -258,106 +308,146 @@ impl TelemetryUi {
                                              &self.image_map,
                                              |img| img);
 
-        self.v48_graph.draw(c.trans(ui.win_w - 405.0, 5.0), g, char_cache);
-        self.avionics_temp_graph.draw(c.trans(ui.win_w - 405.0, 185.0), g, char_cache);
-        self.v12_graph.draw(c.trans(ui.win_w - 405.0, 365.0), g, char_cache);
-        self.motor_temp_graph.draw(c.trans(ui.win_w - 405.0, 545.0), g, char_cache);
+        replay_update
     }
 
-    pub fn set_widgets(&mut self, ui: &mut conrod_config::UiCell) {
-        let time_now = time::now();
-
+    /// Returns the packets to play when the replay controls were used.
+    pub fn set_widgets(&mut self, ui: &mut conrod_config::UiCell,
+                       replay: Option<&mut Replay>) -> Option<ReplayUpdate> {
         // Draw the background.
         widget::Canvas::new()
             .color(self.bg_color)
             .set(CANVAS, ui);
 
-        // Local time
-        Text::new(format!("{}", time_now.strftime("Local  %x  %X").unwrap()).as_str())
-            .x_y((-ui.win_w / 2.0) + 100.0, (ui.win_h / 2.0) - 10.0)
-            .font_size(16)
-            .color(self.bg_color.plain_contrast())
-            .set(LOCAL_TIME, ui);
-
-        // UTC time
-        Text::new(format!("{}", time_now.to_utc().strftime("%Z  %x  %X").unwrap()).as_str())
-            .x_y((-ui.win_w / 2.0) + 104.0, (ui.win_h / 2.0) - 30.0)
-            .font_size(16)
-            .color(self.bg_color.plain_contrast())
-            .set(UTC_TIME, ui);
-
-        // Mission time label
-        let mission_time =
-            match self.mission_time {
-                MissionTime::Paused(t) => t,
-                MissionTime::Running(start_time, extra_time) =>
-                    (time::now() - start_time) + extra_time
+        // The layout editor's toolbar and the replay controls get strips along the bottom, the
+        // panels the rest of the window
+        let mut area = Area::window(ui.win_w, ui.win_h);
+        let editor_area =
+            if self.editing_layout {
+                let (rest, strip) = area.cut_bottom(40.0);
+                area = rest;
+                Some(strip)
+            } else {
+                None
             };
-        let total_days = mission_time.num_days();
-        let total_hours = mission_time.num_hours();
-        let total_minutes = mission_time.num_minutes();
-        let total_seconds = mission_time.num_seconds();
-
-        let days = total_days;
-        let hours = total_hours - total_days*24;
-        let minutes = total_minutes - total_hours*60;
-        let seconds = total_seconds - total_minutes*60;
-        Text::new(format!("Mission Time: {}:{}:{}:{}", days, hours, minutes, seconds).as_str())
-            .x_y((-ui.win_w / 2.0) + 150.0, (ui.win_h / 2.0) - 70.0)
-            .font_size(20)
-            .color(self.bg_color.plain_contrast())
-            .set(MISSION_TIME_LABEL, ui);
-
-        // Mission start/pause button
-        let mission_start_text =
-            match self.mission_time {
-                MissionTime::Paused(_) => "Start",
-                MissionTime::Running(_, _) => "Pause",
+        let replay_area =
+            if replay.is_some() {
+                let (rest, strip) = area.cut_bottom(60.0);
+                area = rest;
+                Some(strip)
+            } else {
+                None
             };
-        if Button::new()
-            .w_h(100.0, 30.0)
-            .x_y((-ui.win_w / 2.0) + 55.0, (ui.win_h / 2.0) - 100.0)
-            .rgb(0.3, 0.8, 0.3)
-            .border(1.0)
-            .label(mission_start_text)
-            .set(MISSION_START_BUTTON, ui)
-            .was_clicked()
-        {
-            match self.mission_time {
-                MissionTime::Paused(current_time) => {
-                    self.mission_time = MissionTime::Running(time::now(), current_time);
-                },
-                MissionTime::Running(start_time, extra_time) => {
-                    self.mission_time = MissionTime::Paused((time::now() - start_time) + extra_time);
+
+        let panels = self.layout.arrange(area, panel_height);
+        for &(ref panel, ref area) in &panels {
+            match panel.as_str() {
+                "status" => self.set_status_panel(ui, area),
+                "power" => self.set_power_panel(ui, area),
+                "gps" => self.set_gps_panel(ui, area),
+                "temp" => self.set_temp_panel(ui, area),
+                "weather" => self.set_weather_panel(ui, area),
+                "imu" => self.set_imu_panel(ui, area),
+                "graphs" => self.set_graphs_panel(ui, area),
+                _ => { },
+            }
+        }
+
+        let replay_update =
+            match (replay, replay_area) {
+                (Some(replay), Some(replay_area)) => {
+                    let replay_control_ids = ReplayControlIds {
+                        play_button: REPLAY_PLAY_BUTTON,
+                        step_button: REPLAY_STEP_BUTTON,
+                        speed_button: REPLAY_SPEED_BUTTON,
+                        scrub_slider: REPLAY_SCRUB_SLIDER,
+                        label: REPLAY_LABEL,
+                    };
+                    widgets::replay_controls(ui, &replay_area, &replay_control_ids, replay,
+                                             self.bg_color.plain_contrast())
                 },
+                _ => None,
             };
-        }
 
-        // Mission reset button
-        if Button::new()
-            .w_h(100.0, 30.0)
-            .x_y((-ui.win_w / 2.0) + 160.0, (ui.win_h / 2.0) - 100.0)
-            .rgb(0.3, 0.8, 0.3)
-            .border(1.0)
-            .label("Reset")
-            .set(MISSION_RESET_BUTTON, ui)
-            .was_clicked()
-        {
-            self.mission_time = MissionTime::Paused(time::Duration::zero());
+        if let Some(editor_area) = editor_area {
+            let layout_editor_ids = LayoutEditorIds {
+                outline: LAYOUT_OUTLINE,
+                label: LAYOUT_PANEL_LABEL,
+                next_button: LAYOUT_NEXT_BUTTON,
+                left_button: LAYOUT_LEFT_BUTTON,
+                right_button: LAYOUT_RIGHT_BUTTON,
+                up_button: LAYOUT_UP_BUTTON,
+                down_button: LAYOUT_DOWN_BUTTON,
+                save_button: LAYOUT_SAVE_BUTTON,
+                done_button: LAYOUT_DONE_BUTTON,
+            };
+            match widgets::layout_editor(ui, &editor_area, &layout_editor_ids, &mut self.layout, &panels,
+                                         &mut self.selected_panel, self.bg_color.plain_contrast()) {
+                Some(LayoutEditorEvent::Save) => self.save_layout(),
+                Some(LayoutEditorEvent::Done) => self.editing_layout = false,
+                None => { },
+            }
         }
 
-        // Time delay
-        Text::new("Time Delay: 0s")
-            .x_y((-ui.win_w / 2.0) + 70.0, (ui.win_h / 2.0) - 150.0)
-            .font_size(18)
+        replay_update
+    }
+
+    fn set_status_panel(&mut self, ui: &mut conrod_config::UiCell, area: &Area) {
+        // Local and UTC time
+        widgets::clock(ui, area, LOCAL_TIME, UTC_TIME, self.bg_color.plain_contrast());
+
+        // Link status
+        let (r, g, b) = self.link.status_rgb();
+        Text::new(self.link.status_text().as_str())
+            .xy(area.at(150.0, 48.0))
+            .font_size(14)
+            .color(rgb(r, g, b))
+            .set(LINK_STATUS, ui);
+
+        let mission_timer_ids = MissionTimerIds {
+            label: MISSION_TIME_LABEL,
+            start_button: MISSION_START_BUTTON,
+            reset_button: MISSION_RESET_BUTTON,
+        };
+        widgets::mission_timer(ui, area, &mission_timer_ids, &mut self.mission_time, self.bg_color.plain_contrast());
+
+        // Measured communication delay
+        Text::new(self.link.delay_text().as_str())
+            .xy(area.at(150.0, 150.0))
+            .font_size(16)
             .color(self.bg_color.plain_contrast())
             .set(TIME_DELAY, ui);
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // Power section
+        // Malformed packets
+        let packet_errors_color =
+            if self.malformed_packets > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(format!("Bad Packets: {}", self.malformed_packets).as_str())
+            .xy(area.at(70.0, 170.0))
+            .font_size(14)
+            .color(packet_errors_color)
+            .set(PACKET_ERRORS, ui);
+
+        // Packets that failed their checksum in either direction
+        let corrupt_color =
+            if self.integrity.corrupted_in() > 0 || self.integrity.corrupted_out() > 0 {
+                rgb(1.0, 0.0, 0.0)
+            } else {
+                self.bg_color.plain_contrast()
+            };
+        Text::new(self.integrity.text().as_str())
+            .xy(area.at(230.0, 170.0))
+            .font_size(14)
+            .color(corrupt_color)
+            .set(CORRUPT_PACKETS, ui);
+    }
 
+    fn set_power_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("Power")
-            .x_y((-ui.win_w / 2.0) + 110.0, (ui.win_h / 2.0) - 190.0)
+            .xy(area.at(110.0, 10.0))
             .font_size(20)
             .color(self.bg_color.plain_contrast())
             .set(POWER_LABEL, ui);
@@ -365,7 +455,7 @@ impl TelemetryUi {
         // 48 bus
 
         Text::new(format!("48 Bus").as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 220.0)
+            .xy(area.at(60.0, 40.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(H_48_LABEL, ui);
@@ -373,20 +463,20 @@ impl TelemetryUi {
         let (h_48_v, h_48_v_color) =
             match self.h_48_v.get() {
                 Some(v) => {
-                    (format!("{0:.2}V", v), self.h_48_v_limits.get_color(v))
+                    (format!("{0:.2}V", v), level_color(self.h_48_v_limits.level(v)))
                 },
                 None => {
                     ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0))
                 },
             };
         Text::new(h_48_v.as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 240.0)
+            .xy(area.at(60.0, 60.0))
             .font_size(16)
             .color(h_48_v_color)
             .set(H_48_V_VALUE, ui);
 
         /*Text::new("NO DATA")
-            .x_y((-ui.win_w / 2.0) + 160.0, (ui.win_h / 2.0) - 240.0)
+            .xy(area.at(160.0, 60.0))
             .font_size(16)
             .color(rgb(0.0, 0.0, 0.0))
             .set(H_48_A_VALUE, ui);*/
@@ -394,7 +484,7 @@ impl TelemetryUi {
         // P-12 E bus
 
         Text::new(format!("P-12 E Bus").as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 340.0)
+            .xy(area.at(60.0, 160.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(P_12_E_LABEL, ui);
@@ -402,7 +492,7 @@ impl TelemetryUi {
         let (p_12_e_v, p_12_e_v_color) =
             match self.p_12_e_v.get() {
                 Some(v) => {
-                    (format!("{0:.2}V", v), self.p_12_e_v_limits.get_color(v))
+                    (format!("{0:.2}V", v), level_color(self.p_12_e_v_limits.level(v)))
                 },
                 None => {
                     ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0))
@@ -418,13 +508,13 @@ impl TelemetryUi {
                 },
             };
         Text::new(p_12_e_v.as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 360.0)
+            .xy(area.at(60.0, 180.0))
             .font_size(16)
             .color(p_12_e_v_color)
             .set(P_12_E_V_VALUE, ui);
 
         Text::new(p_12_e_a.as_str())
-            .x_y((-ui.win_w / 2.0) + 160.0, (ui.win_h / 2.0) - 360.0)
+            .xy(area.at(160.0, 180.0))
             .font_size(16)
             .color(p_12_e_a_color)
             .set(P_12_E_A_VALUE, ui);
@@ -432,7 +522,7 @@ impl TelemetryUi {
         // Left motor
 
         Text::new(format!("L Motor").as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 460.0)
+            .xy(area.at(60.0, 280.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(L_MOTOR_POWER_LABEL, ui);
@@ -445,7 +535,7 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(l_motor_amp.as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 480.0)
+            .xy(area.at(60.0, 300.0))
             .font_size(16)
             .color(l_motor_amp_color)
             .set(L_MOTOR_AMP_LABEL, ui);
@@ -453,7 +543,7 @@ impl TelemetryUi {
         // Right motor
 
         Text::new(format!("R Motor").as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 520.0)
+            .xy(area.at(60.0, 340.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(R_MOTOR_POWER_LABEL, ui);
@@ -466,16 +556,15 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(r_motor_amp.as_str())
-            .x_y((-ui.win_w / 2.0) + 60.0, (ui.win_h / 2.0) - 540.0)
+            .xy(area.at(60.0, 360.0))
             .font_size(16)
             .color(r_motor_amp_color)
             .set(R_MOTOR_AMP_LABEL, ui);
+    }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // GPS section
-
+    fn set_gps_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("GPS")
-            .x_y((-ui.win_w / 2.0) + 410.0, (ui.win_h / 2.0) - 50.0)
+            .xy(area.at(110.0, 15.0))
             .font_size(22)
             .color(self.bg_color.plain_contrast())
             .set(GPS_LABEL, ui);
@@ -483,14 +572,11 @@ impl TelemetryUi {
         // Latitude label
         let (latitude, latitude_color) =
             match self.latitude {
-                Some(lat) => {
-                    let (deg, min, sec) = gps_degrees_to_dms(lat);
-                    (format!("{}  {}' {:.*}\" N", deg, min, 2, sec), rgb(0.0, 1.0, 0.0))
-                },
+                Some(lat) => (format_latitude(lat), rgb(0.0, 1.0, 0.0)),
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(latitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 75.0)
+            .xy(area.at(200.0, 40.0))
             .font_size(16)
             .color(latitude_color)
             .set(LATITUDE_LABEL, ui);
@@ -498,14 +584,11 @@ impl TelemetryUi {
         // Longitude label
         let (longitude, longitude_color) =
             match self.longitude {
-                Some(lng) => {
-                    let (deg, min, sec) = gps_degrees_to_dms(lng);
-                    (format!("{}  {}' {:.*}\" N", deg, min, 2, sec), rgb(0.0, 1.0, 0.0))
-                },
+                Some(lng) => (format_longitude(lng), rgb(0.0, 1.0, 0.0)),
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(longitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 95.0)
+            .xy(area.at(200.0, 60.0))
             .font_size(16)
             .color(longitude_color)
             .set(LONGITUDE_LABEL, ui);
@@ -519,7 +602,7 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(speed.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 115.0)
+            .xy(area.at(200.0, 80.0))
             .font_size(16)
             .color(speed_color)
             .set(SPEED_LABEL, ui);
@@ -533,7 +616,7 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(gps_altitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 135.0)
+            .xy(area.at(200.0, 100.0))
             .font_size(16)
             .color(gps_altitude_color)
             .set(GPS_ALTITUDE_LABEL, ui);
@@ -547,16 +630,15 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(angle.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 155.0)
+            .xy(area.at(200.0, 120.0))
             .font_size(16)
             .color(angle_color)
             .set(ANGLE_LABEL, ui);
+    }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // Temp section
-
+    fn set_temp_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("Temp")
-            .x_y((-ui.win_w / 2.0) + 410.0, (ui.win_h / 2.0) - 190.0)
+            .xy(area.at(110.0, 15.0))
             .font_size(20)
             .color(self.bg_color.plain_contrast())
             .set(TEMP_LABEL, ui);
@@ -564,7 +646,7 @@ impl TelemetryUi {
         // Left motor temp
 
         Text::new(format!("L Motor").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 220.0)
+            .xy(area.at(60.0, 45.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(L_MOTOR_TEMP_LABEL, ui);
@@ -572,12 +654,12 @@ impl TelemetryUi {
         let (l_motor_temp, l_motor_temp_color) =
             match self.l_motor_temp.get() {
                 Some(temp) => {
-                    (format!("{0:.2} C", temp), self.l_motor_temp_limits.get_color(temp))
+                    (format!("{0:.2} C", temp), level_color(self.l_motor_temp_limits.level(temp)))
                 },
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(l_motor_temp.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 220.0)
+            .xy(area.at(200.0, 45.0))
             .font_size(16)
             .color(l_motor_temp_color)
             .set(L_MOTOR_C_LABEL, ui);
@@ -585,7 +667,7 @@ impl TelemetryUi {
         // Right motor temp
 
         Text::new(format!("R Motor").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 240.0)
+            .xy(area.at(60.0, 65.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(R_MOTOR_TEMP_LABEL, ui);
@@ -593,12 +675,12 @@ impl TelemetryUi {
         let (r_motor_temp, r_motor_temp_color) =
             match self.r_motor_temp.get() {
                 Some(temp) => {
-                    (format!("{0:.2} C", temp), self.r_motor_temp_limits.get_color(temp))
+                    (format!("{0:.2} C", temp), level_color(self.r_motor_temp_limits.level(temp)))
                 },
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(r_motor_temp.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 240.0)
+            .xy(area.at(200.0, 65.0))
             .font_size(16)
             .color(r_motor_temp_color)
             .set(R_MOTOR_C_LABEL, ui);
@@ -606,7 +688,7 @@ impl TelemetryUi {
         // avionics box temp
 
         Text::new(format!("Avionics").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 260.0)
+            .xy(area.at(60.0, 85.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(AVIONICS_TEMP_LABEL, ui);
@@ -614,22 +696,20 @@ impl TelemetryUi {
         let (avionics_temp, avionics_temp_color) =
             match self.avionics_temp.get() {
                 Some(temp) => {
-                    (format!("{0:.2} C", temp), self.avionics_temp_limits.get_color(temp))
+                    (format!("{0:.2} C", temp), level_color(self.avionics_temp_limits.level(temp)))
                 },
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(avionics_temp.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 260.0)
+            .xy(area.at(200.0, 85.0))
             .font_size(16)
             .color(avionics_temp_color)
             .set(AVIONICS_TEMP_VALUE, ui);
+    }
 
-
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // Weather section
-
+    fn set_weather_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("Weather")
-            .x_y((-ui.win_w / 2.0) + 410.0, (ui.win_h / 2.0) - 350.0)
+            .xy(area.at(110.0, 15.0))
             .font_size(20)
             .color(self.bg_color.plain_contrast())
             .set(WEATHER_LABEL, ui);
@@ -637,7 +717,7 @@ impl TelemetryUi {
         // Wind speed
 
         Text::new(format!("Wind Speed").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 380.0)
+            .xy(area.at(60.0, 45.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(WIND_LABEL, ui);
@@ -650,7 +730,7 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(wind_speed.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 380.0)
+            .xy(area.at(200.0, 45.0))
             .font_size(16)
             .color(wind_speed_color)
             .set(WIND_VALUE, ui);
@@ -658,7 +738,7 @@ impl TelemetryUi {
         // Altitude
 
         Text::new(format!("Altitude").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 400.0)
+            .xy(area.at(60.0, 65.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(ALTITUDE_LABEL, ui);
@@ -671,7 +751,7 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(altitude.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 400.0)
+            .xy(area.at(200.0, 65.0))
             .font_size(16)
             .color(altitude_color)
             .set(ALTITUDE_VALUE, ui);
@@ -679,7 +759,7 @@ impl TelemetryUi {
         // Pressure
 
         Text::new(format!("Pressure").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 420.0)
+            .xy(area.at(60.0, 85.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(PRESSURE_LABEL, ui);
@@ -692,7 +772,7 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(pressure.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 420.0)
+            .xy(area.at(200.0, 85.0))
             .font_size(16)
             .color(pressure_color)
             .set(PRESSURE_VALUE, ui);
@@ -700,7 +780,7 @@ impl TelemetryUi {
         // Temp
 
         Text::new(format!("Temp").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 440.0)
+            .xy(area.at(60.0, 105.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(WEATHER_TEMP_LABEL, ui);
@@ -713,16 +793,15 @@ impl TelemetryUi {
                 None => ("NO DATA".to_string(), rgb(0.0, 0.0, 0.0)),
             };
         Text::new(temp.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 440.0)
+            .xy(area.at(200.0, 105.0))
             .font_size(16)
             .color(temp_color)
             .set(WEATHER_TEMP_VALUE, ui);
+    }
 
-        ////////////////////////////////////////////////////////////////////////////////////////////
-        // IMU section
-
+    fn set_imu_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
         Text::new("IMU")
-            .x_y((-ui.win_w / 2.0) + 410.0, (ui.win_h / 2.0) - 500.0)
+            .xy(area.at(110.0, 15.0))
             .font_size(20)
             .color(self.bg_color.plain_contrast())
             .set(IMU_LABEL, ui);
@@ -740,13 +819,13 @@ impl TelemetryUi {
         // IMU pitch
 
         Text::new(format!("Pitch").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 530.0)
+            .xy(area.at(60.0, 45.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(IMU_PITCH_LABEL, ui);
 
         Text::new(pitch.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 530.0)
+            .xy(area.at(200.0, 45.0))
             .font_size(16)
             .color(imu_color)
             .set(IMU_PITCH_VALUE, ui);
@@ -754,13 +833,13 @@ impl TelemetryUi {
         // IMU roll
 
         Text::new(format!("Roll").as_str())
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 550.0)
+            .xy(area.at(60.0, 65.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(IMU_ROLL_LABEL, ui);
 
         Text::new(roll.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 550.0)
+            .xy(area.at(200.0, 65.0))
             .font_size(16)
             .color(imu_color)
             .set(IMU_ROLL_VALUE, ui);
@@ -768,148 +847,192 @@ impl TelemetryUi {
         // IMU heading
 
         Text::new("Heading")
-            .x_y((-ui.win_w / 2.0) + 360.0, (ui.win_h / 2.0) - 570.0)
+            .xy(area.at(60.0, 85.0))
             .font_size(18)
             .color(self.bg_color.plain_contrast())
             .set(IMU_HEADING_LABEL, ui);
 
         Text::new(heading.as_str())
-            .x_y((-ui.win_w / 2.0) + 500.0, (ui.win_h / 2.0) - 570.0)
+            .xy(area.at(200.0, 85.0))
             .font_size(16)
             .color(imu_color)
             .set(IMU_HEADING_VALUE, ui);
+    }
 
-        // Trend graph labels
-        Text::new("H-48 V")
-            .x_y((ui.win_w / 2.0) - 405.0 - 80.0, (ui.win_h / 2.0) - 90.0)
-            .font_size(18)
-            .color(self.bg_color.plain_contrast())
-            .set(TREND_H_48V_LABEL, ui);
-        Text::new("Avionics Temp")
-            .x_y((ui.win_w / 2.0) - 405.0 - 80.0, (ui.win_h / 2.0) - 270.0)
-            .font_size(18)
-            .color(self.bg_color.plain_contrast())
-            .set(TREND_AVIONICS_TEMP_LABEL, ui);
-        Text::new("P-12 E V")
-            .x_y((ui.win_w / 2.0) - 405.0 - 80.0, (ui.win_h / 2.0) - 450.0)
-            .font_size(18)
-            .color(self.bg_color.plain_contrast())
-            .set(TREND_P_12_E_V_LABEL, ui);
-        Text::new("LR Motor Temp")
-            .x_y((ui.win_w / 2.0) - 405.0 - 80.0, (ui.win_h / 2.0) - 630.0)
-            .font_size(18)
-            .color(self.bg_color.plain_contrast())
-            .set(TREND_LR_MOTOR_TEMP_LABEL, ui);
+    // The trend graphs stacked over the panel's height, right of their labels
+    fn set_graphs_panel(&self, ui: &mut conrod_config::UiCell, area: &Area) {
+        let axis =
+            match self.graph_time {
+                GraphTime::Utc => TimeAxis::Utc,
+                GraphTime::Mission => {
+                    let elapsed = self.mission_time.elapsed().num_milliseconds() as f64 / 1000.0;
+                    TimeAxis::MissionElapsed(self.clock_s - elapsed)
+                },
+            };
+
+        let graphs = [(&self.v48_graph, TREND_H_48V_GRAPH, "H-48 V", TREND_H_48V_LABEL),
+                      (&self.avionics_temp_graph, TREND_AVIONICS_TEMP_GRAPH, "Avionics Temp", TREND_AVIONICS_TEMP_LABEL),
+                      (&self.v12_graph, TREND_P_12_E_V_GRAPH, "P-12 E V", TREND_P_12_E_V_LABEL),
+                      (&self.motor_temp_graph, TREND_LR_MOTOR_TEMP_GRAPH, "LR Motor Temp", TREND_LR_MOTOR_TEMP_LABEL)];
+        let label_w = 160.0;
+        let gap = 30.0;
+        let graph_w = (area.w - label_w).max(0.0);
+        let graph_h = ((area.h - gap * (graphs.len() - 1) as f64) / graphs.len() as f64).max(0.0);
+        for (i, &(graph, id, label, label_id)) in graphs.iter().enumerate() {
+            let y = i as f64 * (graph_h + gap) + graph_h / 2.0;
+
+            TrendGraph::new(graph, axis)
+                .w_h(graph_w, graph_h)
+                .xy(area.at(label_w + graph_w / 2.0, y))
+                .rgb(0.15, 0.15, 0.2)
+                .label_color(color::WHITE)
+                .set(id, ui);
+
+            Text::new(label)
+                .xy(area.at(label_w / 2.0, y))
+                .font_size(18)
+                .color(self.bg_color.plain_contrast())
+                .set(label_id, ui);
+        }
     }
 
-    pub fn handle_packet(&mut self, packet: String) {
-        let packets = packet.split("|");
-        
-        for packet in packets {
-            let packet_parts: Vec<String> = packet.split(":").map(|s| s.to_string()).collect();
+    /// Arrange the panels by `layout`, or the default one if None. The layout editor (F2) saves to
+    /// `path`.
+    pub fn set_layout(&mut self, layout: Option<Layout>, path: String) {
+        let mut layout = layout.unwrap_or_else(default_layout);
+        let unknown: Vec<String> =
+            layout.panels().into_iter().filter(|panel| !PANELS.contains(panel)).map(|panel| panel.to_string()).collect();
+        for panel in unknown {
+            println!("WARNING: Unknown telemetry panel '{}', expected one of {}", panel, PANELS.join(", "));
+            layout.remove(&panel);
+        }
+        self.layout = layout;
+        self.layout_path = path;
+    }
 
-            //println!("{:?}", packet_parts);
+    fn save_layout(&self) {
+        match self.layout.save(&self.layout_path, "telemetry") {
+            Ok(()) => println!("Saved the layout to {}", self.layout_path),
+            Err(e) => println!("WARNING: Failed to save the layout to {}: {}", self.layout_path, e),
+        }
+    }
 
-            match packet_parts[0].as_str() {
-                "VOLT" => {
-                    /////////////////////
-                    self.h_48_v.add_value(packet_parts[1].parse().unwrap_or(0.0));
-                    let h_48_v = self.h_48_v.get().unwrap_or(0.0);
+    /// Record the pings we send to the rover.
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.link.set_capture(capture);
+    }
 
-                    let point_x = self.v48_graph.num_points(0) as f64;
-                    self.v48_graph.add_point(0, point_x, h_48_v);
+    pub fn handle_dropped(&mut self, error: FrameError) {
+        println!("WARNING: Dropped datagram: {}", error);
+        self.integrity.on_dropped(&error);
+    }
 
-                    /////////////////////
-                    self.p_12_e_v.add_value(packet_parts[3].parse().unwrap_or(0.0));
-                    let p_12_e_v = self.p_12_e_v.get().unwrap_or(0.0);
+    pub fn handle_net_error(&mut self, error: String) {
+        println!("WARNING: {}", error);
+        self.link.on_error(error);
+    }
 
-                    let point_x = self.v12_graph.num_points(0) as f64;
-                    self.v12_graph.add_point(0, point_x, p_12_e_v);
-                },
-                "AMP" => {
-                    self.l_motor_amp.add_value(packet_parts[1].parse().unwrap_or(0.0));
-                    self.r_motor_amp.add_value(packet_parts[2].parse().unwrap_or(0.0));
-                    self.p_12_e_a.add_value(packet_parts[3].parse().unwrap_or(0.0));
-                },
-                "GPS" => {
-                    if packet_parts.len() == 6 {
-                        self.latitude = packet_parts[1].parse().ok();
-                        self.longitude = packet_parts[2].parse().ok();
-                        self.speed = packet_parts[3].parse().ok();
-                        self.gps_altitude = packet_parts[4].parse().ok();
-                        self.angle = packet_parts[5].parse().ok();
-                    }
-                },
-                "L_MOTOR_TEMP" => {
-                    self.l_motor_temp.add_value(packet_parts[1].parse().unwrap());
-                    let l_motor_temp = self.l_motor_temp.get().unwrap();
+    /// Replace our socket after it has kept failing.
+    pub fn rebind(&mut self, receiver: &mut PacketReceiver) {
+        match receiver.rebind(&mut self.client) {
+            Ok(()) => {
+                println!("Rebound the rover socket");
+                self.link.clear_error();
+            },
+            Err(e) => self.handle_net_error(format!("Failed to rebind socket: {}", e)),
+        }
+    }
 
-                    let point_x = self.motor_temp_graph.num_points(0) as f64;
-                    self.motor_temp_graph.add_point(0, point_x, l_motor_temp);
-                },
-                "R_MOTOR_TEMP" => {
-                    self.r_motor_temp.add_value(packet_parts[1].parse().unwrap());
-                    let r_motor_temp = self.r_motor_temp.get().unwrap();
+    pub fn handle_packet(&mut self, packet: String) {
+        self.handle_packet_at(now_s(), packet);
+    }
 
-                    let point_x = self.motor_temp_graph.num_points(1) as f64;
-                    self.motor_temp_graph.add_point(1, point_x, r_motor_temp);
-                },
-                "AVIONICS_TEMP" => {
-                    let avionics_temp = packet_parts[1].parse().unwrap_or(0.0);
-                    self.avionics_temp.add_value(avionics_temp);
-                    
-                    let point_x = self.avionics_temp_graph.num_points(0) as f64;
-                    self.avionics_temp_graph.add_point(0, point_x, avionics_temp);
-                },
-                "W_TEMP" => {
-                    let temp = packet_parts[1].parse().unwrap();
-                    self.temp = Some(temp);
-                },
-                "W_PR_ALT" => {
-                    let pressure = packet_parts[1].parse().unwrap();
-                    let altitude= packet_parts[2].parse().unwrap();
-                    self.pressure = Some(pressure);
-                    self.altitude = Some(altitude);
-                },
-                "W_WND_SPD" => {
-                    self.wind_speed.add_value(packet_parts[1].parse().unwrap());
-                },
-                "IMU" => {
-                    let ax: f64 = packet_parts[1].parse().unwrap_or(0.0);
-                    let ay: f64 = packet_parts[2].parse().unwrap_or(0.0);
-                    let az: f64 = packet_parts[3].parse().unwrap_or(0.0);
-
-                    let mx: f64 = packet_parts[7].parse().unwrap_or(0.0);
-                    let my: f64 = packet_parts[8].parse().unwrap_or(0.0);
-                    let mz: f64 = packet_parts[9].parse().unwrap_or(0.0);
-
-                    let (ax, ay, az) = (ay, -az, ax);
-                    let (mx, my, mz) = (my, -mz, mx);
-
-                    let roll = f64::atan2(ay, az);
-                    let pitch = f64::atan2(-ax, ay*f64::sin(roll) + az*f64::cos(roll));
-                    let heading = f64::atan2(mz*f64::sin(roll) - my*f64::cos(roll),
-                                             mx*f64::cos(pitch) + my*f64::sin(pitch)*f64::sin(roll) + mz*f64::sin(pitch)*f64::cos(roll));
-
-                    let mut heading = heading.to_degrees();
-                    let mut roll = roll.to_degrees() + 180.0;
-                    let pitch = pitch.to_degrees();
-                    if heading < 0.0 {
-                        heading += 360.0;
-                    }
-                    if roll >= 180.0 {
-                        roll -= 360.0;
-                    }
-                    heading = 360.0 - heading;
-                    self.pitch_roll_heading = Some((pitch, roll, heading));
+    /// Handle a packet received at `utc_s`, e.g. from a replay.
+    pub fn handle_packet_at(&mut self, utc_s: f64, packet: String) {
+        self.link.on_packet();
+        if utc_s > self.clock_s {
+            self.clock_s = utc_s;
+        }
+
+        for msg in parse_telemetry(packet.as_str()) {
+            match msg {
+                Ok(msg) => self.handle_msg(utc_s, msg),
+                Err(e) => {
+                    println!("WARNING: Malformed packet: {}", e);
+                    self.malformed_packets += 1;
                 },
-                _ => { println!("WARNING: Unknown packet ID: {}", packet_parts[0]) },
             }
         }
     }
 
+    fn handle_msg(&mut self, utc_s: f64, msg: TelemetryMsg) {
+        self.logger.on_msg(&msg, self.mission_time.elapsed());
+
+        match msg {
+            TelemetryMsg::Volt { h_48_v, p_12_e_v } => {
+                /////////////////////
+                self.h_48_v.add_value(h_48_v);
+                let h_48_v = self.h_48_v.get().unwrap_or(0.0);
+
+                self.v48_graph.add_point(0, utc_s, h_48_v);
+
+                /////////////////////
+                self.p_12_e_v.add_value(p_12_e_v);
+                let p_12_e_v = self.p_12_e_v.get().unwrap_or(0.0);
+
+                self.v12_graph.add_point(0, utc_s, p_12_e_v);
+            },
+            TelemetryMsg::Amp { l_motor, r_motor, p_12_e } => {
+                self.l_motor_amp.add_value(l_motor);
+                self.r_motor_amp.add_value(r_motor);
+                self.p_12_e_a.add_value(p_12_e);
+            },
+            TelemetryMsg::Gps { latitude, longitude, speed, altitude, angle } => {
+                self.latitude = latitude;
+                self.longitude = longitude;
+                self.speed = speed;
+                self.gps_altitude = altitude;
+                self.angle = angle;
+            },
+            TelemetryMsg::LMotorTemp(l_motor_temp) => {
+                self.l_motor_temp.add_value(l_motor_temp);
+                let l_motor_temp = self.l_motor_temp.get().unwrap();
+
+                self.motor_temp_graph.add_point(0, utc_s, l_motor_temp);
+            },
+            TelemetryMsg::RMotorTemp(r_motor_temp) => {
+                self.r_motor_temp.add_value(r_motor_temp);
+                let r_motor_temp = self.r_motor_temp.get().unwrap();
+
+                self.motor_temp_graph.add_point(1, utc_s, r_motor_temp);
+            },
+            TelemetryMsg::AvionicsTemp(avionics_temp) => {
+                self.avionics_temp.add_value(avionics_temp);
+
+                self.avionics_temp_graph.add_point(0, utc_s, avionics_temp);
+            },
+            TelemetryMsg::WeatherTemp(temp) => {
+                self.temp = Some(temp);
+            },
+            TelemetryMsg::WeatherPressureAltitude { pressure, altitude } => {
+                self.pressure = Some(pressure);
+                self.altitude = Some(altitude);
+            },
+            TelemetryMsg::WindSpeed(wind_speed) => {
+                self.wind_speed.add_value(wind_speed);
+            },
+            TelemetryMsg::Imu { accel, mag, .. } => {
+                self.pitch_roll_heading = Some(pitch_roll_heading(accel, mag));
+            },
+            TelemetryMsg::Ack { .. } => { },
+            TelemetryMsg::Pong { seq, sent_ms, rover_ms } => self.link.on_pong(seq, sent_ms, rover_ms),
+            TelemetryMsg::BadCommands(count) => self.integrity.on_rover_report(count),
+        }
+    }
+
     pub fn on_key_pressed(&mut self, key: piston_window::Key) {
         match key {
+            piston_window::Key::F2 => self.editing_layout = !self.editing_layout,
             _ => { },
         }
     }
@@ -921,33 +1044,25 @@ impl TelemetryUi {
     }
 }
 
-fn gps_degrees_to_dms(degrees: f64) -> (i32, i32, f64) {
-    use std::f64;
-
-    let degrees = f64::abs(degrees);
-
-    let minutes = (degrees - f64::floor(degrees)) * 60.0; 
-    let seconds = (minutes - f64::floor(minutes)) * 60.0;
-    let degrees =
-        if degrees < 0.0 {
-            f64::ceil(degrees) as i32
-        } else {
-            f64::floor(degrees) as i32
-        };
-
-    (degrees, f64::floor(minutes) as i32, seconds)
-}
-
 widget_ids! {
     CANVAS,
 
     // Widget IDs
     LOCAL_TIME,
     UTC_TIME,
+    LINK_STATUS,
     MISSION_TIME_LABEL,
     MISSION_START_BUTTON,
     MISSION_RESET_BUTTON,
     TIME_DELAY,
+    PACKET_ERRORS,
+    CORRUPT_PACKETS,
+
+    REPLAY_PLAY_BUTTON,
+    REPLAY_STEP_BUTTON,
+    REPLAY_SPEED_BUTTON,
+    REPLAY_SCRUB_SLIDER,
+    REPLAY_LABEL,
 
     // Power section
     POWER_LABEL,
@@ -1015,9 +1130,32 @@ widget_ids! {
     IMU_HEADING_LABEL,
     IMU_HEADING_VALUE,
 
+    // Trend graphs
+    TREND_H_48V_GRAPH,
+    TREND_AVIONICS_TEMP_GRAPH,
+    TREND_P_12_E_V_GRAPH,
+    TREND_LR_MOTOR_TEMP_GRAPH,
+
     // Trend graph labels
     TREND_H_48V_LABEL,
     TREND_AVIONICS_TEMP_LABEL,
     TREND_P_12_E_V_LABEL,
     TREND_LR_MOTOR_TEMP_LABEL,
+
+    // Layout editor
+    LAYOUT_OUTLINE,
+    LAYOUT_PANEL_LABEL,
+    LAYOUT_NEXT_BUTTON,
+    LAYOUT_LEFT_BUTTON,
+    LAYOUT_RIGHT_BUTTON,
+    LAYOUT_UP_BUTTON,
+    LAYOUT_DOWN_BUTTON,
+    LAYOUT_SAVE_BUTTON,
+    LAYOUT_DONE_BUTTON,
+}
+
+// Seconds since the unix epoch
+fn now_s() -> f64 {
+    let now = time::get_time();
+    now.sec as f64 + now.nsec as f64 / 1e9
 }
@@ -1,8 +1,5 @@
-use std::fs;
 use std::net::UdpSocket;
 use std::path::Path;
-use std::sync::mpsc::channel;
-use std::thread;
 
 
 extern crate time;
@@ -11,20 +8,41 @@ extern crate graphics;
 extern crate gfx_graphics;
 extern crate gfx_device_gl;
 #[macro_use] extern crate conrod;
+extern crate toml;
+extern crate rover_common;
 
 use conrod::{
     Theme,
 };
-use piston_window::{EventLoop, Glyphs, PistonWindow, WindowSettings};
+use piston_window::{EventLoop, PistonWindow, WindowSettings};
 
+use rover_common::capture::{Capture, Direction};
+use rover_common::logger::Logger;
+use rover_common::protocol::HANDSHAKE;
+use rover_common::receiver::{datagram_event, NetEvent, PacketReceiver};
+use rover_common::replay::{Replay, ReplayUpdate};
+use rover_common::session::Session;
+
+use config::Config;
 use tele_ui::TelemetryUi;
 
 pub mod avg_val;
+pub mod config;
 pub mod conrod_config;
 pub mod line_graph;
 pub mod tele_ui;
+pub mod trend_graph;
 
 fn main() {
+    let config =
+        match Config::load("telemetry") {
+            Ok(config) => config,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            },
+        };
+
     let ref mut window: PistonWindow = WindowSettings::new("PISCES Telemetry".to_string(),
                                                            [1280, 700]).exit_on_esc(true)
                                                                        .build().unwrap();
@@ -37,36 +55,92 @@ fn main() {
     };
 
     ui.fonts.insert_from_file(font_path).unwrap();
-
-    let mut char_cache = Glyphs::new(&font_path, window.factory.clone()).unwrap();
-    
-    // Create a UDP socket to talk to the rover
-    let socket = UdpSocket::bind("0.0.0.0:30001").ok().expect("Failed to open UDP socket");
-    socket.send_to(b"connect me plz", ("10.10.153.8", 30001)).unwrap();
     
-    let in_socket = socket;
-    let (packet_t, packet_r) = channel();
-    
-    thread::Builder::new()
-        .name("packet_in".to_string())
-        .spawn(move || {
-            let mut buf = [0u8; 512];
-            loop {
-                let (bytes_read, _) = in_socket.recv_from(&mut buf).unwrap();
-                if let Ok(msg) = String::from_utf8(buf[0..bytes_read].iter().cloned().collect()) {
-                    packet_t.send(msg).unwrap();
+    // Replaying a capture shows what was received without logging anything or talking to the rover
+    let mut replay =
+        match config.replay {
+            Some(ref path) => {
+                match Replay::open(path) {
+                    Ok(replay) => Some(replay),
+                    Err(e) => {
+                        println!("Failed to open replay {}: {}", path, e);
+                        return;
+                    },
                 }
+            },
+            None => None,
+        };
+
+    // Everything written during a run goes in a session folder listed in its manifest
+    let session =
+        if replay.is_some() {
+            None
+        } else {
+            match Session::create(config.mission_data_dir.as_str(), "telemetry", env!("CARGO_PKG_VERSION"),
+                                  config.to_json()) {
+                Ok(session) => {
+                    println!("Session {}", session.id());
+                    Some(session)
+                },
+                Err(e) => {
+                    println!("Failed to create session folder in {}: {}", config.mission_data_dir, e);
+                    return;
+                },
             }
-        }).unwrap();
-    
-    let mission_folder = format!("{}", time::now().strftime("%Y%b%d_%H_%M").unwrap());
-    fs::create_dir_all(format!("mission_data/{}", mission_folder).as_str()).unwrap();
-    let mut tele_ui = TelemetryUi::new(mission_folder.as_str());
+        };
+
+    let mut capture = None;
+    let mut receiver = None;
+    let mut tele_ui =
+        match session {
+            None => replay_ui(&config),
+            Some(ref session) => {
+                // Record every packet to and from the rover
+                capture =
+                    match Capture::create(session.path("telemetry.cap")) {
+                        Ok(capture) => {
+                            session.add_artifact("telemetry.cap", "capture", "rover link");
+                            Some(capture)
+                        },
+                        Err(e) => {
+                            println!("WARNING: Failed to create packet capture: {}", e);
+                            None
+                        },
+                    };
+
+                // Create a UDP socket to talk to the rover
+                let socket =
+                    match PacketReceiver::bind(config.bind_port, capture.clone()) {
+                        Ok((socket, bound)) => {
+                            receiver = Some(bound);
+                            socket
+                        },
+                        Err(e) => {
+                            println!("Failed to open UDP socket on port {}: {}", config.bind_port, e);
+                            return;
+                        },
+                    };
+                // Not fatal if the network is down, the link monitor keeps retrying the handshake
+                if let Err(e) = socket.send_to(HANDSHAKE, (config.rover_addr.0.as_str(), config.rover_addr.1)) {
+                    println!("WARNING: Failed to send handshake: {}", e);
+                } else if let Some(ref capture) = capture {
+                    capture.record(Direction::Out, HANDSHAKE);
+                }
+
+                let mut tele_ui = TelemetryUi::new(Some(session), socket, config.rover_addr.clone(),
+                                                   config.command_checksums);
+                if let Some(ref capture) = capture {
+                    tele_ui.set_capture(capture.clone());
+                }
+                tele_ui.set_logger(Logger::create(session, &config.log_sinks(), config.log_flush_ms));
+                tele_ui.set_graphs(config.graph_time, config.graph_window_s as f64, config.graph_gridlines);
+                tele_ui.set_layout(config.layout.clone(), config.layout_file.clone());
+                tele_ui
+            },
+        };
     
     ///////////////////////////////////////////////////////////////////////////////////////
 
-    let mut last_update_time = time::now();
-
     window.set_ups(20);
     window.set_max_fps(60);
 
@@ -93,21 +167,81 @@ fn main() {
         });
         
         // Update
-        e.update(|_| {
-            while let Ok(packet) = packet_r.try_recv() {
-                tele_ui.handle_packet(packet);
+        e.update(|u_args| {
+            if let Some(ref mut replay) = replay {
+                let update = replay.update(u_args.dt);
+                play(&mut tele_ui, update, &config);
+                return;
             }
 
-            // Log some data
-            if (time::now()-last_update_time).num_seconds() >= 1 {
-                last_update_time = time::now();
-                tele_ui.log_data();
+            tele_ui.update();
+
+            if let Some(ref mut receiver) = receiver {
+                while let Some(event) = receiver.try_next() {
+                    match event {
+                        NetEvent::Packet(packet) => tele_ui.handle_packet(packet),
+                        NetEvent::Dropped(e) => tele_ui.handle_dropped(e),
+                        NetEvent::Error(e) => tele_ui.handle_net_error(e),
+                    }
+                }
+                if receiver.needs_rebind() {
+                    tele_ui.rebind(receiver);
+                }
             }
+
+            tele_ui.update_logs();
         });
         
         // Render GUI
+        let mut replay_update = None;
         window.draw_2d(&e, |c, g| {
-            tele_ui.draw_ui(c, g, &mut glyph_cache, &mut char_cache, &mut ui);
+            replay_update = tele_ui.draw_ui(c, g, &mut glyph_cache, &mut ui, replay.as_mut());
         });
+        if let Some(update) = replay_update {
+            play(&mut tele_ui, update, &config);
+        }
+    }
+
+    // The window has closed
+    if let Err(e) = tele_ui.flush_logs() {
+        println!("WARNING: Failed to write logs: {}", e);
+    }
+    if let Some(ref mut receiver) = receiver {
+        receiver.shutdown();
+    }
+    if let Some(ref capture) = capture {
+        if let Err(e) = capture.flush() {
+            println!("WARNING: Failed to write packet capture: {}", e);
+        }
+    }
+    if let Some(ref session) = session {
+        if let Err(e) = tele_ui.export_graphs(session) {
+            println!("WARNING: Failed to export the graphs: {}", e);
+        }
+        session.finish();
+    }
+}
+
+// A UI that shows a replay. It has nothing to log and never talks to the rover, but still needs a
+// socket of its own.
+fn replay_ui(config: &Config) -> TelemetryUi {
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let mut tele_ui = TelemetryUi::new(None, socket, config.rover_addr.clone(), config.command_checksums);
+    tele_ui.set_graphs(config.graph_time, config.graph_window_s as f64, config.graph_gridlines);
+    tele_ui.set_layout(config.layout.clone(), config.layout_file.clone());
+    tele_ui
+}
+
+// Hand replayed datagrams to the UI, starting over with a fresh one when the replay went back.
+fn play(tele_ui: &mut TelemetryUi, update: ReplayUpdate, config: &Config) {
+    if update.reset {
+        *tele_ui = replay_ui(config);
+    }
+    for (time_us, data) in update.packets {
+        match datagram_event(&data) {
+            NetEvent::Packet(packet) => tele_ui.handle_packet_at(time_us as f64 / 1e6, packet),
+            NetEvent::Dropped(e) => tele_ui.handle_dropped(e),
+            NetEvent::Error(e) => tele_ui.handle_net_error(e),
+        }
     }
 }
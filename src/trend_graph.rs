@@ -0,0 +1,447 @@
+use conrod::{self, Color, Colorable, Labelable, Positionable, Sizeable, Widget};
+use conrod::color;
+use conrod::widget::{self, Button, Line, PointPath, Rectangle, Text};
+
+use rover_common::limits::Level;
+
+use line_graph::{LineGraph, TimeAxis, MAX_TICKS};
+
+const ZOOM_STEP: f64 = 1.25;
+const MIN_WINDOW_S: f64 = 10.0;
+const MAX_Y_GRIDLINES: usize = 10;
+
+/// An interactive trend graph of a `LineGraph`, with a legend of its lines. The mouse wheel zooms the
+/// time axis around the mouse, dragging pans back through history and hovering shows each line's
+/// value and time under the mouse. The buttons in the corner toggle Y autoscale and following the
+/// newest values.
+pub struct TrendGraph<'a> {
+    common: widget::CommonBuilder,
+    graph: &'a LineGraph,
+    axis: TimeAxis,
+    style: Style,
+}
+
+widget_style! {
+    style Style {
+        /// Background of the plot area.
+        - color: Color { theme.shape_color }
+        /// Tick lines, tick labels and the scale.
+        - label_color: Color { theme.label_color }
+        - font_size: conrod::FontSize { 10 }
+    }
+}
+
+/// What the operator did to the graph, kept between frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    window_s: Option<f64>, // Zoomed window, None shows the graph's own
+    end: Option<f64>, // End of a panned window, None follows the newest values
+    autoscale: bool,
+    background_idx: widget::IndexSlot,
+    band_idxs: Vec<widget::IndexSlot>,
+    y_grid_idxs: Vec<widget::IndexSlot>,
+    line_idxs: Vec<widget::IndexSlot>,
+    legend_idxs: Vec<(widget::IndexSlot, widget::IndexSlot)>,
+    tick_idxs: Vec<(widget::IndexSlot, widget::IndexSlot)>,
+    upper_scale_idx: widget::IndexSlot,
+    lower_scale_idx: widget::IndexSlot,
+    cursor_idx: widget::IndexSlot,
+    readout_idxs: Vec<widget::IndexSlot>,
+    autoscale_button_idx: widget::IndexSlot,
+    follow_button_idx: widget::IndexSlot,
+}
+
+impl<'a> TrendGraph<'a> {
+    pub fn new(graph: &'a LineGraph, axis: TimeAxis) -> TrendGraph<'a> {
+        TrendGraph {
+            common: widget::CommonBuilder::new(),
+            graph: graph,
+            axis: axis,
+            style: Style::new(),
+        }
+    }
+
+    pub fn label_color(mut self, color: Color) -> Self {
+        self.style.label_color = Some(color);
+        self
+    }
+}
+
+impl<'a> Colorable for TrendGraph<'a> {
+    fn color(mut self, color: Color) -> Self {
+        self.style.color = Some(color);
+        self
+    }
+}
+
+impl<'a> Widget for TrendGraph<'a> {
+    type State = State;
+    type Style = Style;
+    type Event = ();
+
+    fn common(&self) -> &widget::CommonBuilder {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut widget::CommonBuilder {
+        &mut self.common
+    }
+
+    fn unique_kind(&self) -> &'static str {
+        "TrendGraph"
+    }
+
+    fn init_state(&self) -> State {
+        let num_lines = self.graph.num_lines();
+        State {
+            window_s: None,
+            end: None,
+            autoscale: false,
+            background_idx: widget::IndexSlot::new(),
+            band_idxs: (0..3).map(|_| widget::IndexSlot::new()).collect(),
+            y_grid_idxs: (0..MAX_Y_GRIDLINES).map(|_| widget::IndexSlot::new()).collect(),
+            line_idxs: (0..num_lines).map(|_| widget::IndexSlot::new()).collect(),
+            legend_idxs: (0..num_lines).map(|_| (widget::IndexSlot::new(), widget::IndexSlot::new())).collect(),
+            tick_idxs: (0..MAX_TICKS).map(|_| (widget::IndexSlot::new(), widget::IndexSlot::new())).collect(),
+            upper_scale_idx: widget::IndexSlot::new(),
+            lower_scale_idx: widget::IndexSlot::new(),
+            cursor_idx: widget::IndexSlot::new(),
+            readout_idxs: (0..num_lines).map(|_| widget::IndexSlot::new()).collect(),
+            autoscale_button_idx: widget::IndexSlot::new(),
+            follow_button_idx: widget::IndexSlot::new(),
+        }
+    }
+
+    fn style(&self) -> Style {
+        self.style.clone()
+    }
+
+    fn update(self, args: widget::UpdateArgs<Self>) -> () {
+        let widget::UpdateArgs { id, state, rect, style, mut ui, .. } = args;
+        let TrendGraph { graph, axis, .. } = self;
+
+        let (left, right, bottom, top) = rect.l_r_b_t();
+        let width = rect.w();
+
+        let (scroll_y, drag_dx, mouse_x) = {
+            let input = ui.widget_input(id);
+            (input.scrolls().map(|scroll| scroll.y).sum::<f64>(),
+             input.drags().left().map(|drag| drag.delta_xy[0]).sum::<f64>(),
+             input.mouse().map(|mouse| mouse.rel_xy()[0]))
+        };
+
+        // Zoom around the time under the mouse, or the newest values when following them
+        let window_s = state.window_s.unwrap_or(graph.window_s);
+        let end = state.end.unwrap_or(graph.end());
+        if scroll_y != 0.0 {
+            let zoom = if scroll_y > 0.0 { 1.0 / ZOOM_STEP } else { ZOOM_STEP };
+            let zoomed_s = (window_s * zoom).max(MIN_WINDOW_S).min(graph.max_window_s);
+            let zoomed_end =
+                match (state.end, mouse_x) {
+                    (Some(end), Some(mouse_x)) => {
+                        let mouse_t = end - window_s * (0.5 - mouse_x / width);
+                        Some(mouse_t + (end - mouse_t) * zoomed_s / window_s)
+                    },
+                    (end, _) => end,
+                };
+            state.update(|state| {
+                state.window_s = Some(zoomed_s);
+                state.end = zoomed_end;
+            });
+        }
+
+        // Dragging to the right goes back in time. Dragging up to the newest values follows them
+        // again.
+        if drag_dx != 0.0 {
+            let panned_end = end - drag_dx / width * window_s;
+            state.update(|state| state.end = if panned_end < graph.end() { Some(panned_end) } else { None });
+        }
+
+        let window_s = state.window_s.unwrap_or(graph.window_s);
+        let end = state.end.unwrap_or(graph.end());
+        let start = end - window_s;
+        let x_pos = |t: f64| left + (t - start) / window_s * width;
+
+        // At most two points per pixel column, whatever the window
+        let lines: Vec<Vec<(f64, f64)>> =
+            (0..graph.num_lines()).map(|i| clip(&graph.decimated(i, start, end, width as usize), start, end))
+                                  .collect();
+
+        let (y_min, y_max) =
+            if state.autoscale {
+                match y_range(&lines) {
+                    Some((min, max)) if max > min => (min - (max - min) * 0.05, max + (max - min) * 0.05),
+                    Some((min, _)) => (min - 1.0, min + 1.0),
+                    None => graph.y_interval,
+                }
+            } else {
+                graph.y_interval
+            };
+        let y_pos = |y: f64| bottom + (y.max(y_min).min(y_max) - y_min) / (y_max - y_min) * rect.h();
+
+        let label_color = style.label_color(&ui.theme);
+        let font_size = style.font_size(&ui.theme);
+
+        let background_idx = state.background_idx.get(&mut ui);
+        Rectangle::fill(rect.dim())
+            .xy(rect.xy())
+            .color(style.color(&ui.theme))
+            .graphics_for(id)
+            .parent(id)
+            .set(background_idx, &mut ui);
+
+        // Shade the red, yellow and green ranges of the limit
+        if let Some(limit) = graph.limit {
+            for (&(low, high, level), band_idx) in limit.bands().iter().zip(state.band_idxs.iter()) {
+                let (low, high) = (y_pos(low), y_pos(high));
+                if high - low < 1.0 {
+                    continue;
+                }
+                let band_idx = band_idx.get(&mut ui);
+                Rectangle::fill([width, high - low])
+                    .x_y(rect.x(), (low + high) / 2.0)
+                    .color(band_color(level))
+                    .graphics_for(id)
+                    .parent(id)
+                    .set(band_idx, &mut ui);
+            }
+        }
+
+        // Value gridlines at multiples of the step, fewer of them when zoomed out
+        if graph.gridlines && graph.y_step > 0.0 {
+            let mut step = graph.y_step;
+            while (y_max - y_min) / step > MAX_Y_GRIDLINES as f64 {
+                step *= 2.0;
+            }
+            let first = (y_min / step).floor() as i64 + 1;
+            for (i, grid_idx) in state.y_grid_idxs.iter().enumerate() {
+                let value = (first + i as i64) as f64 * step;
+                if value >= y_max {
+                    break;
+                }
+                let y = y_pos(value);
+                let grid_idx = grid_idx.get(&mut ui);
+                Line::abs([left, y], [right, y])
+                    .color(label_color.alpha(0.2))
+                    .thickness(0.5)
+                    .graphics_for(id)
+                    .parent(id)
+                    .set(grid_idx, &mut ui);
+            }
+        }
+
+        // Time ticks with their labels along the bottom
+        for (tick, &(ref line_idx, ref label_idx)) in axis.ticks(start, end).into_iter().zip(state.tick_idxs.iter()) {
+            let x = x_pos(tick);
+            if graph.gridlines {
+                let line_idx = line_idx.get(&mut ui);
+                Line::abs([x, bottom], [x, top])
+                    .color(label_color.alpha(0.3))
+                    .thickness(0.5)
+                    .graphics_for(id)
+                    .parent(id)
+                    .set(line_idx, &mut ui);
+            }
+
+            // Leave room for the scale in the corners
+            if x > left + 30.0 && x < right - 30.0 {
+                let label_idx = label_idx.get(&mut ui);
+                Text::new(axis.label(tick).as_str())
+                    .x_y(x, bottom + 8.0)
+                    .font_size(font_size)
+                    .color(label_color)
+                    .graphics_for(id)
+                    .parent(id)
+                    .set(label_idx, &mut ui);
+            }
+        }
+
+        let upper_scale_idx = state.upper_scale_idx.get(&mut ui);
+        Text::new(format!("{:.1}", y_max).as_str())
+            .top_left_with_margin_on(id, 2.0)
+            .font_size(font_size + 2)
+            .color(label_color)
+            .graphics_for(id)
+            .parent(id)
+            .set(upper_scale_idx, &mut ui);
+        let lower_scale_idx = state.lower_scale_idx.get(&mut ui);
+        Text::new(format!("{:.1}", y_min).as_str())
+            .bottom_left_with_margin_on(id, 2.0)
+            .font_size(font_size + 2)
+            .color(label_color)
+            .graphics_for(id)
+            .parent(id)
+            .set(lower_scale_idx, &mut ui);
+
+        for (i, (line, line_idx)) in lines.iter().zip(state.line_idxs.iter()).enumerate() {
+            let points: Vec<[f64; 2]> = line.iter().map(|&(t, y)| [x_pos(t), y_pos(y)]).collect();
+            if points.len() < 2 {
+                continue;
+            }
+            let line_color = graph.color(i);
+            let line_idx = line_idx.get(&mut ui);
+            PointPath::new(points)
+                .wh(rect.dim())
+                .xy(rect.xy())
+                .color(color::rgba(line_color[0], line_color[1], line_color[2], line_color[3]))
+                .thickness(1.0)
+                .graphics_for(id)
+                .parent(id)
+                .set(line_idx, &mut ui);
+        }
+
+        // Hover cursor with the value of each line under it, in the line's color
+        if let Some(mouse_x) = mouse_x {
+            let x = rect.x() + mouse_x;
+            let t = start + (x - left) / width * window_s;
+
+            let cursor_idx = state.cursor_idx.get(&mut ui);
+            Line::abs([x, bottom], [x, top])
+                .color(label_color)
+                .thickness(1.0)
+                .graphics_for(id)
+                .parent(id)
+                .set(cursor_idx, &mut ui);
+
+            let mut readout_y = top - 26.0;
+            for (i, readout_idx) in state.readout_idxs.iter().enumerate() {
+                if let Some((point_t, y)) = graph.nearest(i, t) {
+                    let line_color = graph.color(i);
+                    let readout_idx = readout_idx.get(&mut ui);
+                    Text::new(format!("{} {:.2}", axis.label(point_t), y).as_str())
+                        .x_y(if x < rect.x() { x + 55.0 } else { x - 55.0 }, readout_y)
+                        .font_size(font_size + 2)
+                        .color(color::rgb(line_color[0], line_color[1], line_color[2]).highlighted())
+                        .graphics_for(id)
+                        .parent(id)
+                        .set(readout_idx, &mut ui);
+                    readout_y -= 14.0;
+                }
+            }
+        }
+
+        // Legend along the top right, the last line rightmost
+        let mut legend_x = right - 4.0;
+        for (i, &(ref swatch_idx, ref name_idx)) in state.legend_idxs.iter().enumerate().rev() {
+            let name = graph.name(i);
+            let line_color = graph.color(i);
+            let line_color = color::rgba(line_color[0], line_color[1], line_color[2], line_color[3]);
+            let name_w = name.len() as f64 * font_size as f64 * 0.6;
+
+            let name_idx = name_idx.get(&mut ui);
+            Text::new(name)
+                .x_y(legend_x - name_w / 2.0, top - 9.0)
+                .font_size(font_size)
+                .color(label_color)
+                .graphics_for(id)
+                .parent(id)
+                .set(name_idx, &mut ui);
+
+            let swatch_idx = swatch_idx.get(&mut ui);
+            Rectangle::fill([10.0, 3.0])
+                .x_y(legend_x - name_w - 9.0, top - 9.0)
+                .color(line_color)
+                .graphics_for(id)
+                .parent(id)
+                .set(swatch_idx, &mut ui);
+
+            legend_x -= name_w + 22.0;
+        }
+
+        let follow_button_idx = state.follow_button_idx.get(&mut ui);
+        if Button::new()
+            .w_h(44.0, 16.0)
+            .bottom_right_with_margin_on(id, 2.0)
+            .color(if state.end.is_none() { color::LIGHT_GREEN } else { color::GREY })
+            .label("Follow")
+            .label_font_size(font_size)
+            .parent(id)
+            .set(follow_button_idx, &mut ui)
+            .was_clicked()
+        {
+            state.update(|state| state.end = if state.end.is_none() { Some(end) } else { None });
+        }
+
+        let autoscale_button_idx = state.autoscale_button_idx.get(&mut ui);
+        if Button::new()
+            .w_h(44.0, 16.0)
+            .left_from(follow_button_idx, 2.0)
+            .color(if state.autoscale { color::LIGHT_GREEN } else { color::GREY })
+            .label("Auto Y")
+            .label_font_size(font_size)
+            .parent(id)
+            .set(autoscale_button_idx, &mut ui)
+            .was_clicked()
+        {
+            state.update(|state| state.autoscale = !state.autoscale);
+        }
+    }
+}
+
+fn band_color(level: Level) -> Color {
+    match level {
+        Level::Red => color::rgba(1.0, 0.0, 0.0, 0.2),
+        Level::Yellow => color::rgba(1.0, 1.0, 0.0, 0.2),
+        Level::Green => color::rgba(0.0, 1.0, 0.0, 0.1),
+    }
+}
+
+// Smallest and largest value of all lines
+fn y_range(lines: &[Vec<(f64, f64)>]) -> Option<(f64, f64)> {
+    lines.iter().flat_map(|line| line.iter()).fold(None, |range, &(_, y)| {
+        match range {
+            Some((min, max)) => Some((y.min(min), y.max(max))),
+            None => Some((y, y)),
+        }
+    })
+}
+
+// Cut the segments running past `start` and `end` at the edges of the graph
+fn clip(points: &[(f64, f64)], start: f64, end: f64) -> Vec<(f64, f64)> {
+    let at = |a: (f64, f64), b: (f64, f64), t: f64| (t, a.1 + (b.1 - a.1) * (t - a.0) / (b.0 - a.0));
+
+    let mut clipped = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        if point.0 < start {
+            if let Some(&next) = points.get(i + 1) {
+                if next.0 > start {
+                    clipped.push(at(point, next, start));
+                }
+            }
+        } else if point.0 > end {
+            if i > 0 && points[i - 1].0 < end {
+                clipped.push(at(points[i - 1], point, end));
+            }
+        } else {
+            clipped.push(point);
+        }
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clip;
+
+    #[test]
+    fn keeps_points_inside() {
+        let points = [(5.0, 1.0), (10.0, 2.0), (15.0, 3.0)];
+        assert_eq!(clip(&points, 5.0, 15.0), points.to_vec());
+    }
+
+    #[test]
+    fn cuts_segments_at_both_edges() {
+        let points = [(0.0, 0.0), (10.0, 10.0), (20.0, 20.0)];
+        assert_eq!(clip(&points, 5.0, 15.0), vec![(5.0, 5.0), (10.0, 10.0), (15.0, 15.0)]);
+
+        // A single segment across the whole graph
+        assert_eq!(clip(&[(0.0, 0.0), (20.0, 40.0)], 5.0, 15.0), vec![(5.0, 10.0), (15.0, 30.0)]);
+    }
+
+    #[test]
+    fn drops_segments_outside() {
+        assert_eq!(clip(&[(0.0, 0.0), (1.0, 1.0)], 5.0, 15.0), vec![]);
+        assert_eq!(clip(&[(20.0, 0.0), (30.0, 1.0)], 5.0, 15.0), vec![]);
+        assert_eq!(clip(&[(0.0, 0.0), (5.0, 1.0)], 5.0, 15.0), vec![(5.0, 1.0)]);
+    }
+}
@@ -8,8 +8,10 @@ use std::path::Path;
 use std::ptr;
 use std::slice;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use std::sync::mpsc::{channel, Sender, Receiver};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use ffmpeg;
 use ffmpeg::codec;
@@ -22,6 +24,11 @@ use image::DynamicImage;
 
 use piston_window::{PistonWindow, G2dTexture, TextureSettings};
 
+use recorder::Recorder;
+
+// Recordings that haven't written their trailer yet
+static ACTIVE_RECORDINGS: AtomicUsize = ATOMIC_USIZE_INIT;
+
 pub enum VideoMsg {
     Start(String),
     Stop,
@@ -161,85 +168,60 @@ fn start_video_recording(decoder: &ffmpeg::codec::decoder::Video,
     let decoder_format = decoder.format();
 
     println!("time_base={}", decoder.time_base());
-    
+
+    ACTIVE_RECORDINGS.fetch_add(1, Ordering::SeqCst);
     thread::Builder::new()
         .name("video_packet_in".to_string())
         .spawn(move || {
-            let fps: i64 = 10;
-
-            /////////////////////////////////////////////////////
-            // Open recording stream
-
-            let mut rec_format = ffmpeg::format::output(&format!("{}", out_path)).unwrap();
-
-            let mut rec_video = {
-                    let mut stream = rec_format.add_stream(ffmpeg::codec::Id::MPEG4).unwrap();
-                    let mut codec  = stream.codec().encoder().video().unwrap();
-
-                    codec.set_width(decoder_width);
-                    codec.set_height(decoder_height);
-                    codec.set_format(ffmpeg::format::Pixel::YUV420P);
-                    //codec.set_time_base((1, fps as i32));
-                    codec.set_time_base((1, 1000));
-                    codec.set_flags(ffmpeg::codec::flag::GLOBAL_HEADER);
-
-                    stream.set_time_base((1, 1000));
-                    //stream.set_time_base((1, fps as i32));
-                    //stream.set_rate((fps as i32, 1));
-
-                    codec.open_as(ffmpeg::codec::Id::MPEG4).unwrap()
-            };
-
-            let mut rec_converter =
-                ffmpeg::software::converter((decoder_width, decoder_height),
-                                            decoder_format,
-                                            ffmpeg::format::Pixel::YUV420P).unwrap();
-
-            rec_format.write_header().unwrap();
-
-            let mut rec_packet = ffmpeg::Packet::empty();
-            let mut rec_frame  = ffmpeg::frame::Video::empty();
+            // No longer active however the thread ends, even if it panics
+            let _active = ActiveRecording;
+
+            let mut recorder =
+                match Recorder::create(out_path.as_str(), decoder_width, decoder_height, decoder_format) {
+                    Ok(recorder) => recorder,
+                    Err(e) => {
+                        println!("WARNING: Failed to start recording {}: {}", out_path, e);
+                        return;
+                    },
+                };
 
-            /////////////////////////////////////////////////////
-            // Process streams
-            
+            // Also finishes if the stream thread goes away without closing the recording
             while let Ok(msg) = msgs.recv() {
                 match msg {
-                    RecordPacket::Packet(pts, input_frame) => {
-                        // Now encode the recording packets
-                        if let Err(e) = rec_converter.run(&input_frame, &mut rec_frame) {
-                            println!("WARNING: video software converter error: {}", e);
-                        }
-                        rec_frame.set_pts(Some(pts));
-
-                        match rec_video.encode(&rec_frame, &mut rec_packet) {
-                            Ok(_) => {
-                                rec_packet.set_stream(0);
-                                rec_packet.rescale_ts((1, 10), (1, 17500));
-                                rec_packet.write_interleaved(&mut rec_format);
-                            },
-                            Err(e) => {
-                                println!("WARNING: Failed to write video frame: {}", e);
-                            },
-                        }
-                    },
-                    RecordPacket::Close => {
-                        break;
-                    },
+                    RecordPacket::Packet(pts, input_frame) => recorder.write(pts, &input_frame),
+                    RecordPacket::Close => break,
                 }
             }
 
-            while let Ok(true) = rec_video.flush(&mut rec_packet) {
-                rec_packet.set_stream(0);
-                rec_packet.rescale_ts((1, 10), (1, 17500));
-                rec_packet.write_interleaved(&mut rec_format);
+            match recorder.finish() {
+                Ok(()) => println!("Finished writing trailer"),
+                Err(e) => println!("WARNING: Failed to finish recording {}: {}", out_path, e),
             }
-
-            rec_format.write_trailer().unwrap();
-            println!("Finished writing trailer");
         }).unwrap();
 }
 
+// Counts a recording thread in ACTIVE_RECORDINGS until it's dropped
+struct ActiveRecording;
+
+impl Drop for ActiveRecording {
+    fn drop(&mut self) {
+        ACTIVE_RECORDINGS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Wait up to `timeout` for every recording to be finished after it was told to stop. Returns
+/// false if some are still being written.
+pub fn wait_for_recordings(timeout: Duration) -> bool {
+    let start = Instant::now();
+    while ACTIVE_RECORDINGS.load(Ordering::SeqCst) > 0 {
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    true
+}
+
 pub fn init_ffmpeg() {
     ffmpeg::init().unwrap();
     ffmpeg::format::network::init();